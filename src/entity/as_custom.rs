@@ -6,7 +6,7 @@ use async_trait::async_trait;
 use json::JsonValue;
 use tokio::sync::{mpsc};
 
-use crate::entity::{Entity, start_entity, EntityType};
+use crate::entity::{Entity, start_entity, EntityType, get_entity_config, get_window_size, get_token_buckets, TokenBucket};
 use crate::get_runtime;
 use crate::protocol::{SmsStatus};
 use crate::global::{CHANNEL_BUFF_NUM, TEMP_SAVE, get_sequence_id};
@@ -20,8 +20,10 @@ pub struct CustomEntity {
 	login_name: String,
 	password: String,
 	allowed_addr: String,
-	read_limit: u32,
 	write_limit: u32,
+	///所有连接共用的接收和发送令牌桶
+	read_bucket: Arc<TokenBucket>,
+	write_bucket: Arc<TokenBucket>,
 	max_channel_number: usize,
 	now_channel_number: Arc<AtomicU8>,
 	service_id: String,
@@ -47,6 +49,8 @@ impl CustomEntity {
 	           config: JsonValue,
 	           send_to_manager_tx: mpsc::Sender<JsonValue>
 	) -> Self {
+		let (read_bucket, write_bucket) = get_token_buckets(&config, read_limit, write_limit);
+
 		CustomEntity {
 			id,
 			name,
@@ -56,8 +60,9 @@ impl CustomEntity {
 			login_name,
 			password,
 			allowed_addr,
-			read_limit,
 			write_limit,
+			read_bucket,
+			write_bucket,
 			max_channel_number,
 			now_channel_number: Arc::new(AtomicU8::new(0)),
 			config,
@@ -84,9 +89,11 @@ impl CustomEntity {
 			self.sp_id.clone(),
 			0, 
 			self.now_channel_number.clone(), 
+			self.send_to_manager_tx.clone(),
 			EntityType::Custom,
 			0,
 			self.write_limit as usize,
+			get_entity_config(&self.config, self.write_bucket.clone()),
 		));
 
 		self.channel_to_entity_tx = Some(channel_to_entity_tx);
@@ -97,10 +104,10 @@ impl CustomEntity {
 
 #[async_trait]
 impl Entity for CustomEntity {
-	async fn login_attach(&self, can_write: bool) -> (usize, SmsStatus, u32, u32, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Sender<JsonValue>>) {
+	async fn login_attach(&self, can_write: bool) -> (usize, SmsStatus, Option<Arc<TokenBucket>>, Option<Arc<TokenBucket>>, usize, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Sender<JsonValue>>) {
 		if self.max_channel_number <= self.now_channel_number.load(Ordering::Relaxed) as usize {
 			log::warn!("当前已经满。不再继续增加。entity_id:{}", self.id);
-			return (0, SmsStatus::OtherError, 0, 0, None, None, None);
+			return (0, SmsStatus::OtherError, None, None, 0, None, None, None);
 		}
 
 		//通过后进行附加上去的动作。
@@ -127,7 +134,7 @@ impl Entity for CustomEntity {
 			log::error!("发送消息出现异常。e:{}", e);
 		}
 
-		(index as usize, SmsStatus::Success, self.read_limit, self.write_limit, Some(entity_to_channel_priority_rx), Some(entity_to_channel_common_rx), Some(channel_to_entity_tx))
+		(index as usize, SmsStatus::Success, Some(self.read_bucket.clone()), Some(self.write_bucket.clone()), get_window_size(&self.config), Some(entity_to_channel_priority_rx), Some(entity_to_channel_common_rx), Some(channel_to_entity_tx))
 	}
 
 	fn get_id(&self) -> u32 {
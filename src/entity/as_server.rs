@@ -6,7 +6,7 @@ use async_trait::async_trait;
 use json::JsonValue;
 use tokio::sync::mpsc::{self};
 
-use crate::entity::{Entity, start_entity, EntityType};
+use crate::entity::{Entity, start_entity, EntityType, get_entity_config, get_window_size, get_token_buckets, TokenBucket};
 use crate::entity::channel::Channel;
 use crate::get_runtime;
 use crate::protocol::{SmsStatus, Protocol};
@@ -24,6 +24,9 @@ pub struct ServerEntity {
 	version: u32,
 	read_limit: u32,
 	write_limit: u32,
+	///所有连接共用的接收和发送令牌桶
+	read_bucket: Arc<TokenBucket>,
+	write_bucket: Arc<TokenBucket>,
 	max_channel_number: usize,
 	now_channel_number: Arc<AtomicU8>,
 	config: JsonValue,
@@ -62,6 +65,7 @@ impl ServerEntity {
 	           send_to_manager_tx: mpsc::Sender<JsonValue>,
 	) -> Self {
 		let protocol = Protocol::get_protocol(protocol.as_str(), version);
+		let (read_bucket, write_bucket) = get_token_buckets(&config, read_limit, write_limit);
 		let server_connect_number = match protocol {
 			Protocol::SGIP(_) => 100,
 			_ => 0,
@@ -78,6 +82,8 @@ impl ServerEntity {
 			version,
 			read_limit,
 			write_limit,
+			read_bucket,
+			write_bucket,
 			max_channel_number,
 			now_channel_number: Arc::new(AtomicU8::new(0)),
 			config,
@@ -108,9 +114,11 @@ impl ServerEntity {
 			self.sp_id.clone(),
 			self.node_id, 
 			self.now_channel_number.clone(), 
+			self.entity_to_manager_tx.clone(),
 			EntityType::Server,
 			self.max_buff_cap,
-			self.write_limit as usize
+			self.write_limit as usize,
+			get_entity_config(&self.config, self.write_bucket.clone()),
 		));
 
 		self.channel_to_entity_tx = Some(channel_to_entity_tx);
@@ -178,10 +186,10 @@ impl ServerEntity {
 
 #[async_trait]
 impl Entity for ServerEntity {
-	async fn login_attach(&self, can_write: bool) -> (usize, SmsStatus, u32, u32, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Sender<JsonValue>>) {
+	async fn login_attach(&self, can_write: bool) -> (usize, SmsStatus, Option<Arc<TokenBucket>>, Option<Arc<TokenBucket>>, usize, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Sender<JsonValue>>) {
 		if (self.max_channel_number + self.server_connect_number) <= self.now_channel_number.load(Ordering::Relaxed) as usize {
 			log::warn!("当前已经满。不再继续增加。entity_id:{},最大可用:{},实际已经:{}", self.id, self.max_channel_number, self.now_channel_number.load(Ordering::Relaxed));
-			return (0, SmsStatus::OtherError, 0, 0, None, None, None);
+			return (0, SmsStatus::OtherError, None, None, 0, None, None, None);
 		}
 
 		//通过后进行附加上去的动作。
@@ -205,7 +213,7 @@ impl Entity for ServerEntity {
 			log::error!("发送消息出现异常。e:{}", e);
 		}
 
-		(index as usize, SmsStatus::Success, self.read_limit, self.write_limit, Some(entity_to_channel_priority_rx), Some(entity_to_channel_common_rx), Some(channel_to_entity_tx))
+		(index as usize, SmsStatus::Success, Some(self.read_bucket.clone()), Some(self.write_bucket.clone()), get_window_size(&self.config), Some(entity_to_channel_priority_rx), Some(entity_to_channel_common_rx), Some(channel_to_entity_tx))
 	}
 
 	fn get_id(&self) -> u32 {
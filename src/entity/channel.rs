@@ -7,13 +7,20 @@ use tokio::sync::mpsc;
 use tokio::time::{Duration, timeout};
 use tokio_util::codec::Framed;
 
-use crate::entity::EntityManager;
+use crate::entity::{EntityManager, TokenBucket};
 use crate::get_runtime;
 use crate::protocol::{MsgType, SmsStatus::{self, MessageError, Success}, Protocol};
-use crate::protocol::names::{ADDRESS, CAN_WRITE, ENTITY_ID, ID, LOGIN_NAME, MSG_IDS, MSG_TYPE_STR, SPEED_LIMIT, STATUS, VERSION, WAIT_RECEIPT};
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::protocol::names::{ADDRESS, CAN_WRITE, CHANNEL_ID, DECODE_ERROR, RESULT, ENTITY_ID, ID, LOGIN_NAME, MSG_IDS, MSG_TYPE_STR, SEQ_ID, SEQ_IDS, SPEED_LIMIT, STATUS, VERSION, WAIT_RECEIPT};
+use crate::protocol::implements::get_frame_error_stats;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use crate::global::{message_sender, TOPIC_TO_B_FAILURE};
+use crate::entity::schedule::{is_expired, send_expired};
+
+///发送窗口里的请求超过这个时间还未收到回复的.不再等待
+const WINDOW_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug)]
 pub struct Channel {
@@ -24,8 +31,11 @@ pub struct Channel {
 	entity_to_channel_priority_rx: Option<mpsc::Receiver<JsonValue>>,
 	entity_to_channel_common_rx: Option<mpsc::Receiver<JsonValue>>,
 	channel_to_entity_tx: Option<mpsc::Sender<JsonValue>>,
-	rx_limit: u32,
-	tx_limit: u32,
+	///实体所有连接共用的接收和发送令牌桶
+	rx_bucket: Option<Arc<TokenBucket>>,
+	tx_bucket: Option<Arc<TokenBucket>>,
+	///发出未收到回复的最大请求数.0为不限制
+	window_size: usize,
 }
 
 impl Channel {
@@ -38,8 +48,9 @@ impl Channel {
 			entity_to_channel_priority_rx: None,
 			entity_to_channel_common_rx: None,
 			channel_to_entity_tx: None,
-			rx_limit: 0,
-			tx_limit: 0,
+			rx_bucket: None,
+			tx_bucket: None,
+			window_size: 0,
 		}
 	}
 
@@ -98,7 +109,7 @@ impl Channel {
 
 				//判断返回类型和返回状态。
 				match (resp[MSG_TYPE_STR].as_str().unwrap_or("").into(),
-				       self.protocol.get_status_enum(resp[STATUS].as_u32().unwrap())) {
+				       self.protocol.get_status_enum(resp[STATUS].as_u32().unwrap_or(u32::MAX))) {
 					(MsgType::ConnectResp, SmsStatus::Success) => {
 						if let (Success, resp) = self.handle_login(resp, false, ip_addr).await {
 							if let Some(version) = resp[VERSION].as_u32() {
@@ -172,24 +183,35 @@ impl Channel {
 			return;
 		}
 
+		let (rx_bucket, tx_bucket) = match (self.rx_bucket.clone(), self.tx_bucket.clone()) {
+			(Some(rx_bucket), Some(tx_bucket)) => (rx_bucket, tx_bucket),
+			_ => {
+				log::error!("没有令牌桶。直接退出。{:?}", self);
+				return;
+			}
+		};
+
 		let entity_to_channel_priority_rx = self.entity_to_channel_priority_rx.as_mut().unwrap();
 		let entity_to_channel_common_rx = self.entity_to_channel_common_rx.as_mut().unwrap();
 
-		let mut curr_tx: u32 = 0;
-		let mut curr_rx: u32 = 0;
+		//下次从队列取消息前需要有的令牌数.长短信令牌不够时等够了再取
+		let mut tx_need: u32 = 1;
 		let mut idle_count: u16 = 0;
 		let mut wait_active_resp = false;
+		//已经发出还未收到回复的请求.seq_id,发出时间
+		let mut window: HashMap<u64, Instant> = HashMap::new();
 
 		let one_secs = Duration::from_millis(1000);
-		let mut timestamp = SystemTime::now().duration_since(UNIX_EPOCH).expect("system time before Unix epoch");
 		loop {
-			let new_time = SystemTime::now().duration_since(UNIX_EPOCH).expect("system time before Unix epoch");
-			if new_time.as_secs() - timestamp.as_secs() >= 1 {
-				// log::debug!("恢复时间窗口");
-				curr_tx = 0;
-				curr_rx = 0;
-				timestamp = new_time;
+			if self.window_size > 0 {
+				let before = window.len();
+				window.retain(|_, send_time| send_time.elapsed() < WINDOW_TIMEOUT);
+				if window.len() < before {
+					warn!("{}发送窗口里有{}个请求超时未收到回复.不再等待", self.id, before - window.len());
+				}
 			}
+			let window_free = self.window_size == 0 || window.len() < self.window_size;
+			let tx_ready = tx_bucket.has_tokens(tx_need);
 
 			//当空闲超过时间后发送心跳
 			if idle_count > 30 {
@@ -214,20 +236,25 @@ impl Channel {
 			//根据当前是否已经发满。发送当前是否可用数据。
 			tokio::select! {
 				biased;
-				msg = entity_to_channel_priority_rx.recv(), if curr_tx < self.tx_limit => {
+				msg = entity_to_channel_priority_rx.recv(), if tx_ready && window_free => {
 					idle_count = 0;
 					match msg {
 						Some(mut send) => {
 							log::debug!("priority收到entity发来的消息.msg:{}",send);
-							let msg_num = send[MSG_IDS].len() as u32;
+							//在通道队列里等待的时候超过了有效期
+							if is_expired(&send, chrono::Local::now().timestamp()) {
+								send_expired(send).await;
+								continue;
+							}
 
-							log::trace!("测试一下。msg_num:{},curr_tx:{},tx_limit:{}", msg_num, curr_tx, self.tx_limit);
+							let msg_num = send[MSG_IDS].len() as u32;
+							tx_need = 1;
 
-							if msg_num + curr_tx > self.tx_limit {
-								log::trace!("长短信长度大于可发送长度。返回满。msg_num:{},curr_tx:{},tx_limit:{}", msg_num, curr_tx, self.tx_limit);
+							if !tx_bucket.try_take(msg_num) {
+								log::trace!("长短信长度大于可用令牌。返回满。msg_num:{}", msg_num);
 								send[SPEED_LIMIT] = true.into();
-								//防止后面连续多条长短信
-								curr_tx = self.tx_limit;
+								//令牌够这条长短信以后再取下一条
+								tx_need = msg_num;
 
 								if let Err(e) = channel_to_entity_tx.send(send).await {
 									log::error!("向实体发送消息出现异常, e:{}", e);
@@ -244,15 +271,26 @@ impl Channel {
 									error!("发送消息出现错误, e:{}", e);
 									message_sender().send(TOPIC_TO_B_FAILURE, "3", send.to_string()).await;
 								} else {
-									// 计数加1
-									curr_tx = curr_tx + msg_num;
-									//把要等待回复的消息再发送回实体
+									if self.window_size > 0 {
+										let now = Instant::now();
+										window.extend(window_seq_ids(&send).into_iter().map(|seq_id| (seq_id, now)));
+									}
+									//把要等待回复的消息再发送回实体.带上通道id用来统计未回执数量
 									send[WAIT_RECEIPT] = true.into();
+									send[CHANNEL_ID] = self.id.into();
 									if let Err(e) = channel_to_entity_tx.send(send).await {
 										log::error!("向实体发送消息出现异常, e:{}", e);
 										return;
 									}
 								}
+							} else if let Some(resp) = unsupported_cancel_resp(&send) {
+								if let Err(e) = channel_to_entity_tx.send(resp).await {
+									log::error!("向实体发送消息出现异常, e:{}", e);
+									return;
+								}
+							} else {
+								//编码失败的消息不再跳过.返回失败
+								message_sender().send(TOPIC_TO_B_FAILURE, "4", send.to_string()).await;
 							}
 						}
 						None => {
@@ -262,20 +300,25 @@ impl Channel {
 						}
 					}
 				}
-				msg = entity_to_channel_common_rx.recv(),if curr_tx < self.tx_limit => {
+				msg = entity_to_channel_common_rx.recv(),if tx_ready && window_free => {
 					idle_count = 0;
 					match msg {
 						Some(mut send) => {
 							log::debug!("common收到entity发来的消息.msg:{}",send);
-							let msg_num = send[MSG_IDS].len() as u32;
+							//在通道队列里等待的时候超过了有效期
+							if is_expired(&send, chrono::Local::now().timestamp()) {
+								send_expired(send).await;
+								continue;
+							}
 
-							log::trace!("测试一下。msg_num:{},curr_tx:{},tx_limit:{}", msg_num, curr_tx, self.tx_limit);
+							let msg_num = send[MSG_IDS].len() as u32;
+							tx_need = 1;
 
-							if msg_num + curr_tx > self.tx_limit {
-								log::trace!("长短信长度大于可发送长度。返回满。msg_num:{},curr_tx:{},tx_limit:{}", msg_num, curr_tx, self.tx_limit);
+							if !tx_bucket.try_take(msg_num) {
+								log::trace!("长短信长度大于可用令牌。返回满。msg_num:{}", msg_num);
 								send[SPEED_LIMIT] = true.into();
-								//防止后面连续多条长短信。设置值以后保证不再获取。
-								curr_tx = self.tx_limit;
+								//令牌够这条长短信以后再取下一条
+								tx_need = msg_num;
 
 								if let Err(e) = channel_to_entity_tx.send(send).await {
 									log::error!("向实体发送消息出现异常, e:{}", e);
@@ -290,15 +333,26 @@ impl Channel {
 								if let Err(e) = framed.send(msg).await {
 									error!("发送回执出现错误, e:{}", e);
 								} else {
-									// 成功计数加1
-									curr_tx = curr_tx + msg_num;
-									//把要等待回复的消息再发送回实体
+									if self.window_size > 0 {
+										let now = Instant::now();
+										window.extend(window_seq_ids(&send).into_iter().map(|seq_id| (seq_id, now)));
+									}
+									//把要等待回复的消息再发送回实体.带上通道id用来统计未回执数量
 									send[WAIT_RECEIPT] = true.into();
+									send[CHANNEL_ID] = self.id.into();
 									if let Err(e) = channel_to_entity_tx.send(send).await {
 										log::error!("向实体发送消息出现异常, e:{}", e);
 										return;
 									}
 								}
+							} else if let Some(resp) = unsupported_cancel_resp(&send) {
+								if let Err(e) = channel_to_entity_tx.send(resp).await {
+									log::error!("向实体发送消息出现异常, e:{}", e);
+									return;
+								}
+							} else {
+								//编码失败的消息不再跳过.返回失败
+								message_sender().send(TOPIC_TO_B_FAILURE, "4", send.to_string()).await;
 							}
 						}
 						None => {
@@ -313,24 +367,41 @@ impl Channel {
 				    Some(Ok(mut json)) => {
 							log::info!("{}通道收到消息:{}",self.id,&json);
 							wait_active_resp = false;
+
+							//解码失败的消息.回复消息结构错误,不交给实体处理
+							if !json[DECODE_ERROR].is_null() {
+								warn!("{}收到结构错误的消息.json:{}", self.id, json);
+								if let Some(resp) = self.protocol.encode_receipt(MessageError, &mut json) {
+									if let Err(e) = framed.send(resp).await {
+										error!("发送回执出现错误, e:{}", e);
+									}
+								}
+								continue;
+							}
 							let ty = json[MSG_TYPE_STR].as_str().unwrap_or("").into();
 
 							match ty {
-								MsgType::Submit => {
-									//当消息需要需要返回的才记录接收数量
-									curr_rx = curr_rx + 1;
-								}
 								MsgType::Terminate => {
 									//收到终止消息。将ID带上
 									json[ID] = self.id.into();
 								}
+								MsgType::Query | MsgType::Cancel => {
+									//查询和删除需要由实体回复到当前通道
+									json[CHANNEL_ID] = self.id.into();
+								}
+								MsgType::SubmitResp | MsgType::DeliverResp | MsgType::ReportResp | MsgType::QueryResp | MsgType::CancelResp => {
+									//收到回复.从发送窗口里移除
+									if let Some(seq_id) = json[SEQ_ID].as_u64() {
+										window.remove(&seq_id);
+									}
+								}
 								_ => {
 									//这里目前不用做处理
 								}
 							}
 
 							//只有发送短信才进行判断
-              if ty != MsgType::Submit || curr_rx <= self.rx_limit {
+              if ty != MsgType::Submit || rx_bucket.try_take(1) {
 								//生成回执...当收到的是回执才回返回Some.
 								if let Some(resp) = self.protocol.encode_receipt(SmsStatus::Success, &mut json) {
 									if let Err(e) = framed.send(resp).await {
@@ -347,7 +418,7 @@ impl Channel {
 							} else {
 								// 超出,返回流量超出的处理
 								if let Some(resp) = self.protocol.encode_receipt(SmsStatus::TrafficRestrictions, &mut json) {
-									log::debug!("当前超出流量.json:{}.已发:{}.可发:{}", json, curr_rx, self.rx_limit);
+									log::debug!("当前超出流量.json:{}", json);
 
 									if let Err(e) = framed.send(resp).await{
 										error!("发送回执出现错误, e:{}",e);
@@ -361,7 +432,9 @@ impl Channel {
 							}
 						}
 						Some(Err(e)) => {
-							error!("解码出现错误,跳过当前消息。{}", e);
+							//分帧出错以后无法找到下一个消息的开头.只能断开连接
+							error!("{}分帧出现错误,断开连接。{}.累计:{}", self.id, e, get_frame_error_stats());
+							self.clear().await;
 							return;
 						}
 						None => {
@@ -373,8 +446,8 @@ impl Channel {
 						}
 				  }
 				}
-				//用来判断限制发送窗口期已过。。
-				_ = time::sleep(Duration::from_micros(1_000_000u64 - timestamp.subsec_micros() as u64)),if curr_tx >= self.tx_limit => {}
+				//等待令牌补充到可以继续发送
+				_ = time::sleep(tx_bucket.wait_time(tx_need)),if !tx_ready => {}
 				_ = time::sleep(one_secs) => {
 					//这里就是用来当全部都没有动作的时间打开再次进行循环.
 					//空闲记数
@@ -476,14 +549,15 @@ impl Channel {
 			}
 		}
 
-		let (id, status, rx_limit, tx_limit, entity_to_channel_priority_rx, entity_to_channel_common_rx, channel_to_entity_tx) 
+		let (id, status, rx_bucket, tx_bucket, window_size, entity_to_channel_priority_rx, entity_to_channel_common_rx, channel_to_entity_tx) 
 			= entity.login_attach(login_info[CAN_WRITE].as_bool().unwrap_or(true)).await;
 
 		if let Success = status {
 			// 设置相关的参数
 			self.id = id;
-			self.rx_limit = rx_limit;
-			self.tx_limit = tx_limit;
+			self.rx_bucket = rx_bucket;
+			self.tx_bucket = tx_bucket;
+			self.window_size = window_size;
 			self.entity_to_channel_priority_rx = entity_to_channel_priority_rx;
 			self.entity_to_channel_common_rx = entity_to_channel_common_rx;
 			self.channel_to_entity_tx = channel_to_entity_tx;
@@ -535,4 +609,32 @@ impl Drop for Channel {
 			});
 		}
 	}
-}
\ No newline at end of file
+}
+
+///协议不支持删除短信时.直接生成一个失败的删除回复
+fn unsupported_cancel_resp(send: &JsonValue) -> Option<JsonValue> {
+	if send[MSG_TYPE_STR].as_str() != Some("Cancel") {
+		return None;
+	}
+
+	log::warn!("当前协议不支持删除短信.返回失败.msg:{}", send);
+	let mut resp = send.clone();
+	resp[MSG_TYPE_STR] = "CancelResp".into();
+	resp[RESULT] = 1.into();
+
+	Some(resp)
+}
+
+///需要放入发送窗口等待回复的seq_id.只有下行短信、状态报告、查询和删除需要
+pub fn window_seq_ids(send: &JsonValue) -> Vec<u64> {
+	match send[MSG_TYPE_STR].as_str().unwrap_or("") {
+		"Submit" | "Deliver" | "Report" | "Query" | "Cancel" => {
+			if send[SEQ_IDS].is_array() {
+				send[SEQ_IDS].members().filter_map(|seq_id| seq_id.as_u64()).collect()
+			} else {
+				send[SEQ_ID].as_u64().into_iter().collect()
+			}
+		}
+		_ => Vec::new(),
+	}
+}
@@ -12,8 +12,8 @@ use tokio::sync::{mpsc, RwLock};
 use crate::entity::{CustomEntity, Entity};
 use crate::entity::as_server::ServerEntity;
 use crate::get_runtime;
-use crate::global::{load_config_file, TOPIC_FROM_B_SUBMIT, TOPIC_FROM_B_DELIVER, TOPIC_FROM_B_REPORT, TOPIC_PASSAGE_REQUEST_STATE};
-use crate::protocol::names::{ADDRESS, ALLOW_ADDRS, CROP_ID, GATEWAY_LOGIN_NAME, GATEWAY_PASSWORD, ID, LOGIN_NAME, MANAGER_TYPE, MAX_BUFF_CAP, MAX_CHANNEL_NUMBER, NAME, NODE_ID, OP_NAME, PASSWORD, PROTOCOL, READ_LIMIT, SERVICE_ID, SP_ID, VERSION, WRITE_LIMIT};
+use crate::global::{load_config_file, message_sender, SCHEDULED_MSG_INDEX, SCHEDULE_SMS_INDEX, TOPIC_FROM_B_SUBMIT, TOPIC_FROM_B_DELIVER, TOPIC_FROM_B_REPORT, TOPIC_FROM_B_QUERY, TOPIC_FROM_B_CANCEL, TOPIC_PASSAGE_REQUEST_STATE, TOPIC_TO_B_CANCEL_RESP};
+use crate::protocol::names::{ADDRESS, ALLOW_ADDRS, CROP_ID, GATEWAY_LOGIN_NAME, GATEWAY_PASSWORD, ID, LOGIN_NAME, MANAGER_TYPE, MAX_BUFF_CAP, MAX_CHANNEL_NUMBER, MSG_ID, MSG_TYPE_STR, NAME, NODE_ID, OP_NAME, PASSAGE_MSG_ID, PASSWORD, RESULT, PROTOCOL, READ_LIMIT, SERVICE_ID, SP_ID, VERSION, WRITE_LIMIT};
 
 ///实体的管理对象。
 /// 负责处理消息队列送过来的实体的开启、关闭等操作
@@ -67,6 +67,8 @@ impl EntityManager {
 			TOPIC_FROM_B_SUBMIT,
 			TOPIC_FROM_B_DELIVER,
 			TOPIC_FROM_B_REPORT,
+			TOPIC_FROM_B_QUERY,
+			TOPIC_FROM_B_CANCEL,
 		];
 
 		//定义来自于服务器的消息队列
@@ -194,6 +196,12 @@ async fn handle_from_entity_msg(manager_type: &str, msg: &JsonValue, context: &m
 			}
 			context.senders.remove(&id);
 		}
+		//需要转给其他实体处理的消息.如客户的删除请求和删除结果
+		"send" => {
+			if let Err(e) = entity_sender.send(msg.clone()).await {
+				log::error!("发送消息出现异常。对端可能已经关闭。e:{}", e);
+			}
+		}
 		//继续收到entity来的消息
 		_ => {
 			log::error!("收到一个未知的manager_type.不处理。跳过。msg:{}", msg);
@@ -212,7 +220,7 @@ async fn handle_queue_msg(topic: &str, mut json: JsonValue, context: &mut RunCon
 
 	let entity_manager = EntityManager::get_entity_manager();
 	match topic {
-		"send.submit" | "send.deliver" | "send.report" => {
+		"send.submit" | "send.deliver" | "send.report" | "send.query" => {
 			if let Some(sender) = context.senders.get(&id) {
 				json[MANAGER_TYPE] = "send".into();
 				if let Err(e) = sender.send(json).await {
@@ -222,6 +230,31 @@ async fn handle_queue_msg(topic: &str, mut json: JsonValue, context: &mut RunCon
 				log::error!("未找到指定id的实体发送者,跳过。msg:{}", json);
 			}
 		}
+		"send.cancel" => {
+			//没有指定通道的时候.根据定时短信的记录查找对应的通道
+			//只有msg_id的是删除网关保存的定时短信
+			let id = if id != 0 {
+				id
+			} else {
+				match (json[PASSAGE_MSG_ID].as_str(), json[MSG_ID].as_str()) {
+					(Some(passage_msg_id), _) => SCHEDULED_MSG_INDEX.read().await.get(passage_msg_id).map(|(id, _)| *id).unwrap_or(0),
+					(None, Some(msg_id)) => SCHEDULE_SMS_INDEX.read().await.get(msg_id).copied().unwrap_or(0),
+					(None, None) => 0
+				}
+			};
+
+			if let Some(sender) = context.senders.get(&id) {
+				json[MANAGER_TYPE] = "send".into();
+				if let Err(e) = sender.send(json).await {
+					log::error!("发送出现异常.e:{}", e);
+				}
+			} else {
+				log::error!("未找到删除短信对应的通道,返回失败。msg:{}", json);
+				json[MSG_TYPE_STR] = "CancelResp".into();
+				json[RESULT] = 1.into();
+				message_sender().send(TOPIC_TO_B_CANCEL_RESP, "", json.to_string()).await;
+			}
+		}
 		"passage.add" | "account.add" | "passage.modify" | "passage.init" | "account.init" | "account.modify" => {
 			let mut entitys = entity_manager.entitys.write().await;
 			if let Some(_) = entitys.get(&id) {
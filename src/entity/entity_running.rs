@@ -3,13 +3,16 @@ use json::JsonValue;
 use tokio::time;
 use std::sync::Arc;
 use std::time::Duration;
-use crate::entity::{ChannelStates, EntityType};
+use crate::entity::{AdaptiveRate, ChannelSelect, ChannelStates, EntityConfig, EntityType, LongSmsCache, RetryPolicy, SmsSchedule, TokenBucket};
+use crate::entity::schedule::{SCHEDULE_DIR, fill_expire_time, is_expired, send_expired};
+use crate::entity::statistics::Statistics;
 use std::collections::HashMap;
-use crate::protocol::names::{ACCOUNT_MSG_ID, SPEED_LIMIT, CAN_WRITE, DEST_ID, DEST_IDS, DURATION, ENTITY_ID, ID, IS_PRIORITY, LONG_SMS_NOW_NUMBER, LONG_SMS_TOTAL, MANAGER_TYPE, MSG_CONTENT, MSG_ID, MSG_IDS, MSG_TYPE_STR, MSG_TYPE_U32, NEED_RE_SEND, NODE_ID, PASSAGE_MSG_ID, RECEIVE_TIME, SEQ_ID, SEQ_IDS, SERVICE_ID, SP_ID, SRC_ID, STATE, WAIT_RECEIPT};
+use crate::protocol::names::{ACCOUNT_MSG_ID, SPEED_LIMIT, CAN_WRITE, DEST_IDS, DURATION, ENTITY_ID, ID, IS_PRIORITY, LONG_SMS_TOTAL, MANAGER_TYPE, MSG_CONTENT, MSG_ID, MSG_IDS, MSG_TYPE_STR, MSG_TYPE_U32, NEED_RE_SEND, NODE_ID, PASSAGE_MSG_ID, RECEIVE_TIME, SEQ_ID, SEQ_IDS, SERVICE_ID, SP_ID, STATE, WAIT_RECEIPT, CHANNEL_ID, RESULT, AT_TIME, QUERY_TIME, QUERY_TYPE, QUERY_CODE, MSG_FMT, RESULT_STATUS, RETRYABLE, EFFECTIVE_RATE, FIRST_SEND_TIME, REASON, RETRY_COUNT, REPLY_ENTITY_ID};
+use crate::protocol::implements::{is_binary_msg_fmt, select_msg_fmt};
+use crate::protocol::report_status::is_delivered;
 use crate::protocol::MsgType;
-use crate::global::{CHANNEL_BUFF_NUM, TEMP_SAVE, TOPIC_TO_B_ACCOUNT_STATE_CHANGE, TOPIC_TO_B_DELIVER, TOPIC_TO_B_DELIVER_RESP, TOPIC_TO_B_FAILURE, TOPIC_TO_B_PASSAGE_STATE_CHANGE, TOPIC_TO_B_REPORT, TOPIC_TO_B_REPORT_RESP, TOPIC_TO_B_SUBMIT, TOPIC_TO_B_SUBMIT_RESP, message_sender};
+use crate::global::{CHANNEL_BUFF_NUM, SCHEDULED_MSG_INDEX, SCHEDULE_SMS_INDEX, TEMP_SAVE, TOPIC_TO_B_CANCEL_RESP, TOPIC_TO_B_ACCOUNT_STATE_CHANGE, TOPIC_TO_B_DELIVER, TOPIC_TO_B_DELIVER_RESP, TOPIC_TO_B_FAILURE, TOPIC_TO_B_PASSAGE_STATE_CHANGE, TOPIC_TO_B_QUERY_RESP, TOPIC_TO_B_REPORT, TOPIC_TO_B_REPORT_RESP, TOPIC_TO_B_SUBMIT, TOPIC_TO_B_SUBMIT_RESP, message_sender};
 use crate::message_queue::KafkaMessageProducer;
-use std::ops::{Add};
 use std::sync::atomic::AtomicU8;
 use std::sync::atomic::Ordering::SeqCst;
 use std::fmt::{Display, Formatter, Error};
@@ -21,15 +24,16 @@ macro_rules! re_send {
 		// log::trace!("开始进行重发处理.{}",$target.entity_id);
 		let mut re_sends = Vec::with_capacity($target.wait_receipt_map.len());
 		let now = chrono::Local::now().timestamp();
+		let retry_policy = $target.retry_policy;
 
 		$target.wait_receipt_map.retain(|_, v| {
 			let receive_time = v[RECEIVE_TIME].as_i64().unwrap_or(0);
 			let duration = v[DURATION].as_i64().unwrap_or(30i64);
 
-			//如果超时并且需要重发.
+			//如果超时并且需要重发.等待时间加倍,不超过重发策略的上限
 			if (receive_time + duration) < now && v[NEED_RE_SEND].as_bool().unwrap_or(true) {
+				v[DURATION] = retry_policy.next_duration(duration).into();
 				re_sends.push(v.to_owned());
-				v[DURATION] = (duration + duration).into();
 
 				false
 			} else {
@@ -37,8 +41,14 @@ macro_rules! re_send {
 			}
 		});
 
-		while let Some(msg) = re_sends.pop() {
-			send_to_channels(msg.to_owned(), $target).await;
+		while let Some(mut msg) = re_sends.pop() {
+			release_in_flight(&mut $target.send_channels, &msg);
+			remove_long_sms_parts($target, &msg);
+
+			match retry_policy.add_retry(&mut msg, now) {
+				Some(reason) => send_retry_failure(msg, reason, $target).await,
+				None => send_to_channels(msg, $target).await,
+			}
 		}
   );
 }
@@ -79,13 +89,32 @@ struct EntityRunContext {
 	index: usize,
 	send_channels: Vec<ChannelStates>,
 	wait_receipt_map: HashMap<u64, JsonValue>,
-	long_sms_cache: HashMap<String, Vec<Option<JsonValue>>>,
+	long_sms_cache: LongSmsCache,
+	///网关保存的定时短信
+	schedule: SmsSchedule,
+	statistics: Statistics,
 	to_queue: Arc<KafkaMessageProducer>,
+	///发往管理器.用来把请求转给其他实体
+	to_manager: mpsc::Sender<JsonValue>,
 	now_conn_num: Arc<AtomicU8>,
 	state_change_json: JsonValue,
 	send_buff_cap: usize,
 	write_limit: usize,
 	is_buff_full: bool,
+	///允许使用的字符集.按优先顺序排列
+	msg_fmts: Vec<u8>,
+	///下行短信计费字段的默认值
+	fee_defaults: JsonValue,
+	///发送时选择连接的方式
+	channel_select: ChannelSelect,
+	///所有连接共用的发送令牌桶
+	write_bucket: Arc<TokenBucket>,
+	///按对端超速回复调整的发送速度
+	adaptive_rate: AdaptiveRate,
+	///未收到回执时的重发策略
+	retry_policy: RetryPolicy,
+	///at_time是否由网关保存.false时直接发往通道,由服务商定时,可以按passage_msg_id删除
+	gateway_schedule: bool,
 }
 
 impl Display for EntityRunContext {
@@ -102,10 +131,14 @@ pub async fn start_entity(
 	sp_id: String, 
 	node_id: u32, 
 	now_conn_num: Arc<AtomicU8>, 
+	to_manager: mpsc::Sender<JsonValue>,
 	entity_type: EntityType,
 	send_buff_cap: usize,
 	write_limit: usize,
+	config: EntityConfig,
 ) {
+	let EntityConfig { msg_fmts, long_sms_timeout, fee_defaults, channel_select, write_bucket, retry_policy, gateway_schedule } = config;
+
 	let send_buff_cap = if send_buff_cap == 0 {
 		CHANNEL_BUFF_NUM
 	} else {
@@ -126,92 +159,369 @@ pub async fn start_entity(
 		index: 0,
 		send_channels: Vec::new(),
 		wait_receipt_map: HashMap::new(),
-		long_sms_cache: HashMap::new(),
+		long_sms_cache: LongSmsCache::new(long_sms_timeout),
+		schedule: SmsSchedule::load(SCHEDULE_DIR, entity_id),
+		statistics: Statistics::new(),
 		to_queue: message_sender().clone(),
+		to_manager,
 		now_conn_num,
 		state_change_json: json::object! {
 			msg_type: msg_type,
 			id: entity_id,
-			state: DISCONNECT
+			state: DISCONNECT,
+			effective_rate: write_bucket.rate(),
 		},
 		send_buff_cap,
 		write_limit,
 		is_buff_full: false,
+		msg_fmts,
+		fee_defaults,
+		channel_select,
+		adaptive_rate: AdaptiveRate::new(write_bucket.rate()),
+		write_bucket,
+		retry_policy,
+		gateway_schedule,
 	};
 
 	log::info!("新开始一个entity.{}", context);
 
+	//恢复的定时短信记录下来.用来进行删除
+	if !context.schedule.is_empty() {
+		log::info!("恢复网关保存的定时短信.id:{}.数量:{}", context.entity_id, context.schedule.len());
+
+		let mut index = SCHEDULE_SMS_INDEX.write().await;
+		for msg_id in context.schedule.msg_ids() {
+			index.insert(msg_id, context.entity_id);
+		}
+	}
+
 	let mut clear_msg_timestamp = chrono::Local::now().timestamp();
 	let clear_msg_duration = 86400 * 4;
 
 	let mut clear_timestamp = chrono::Local::now().timestamp();
 	let clear_duration = 86400;
 
+	let mut long_sms_timestamp = chrono::Local::now().timestamp();
+	let long_sms_duration = 10;
+
 	let mut re_send_timestamp = chrono::Local::now().timestamp();
 	let re_send_duration = 10;
 
 	loop {
 		//一个时间窗口过去,清除发送数据
 		if (clear_msg_timestamp + clear_msg_duration) < chrono::Local::now().timestamp() {
-			clear_send_sms_cache(&mut context.wait_receipt_map, clear_msg_duration);
+			clear_send_sms_cache(&mut context, clear_msg_duration);
+			clear_scheduled_msg_index(context.entity_id, clear_msg_duration).await;
 			clear_msg_timestamp = chrono::Local::now().timestamp()
 		}
 
-		//一个时间窗口过去,清除长短信数据
+		//一个时间窗口过去,清除统计数据
 		if (clear_timestamp + clear_duration) < chrono::Local::now().timestamp() {
-			clear_long_sms_cache(&mut context.long_sms_cache, clear_duration);
+			context.statistics.clear(7);
 			clear_timestamp = chrono::Local::now().timestamp()
 		}
 
+		//一个时间窗口过去,把超时未收全的长短信按部分内容发出
+		if (long_sms_timestamp + long_sms_duration) < chrono::Local::now().timestamp() {
+			send_expired_long_sms(&mut context).await;
+			long_sms_timestamp = chrono::Local::now().timestamp()
+		}
+
 		//一个时间窗口过去,计算重发
 		if (re_send_timestamp + re_send_duration) < chrono::Local::now().timestamp() {
 			re_send!(&mut context);
 			re_send_timestamp = chrono::Local::now().timestamp()
 		}
 
+		//发送到时间的定时短信.有变化时写入文件
+		send_due_schedule(&mut context).await;
+		context.schedule.save().await;
+
+		//一段时间没有超速时逐渐恢复发送速度
+		if context.adaptive_rate.on_tick(chrono::Local::now().timestamp()) {
+			apply_effective_rate(&mut context).await;
+		}
+
+		//有定时短信时按下一条的时间醒来
+		let wait = context.schedule.next_wait(chrono::Local::now().timestamp()).unwrap_or(10).clamp(1, 10) as u64;
+
 		tokio::select! {
 			from_manager_msg = manager_to_entity_rx.recv() => {
 				if !handle_from_manager_rx(from_manager_msg,&mut context).await {
+					context.schedule.save().await;
 					return;
 				}
 			}
 			from_channel_msg = from_channel.recv() => {
 			if !handle_from_channel_rx(from_channel_msg,&mut context).await {
+					context.schedule.save().await;
 					return;
 				}
 			}
-			_ = tokio::time::sleep(tokio::time::Duration::from_secs(10)) => {
+			_ = tokio::time::sleep(tokio::time::Duration::from_secs(wait)) => {
 				//这里就是用来当全部都没有动作的时间打开再次进行循环.
 			}
 		}
 	}
 }
 
-fn clear_long_sms_cache(cache: &mut HashMap<String, Vec<Option<JsonValue>>>, duration: i64) {
-	let now = chrono::Local::now().timestamp();
+///重发次数用完或者超过重发期限.不再重发,发往sms.send.failure
+async fn send_retry_failure(mut msg: JsonValue, reason: &str, context: &mut EntityRunContext) {
+	log::warn!("消息不再重发.id:{}.reason:{}.msg:{}", context.entity_id, reason, msg);
+
+	msg[REASON] = reason.into();
+	send_to_queue!(&context.to_queue, TOPIC_TO_B_FAILURE, "6", msg);
+}
 
-	cache.retain(|_key, value| {
-		//查找是否存在超时的
-		value.iter().find(|item| {
-			if let Some(json) = item {
-				if json[RECEIVE_TIME].as_i64().unwrap_or(0) + duration < now {
-					return true;
+///把调整后的速度设置到令牌桶.并通过状态变化通知出去
+async fn apply_effective_rate(context: &mut EntityRunContext) {
+	let rate = context.adaptive_rate.rate();
+	log::info!("调整实际发送速度.id:{}.rate:{}", context.entity_id, rate);
+
+	context.write_bucket.set_rate(rate);
+	context.state_change_json[EFFECTIVE_RATE] = rate.into();
+	send_entity_state!(context);
+}
+
+///超时未收全的长短信.上行发往toB.deliver,下行发往toB.submit.带有l_s_partial标记
+async fn send_expired_long_sms(context: &mut EntityRunContext) {
+	for mut json in context.long_sms_cache.take_expired() {
+		match MsgType::from(json[MSG_TYPE_STR].as_str().unwrap_or("")) {
+			MsgType::Submit => {
+				send_to_queue!(&context.to_queue, TOPIC_TO_B_SUBMIT, "", json);
+			}
+			_ => {
+				send_to_queue!(&context.to_queue, TOPIC_TO_B_DELIVER, "", json);
+			}
+		}
+	}
+}
+
+///网关保存的定时短信到时间以后发往通道
+async fn send_due_schedule(context: &mut EntityRunContext) {
+	let due = context.schedule.take_due(chrono::Local::now().timestamp());
+	if due.is_empty() {
+		return;
+	}
+
+	{
+		let mut index = SCHEDULE_SMS_INDEX.write().await;
+		for msg in due.iter() {
+			for msg_id in msg[MSG_IDS].members() {
+				if let Some(msg_id) = msg_id.as_str() {
+					index.remove(msg_id);
 				}
 			}
+		}
+	}
 
-			return false;
-		}).is_none()
+	for msg in due {
+		log::debug!("定时短信到时间.开始发送.id:{}.msg:{}", context.entity_id, msg);
+		send_to_channels(msg, context).await;
+	}
+}
+
+///at_time为以后时间的下行短信由网关保存,到时间再发送.其他的直接发送.
+///通道配置gatewaySchedule为false时全部直接发送,at_time由服务商处理
+async fn schedule_or_send(msg: JsonValue, context: &mut EntityRunContext) {
+	if !context.gateway_schedule {
+		send_to_channels(msg, context).await;
+		return;
+	}
+
+	let msg_ids: Vec<String> = msg[MSG_IDS].members().filter_map(|id| id.as_str().map(|id| id.to_owned())).collect();
+
+	match context.schedule.add(msg, chrono::Local::now().timestamp()) {
+		Some(msg) => send_to_channels(msg, context).await,
+		None => {
+			log::debug!("收到定时短信.由网关保存.id:{}.msg_ids:{:?}", context.entity_id, msg_ids);
+
+			let mut index = SCHEDULE_SMS_INDEX.write().await;
+			for msg_id in msg_ids {
+				index.insert(msg_id, context.entity_id);
+			}
+		}
+	}
+}
+
+///删除网关保存的还未发送的定时短信.返回是否删除成功
+async fn remove_schedule(msg: &JsonValue, context: &mut EntityRunContext) -> bool {
+	let removed = match msg[MSG_ID].as_str() {
+		Some(msg_id) => context.schedule.remove(msg_id),
+		None => None,
+	};
+
+	match removed {
+		Some(removed) => {
+			let mut index = SCHEDULE_SMS_INDEX.write().await;
+			for msg_id in removed[MSG_IDS].members() {
+				if let Some(msg_id) = msg_id.as_str() {
+					index.remove(msg_id);
+				}
+			}
+
+			true
+		}
+		None => {
+			log::warn!("没有找到需要删除的定时短信.id:{}.msg:{}", context.entity_id, msg);
+			false
+		}
+	}
+}
+
+///删除网关保存的还未发送的定时短信.结果发往toB.cancel.response.
+///其他实体转过来的客户删除请求,结果经管理器发回那个实体
+async fn cancel_schedule(mut msg: JsonValue, context: &mut EntityRunContext) {
+	let result = if remove_schedule(&msg, context).await { 0 } else { 1 };
+
+	if let Some(reply_entity_id) = msg[REPLY_ENTITY_ID].as_u32() {
+		let resp = json::object! {
+			manager_type: "send",
+			id: reply_entity_id,
+			msg_type: "CancelResp",
+			channel_id: msg[CHANNEL_ID].clone(),
+			seq_id: msg[SEQ_ID].clone(),
+			result: result,
+		};
+
+		if let Err(e) = context.to_manager.send(resp).await {
+			log::error!("发送删除结果出现异常.e:{}", e);
+		}
+		return;
+	}
+
+	msg[RESULT] = result.into();
+	msg[MSG_TYPE_STR] = "CancelResp".into();
+	msg[ENTITY_ID] = context.entity_id.into();
+	msg[RECEIVE_TIME] = chrono::Local::now().timestamp().into();
+	send_to_queue!(&context.to_queue, TOPIC_TO_B_CANCEL_RESP, "", msg);
+}
+
+///对端(客户)的删除请求.定时短信保存在通道实体里面,按msg_id找到以后经管理器转过去删除.
+///找不到时直接回复失败
+async fn cancel_from_channel(mut msg: JsonValue, context: &mut EntityRunContext) {
+	let owner = match msg[MSG_ID].as_str() {
+		Some(msg_id) => SCHEDULE_SMS_INDEX.read().await.get(msg_id).copied(),
+		None => None,
+	};
+
+	let result = match owner {
+		Some(id) if id == context.entity_id => if remove_schedule(&msg, context).await { 0 } else { 1 },
+		Some(id) => {
+			msg[MANAGER_TYPE] = "send".into();
+			msg[ID] = id.into();
+			msg[REPLY_ENTITY_ID] = context.entity_id.into();
+			msg.remove(MSG_TYPE_U32);
+
+			match context.to_manager.send(msg.clone()).await {
+				Ok(_) => return,
+				Err(e) => {
+					log::error!("转发删除请求出现异常.e:{}", e);
+					1
+				}
+			}
+		}
+		None => {
+			log::warn!("没有找到需要删除的定时短信.id:{}.msg:{}", context.entity_id, msg);
+			1
+		}
+	};
+
+	reply_to_channel(json::object! {
+		msg_type: "CancelResp",
+		channel_id: msg[CHANNEL_ID].clone(),
+		seq_id: msg[SEQ_ID].clone(),
+		result: result,
+	}, context).await;
+}
+
+///回复对端的请求.发往收到请求的通道
+async fn reply_to_channel(resp: JsonValue, context: &mut EntityRunContext) {
+	let channel_id = resp[CHANNEL_ID].as_usize().unwrap_or(0);
+
+	match context.send_channels.iter().find(|item| item.id == channel_id) {
+		Some(channel) => {
+			if let Err(e) = channel.entity_to_channel_priority_tx.send(resp).await {
+				log::error!("发送回复出现异常.e:{}", e);
+			}
+		}
+		None => log::error!("未找到请求对应的通道.不回复.msg:{}", resp),
+	}
+}
+
+///清除当前实体超时的定时短信记录
+async fn clear_scheduled_msg_index(entity_id: u32, duration: i64) {
+	let now = chrono::Local::now().timestamp();
+
+	SCHEDULED_MSG_INDEX.write().await.retain(|_key, (id, time)| {
+		*id != entity_id || *time + duration > now
 	});
 }
 
-fn clear_send_sms_cache(cache: &mut HashMap<u64, JsonValue>, duration: i64) {
+fn clear_send_sms_cache(context: &mut EntityRunContext, duration: i64) {
 	let now = chrono::Local::now().timestamp();
+	let send_channels = &mut context.send_channels;
+
+	context.wait_receipt_map.retain(|_key, value| {
+		let keep = value[RECEIVE_TIME].as_i64().unwrap_or(now) + duration > now;
+		if !keep {
+			release_in_flight(send_channels, value);
+		}
 
-	cache.retain(|_key, value| {
-		value[RECEIVE_TIME].as_i64().unwrap_or(now) + duration > now
+		keep
 	});
 }
 
+///回执等待里面移除的下行短信.对应连接的未回执数量减一
+fn release_in_flight(send_channels: &mut [ChannelStates], msg: &JsonValue) {
+	if msg[MSG_TYPE_STR] != "Submit" {
+		return;
+	}
+
+	let channel_id = msg[CHANNEL_ID].as_usize();
+	if let Some(channel) = send_channels.iter_mut().find(|item| Some(item.id) == channel_id) {
+		channel.in_flight = channel.in_flight.saturating_sub(1);
+	}
+}
+
+///长短信的每一条都放在等待回执里面,内容是整条短信.
+///整条重发或者不再发送时把其他条也移除,避免其他条的回执再次引起重发
+fn remove_long_sms_parts(context: &mut EntityRunContext, source: &JsonValue) {
+	if source[SEQ_IDS].len() <= 1 {
+		return;
+	}
+
+	for seq_id in source[SEQ_IDS].members().filter_map(|seq_id| seq_id.as_u64()) {
+		if let Some(part) = context.wait_receipt_map.remove(&seq_id) {
+			release_in_flight(&mut context.send_channels, &part);
+		}
+	}
+}
+
+///按实体的选择方式取得发送使用的连接下标.没有可写的连接时返回None.
+///最少的有多个时从轮询的位置开始取第一个,保证平均使用
+fn select_channel(context: &mut EntityRunContext) -> Option<usize> {
+	let size = context.send_channels.len();
+	if size == 0 {
+		return None;
+	}
+
+	if context.index == usize::MAX {
+		context.index = 1;
+	}
+	context.index += 1;
+
+	let start = context.index;
+	let channels = &context.send_channels;
+	let mut candidates = (0..size).map(|i| (start + i) % size).filter(|i| channels[*i].can_write);
+
+	match context.channel_select {
+		ChannelSelect::RoundRobin => candidates.next(),
+		ChannelSelect::LeastQueued => candidates.min_by_key(|i| channels[*i].queued()),
+		ChannelSelect::LeastInFlight => candidates.min_by_key(|i| (channels[*i].in_flight, channels[*i].queued())),
+	}
+}
+
 ///entity处理来自于通道端的消息
 async fn handle_from_channel_rx(msg: Option<JsonValue>, context: &mut EntityRunContext) -> bool {
 	match msg {
@@ -230,10 +540,30 @@ async fn handle_from_channel_rx(msg: Option<JsonValue>, context: &mut EntityRunC
 						(MsgType::SubmitResp, _) => {
 							if let Some(mut source) = context.wait_receipt_map.remove(&get_key(&msg)) {
 								log::trace!("收到submit回执..移除缓存:{}", source);
+								release_in_flight(&mut context.send_channels, &source);
 
-								//收到的消息是已超速，压回去，等待后续发送。
-								if msg[SPEED_LIMIT].as_bool().unwrap_or(false) {
-									send_to_channels(source, context).await;
+								//收到的消息是已超速或者对照表里可以重发的错误，压回去，等待后续发送。
+								if msg[SPEED_LIMIT].as_bool().unwrap_or(false) || msg[RETRYABLE].as_bool().unwrap_or(false) {
+									//对端超速时降低发送速度.避免按原来的速度反复重发
+									if msg[SPEED_LIMIT].as_bool().unwrap_or(false) && context.adaptive_rate.on_speed_limit(chrono::Local::now().timestamp()) {
+										apply_effective_rate(context).await;
+									}
+
+									log::debug!("回执为可重发的错误.重新发送.result:{}.status:{}", msg[RESULT], msg[RESULT_STATUS]);
+									//长短信整条只重发一次.其他条的等待回执不再处理
+									remove_long_sms_parts(context, &source);
+
+									//超速只是等待后续发送.其他可重发的错误计入重发次数
+									let reason = if msg[SPEED_LIMIT].as_bool().unwrap_or(false) {
+										None
+									} else {
+										context.retry_policy.add_retry(&mut source, chrono::Local::now().timestamp())
+									};
+
+									match reason {
+										Some(reason) => send_retry_failure(source, reason, context).await,
+										None => send_to_channels(source, context).await,
+									}
 								} else {
 									//当缓冲区已满的时候进行判断，已到达可接收的时候发送消息
 									if context.is_buff_full {
@@ -250,6 +580,13 @@ async fn handle_from_channel_rx(msg: Option<JsonValue>, context: &mut EntityRunC
 
 									msg[ACCOUNT_MSG_ID] = source.remove(ACCOUNT_MSG_ID);
 									msg[PASSAGE_MSG_ID] = msg.remove(MSG_ID);
+
+									//定时短信记录下来.用来进行删除
+									if !source[AT_TIME].as_str().unwrap_or("").is_empty() {
+										if let Some(passage_msg_id) = msg[PASSAGE_MSG_ID].as_str() {
+											SCHEDULED_MSG_INDEX.write().await.insert(passage_msg_id.to_owned(), (context.entity_id, chrono::Local::now().timestamp()));
+										}
+									}
 									
 									send_to_queue!(&context.to_queue, TOPIC_TO_B_SUBMIT_RESP, "", msg);
 								}
@@ -258,14 +595,51 @@ async fn handle_from_channel_rx(msg: Option<JsonValue>, context: &mut EntityRunC
 							}
 						}
 						//发送需要等待回执
+						//删除请求等待回执
+						(MsgType::Cancel, Some(true)) => {
+							msg[NEED_RE_SEND] = false.into();
+							context.wait_receipt_map.insert(get_key(&msg), msg);
+						}
+						//对端(客户)的删除请求
+						(MsgType::Cancel, None) => cancel_from_channel(msg, context).await,
+						(MsgType::CancelResp, None) => {
+							if let Some(mut source) = msg[SEQ_ID].as_u64().and_then(|seq_id| context.wait_receipt_map.remove(&seq_id)) {
+								msg[PASSAGE_MSG_ID] = source.remove(PASSAGE_MSG_ID);
+								msg[ACCOUNT_MSG_ID] = source.remove(ACCOUNT_MSG_ID);
+							}
+
+							if msg[RESULT].as_u32().unwrap_or(1) == 0 {
+								if let Some(passage_msg_id) = msg[PASSAGE_MSG_ID].as_str() {
+									SCHEDULED_MSG_INDEX.write().await.remove(passage_msg_id);
+								}
+							}
+
+							send_to_queue!(&context.to_queue, TOPIC_TO_B_CANCEL_RESP, "", msg);
+						}
 						(MsgType::Submit, Some(true)) => {
 							log::trace!("缓存消息.等待回执..消息:{}", msg);
+							//重发的消息已经统计过.只在第一次发送时统计
+							if msg[RETRY_COUNT].is_null() {
+								context.statistics.add_mt(msg[SERVICE_ID].as_str().unwrap_or(""), msg[MSG_IDS].len() as u32, &msg[DEST_IDS]);
+							}
+
+							//每个seq_id都等待一个回执
+							let channel_id = msg[CHANNEL_ID].as_usize();
+							if let Some(channel) = context.send_channels.iter_mut().find(|item| Some(item.id) == channel_id) {
+								channel.in_flight += msg[SEQ_IDS].len();
+							}
+
 							insert_into_wait_receipt(&mut context.wait_receipt_map, msg);
 						}
 						//状态报告需要等待回执
 						(MsgType::Deliver, Some(true)) |
 						(MsgType::Report, Some(true)) => {
 							log::trace!("缓存上行或状态报告消息.等待回执..消息:{}", msg);
+							match v.into() {
+								MsgType::Report => context.statistics.add_mt_result(msg[SERVICE_ID].as_str().unwrap_or(""), is_delivered(&msg)),
+								_ => context.statistics.add_mo(msg[SERVICE_ID].as_str().unwrap_or("")),
+							}
+
 							if msg[SEQ_IDS].is_array() && !msg[SEQ_IDS].is_empty() {
 								if let Some(seq_id) = msg[SEQ_IDS][0].as_u64() {
 									context.wait_receipt_map.insert(seq_id, msg);
@@ -289,13 +663,22 @@ async fn handle_from_channel_rx(msg: Option<JsonValue>, context: &mut EntityRunC
 								log::trace!("收到deliver回执..移除缓存:{}", item);
 
 								msg[ACCOUNT_MSG_ID] = item.remove(MSG_ID);
+								//状态报告的回执也是走的这里.只统计上行
+								if item[MSG_TYPE_STR].as_str() == Some("Deliver") {
+									context.statistics.add_mo_result(item[SERVICE_ID].as_str().unwrap_or(""), msg[RESULT].as_u32().unwrap_or(0) == 0);
+								}
 							}
 
 							send_to_queue!(&context.to_queue, TOPIC_TO_B_DELIVER_RESP, "", msg);
 						}
 						(MsgType::Report, Some(false)) |
 						(MsgType::Report, None) => {
+							context.statistics.add_mt_result(msg[SERVICE_ID].as_str().unwrap_or(""), is_delivered(&msg));
+
 							if !msg[PASSAGE_MSG_ID].is_empty() {
+								if let Some(passage_msg_id) = msg[PASSAGE_MSG_ID].as_str() {
+									SCHEDULED_MSG_INDEX.write().await.remove(passage_msg_id);
+								}
 								msg[MSG_ID] = msg.remove(PASSAGE_MSG_ID);
 							}
 
@@ -304,8 +687,12 @@ async fn handle_from_channel_rx(msg: Option<JsonValue>, context: &mut EntityRunC
 						//收到上传消息
 						(MsgType::Deliver, Some(false)) |
 						(MsgType::Deliver, None) => {
+							//收到的上行已经回复成功
+							context.statistics.add_mo(msg[SERVICE_ID].as_str().unwrap_or(""));
+							context.statistics.add_mo_result(msg[SERVICE_ID].as_str().unwrap_or(""), true);
+
 							if let Some(total) = msg[LONG_SMS_TOTAL].as_u8() {
-								if let Some(mut json) = handle_long_sms(context, msg, total) {
+								if let Some(mut json) = context.long_sms_cache.add(msg, total) {
 									send_to_queue!(&context.to_queue, TOPIC_TO_B_DELIVER, "", json);
 								}
 							} else {
@@ -319,11 +706,15 @@ async fn handle_from_channel_rx(msg: Option<JsonValue>, context: &mut EntityRunC
 								msg.remove(SPEED_LIMIT);
 								send_to_channels(msg, context).await;
 							}	else if let Some(total) = msg[LONG_SMS_TOTAL].as_u8() {
+								context.statistics.add_mt(msg[SERVICE_ID].as_str().unwrap_or(""), 1, &msg[DEST_IDS]);
+
 								//长短信的处理
-								if let Some(mut json) = handle_long_sms(context, msg, total) {
+								if let Some(mut json) = context.long_sms_cache.add(msg, total) {
 									send_to_queue!(&context.to_queue, TOPIC_TO_B_SUBMIT, "", json);
 								}
 							} else {
+								context.statistics.add_mt(msg[SERVICE_ID].as_str().unwrap_or(""), 1, &msg[DEST_IDS]);
+
 								let mut msg_ids = Vec::with_capacity(1);
 								msg_ids.push(msg[MSG_ID].as_str().unwrap_or(""));
 								msg[MSG_IDS] = msg_ids.into();
@@ -332,6 +723,28 @@ async fn handle_from_channel_rx(msg: Option<JsonValue>, context: &mut EntityRunC
 								send_to_queue!(&context.to_queue, TOPIC_TO_B_SUBMIT, "", msg);
 							}
 						}
+						//对端的查询请求.使用统计数据回复到原通道
+						(MsgType::Query, None) => {
+							let mut resp = json::object! {
+								msg_type: "QueryResp",
+								channel_id: msg[CHANNEL_ID].clone(),
+								seq_id: msg[SEQ_ID].clone(),
+								query_time: msg[QUERY_TIME].clone(),
+								query_type: msg[QUERY_TYPE].clone(),
+								query_code: msg[QUERY_CODE].clone(),
+							};
+
+							context.statistics.fill_query_resp(&mut resp);
+							reply_to_channel(resp, context).await;
+						}
+						//收到对端的查询结果
+						(MsgType::QueryResp, None) => {
+							send_to_queue!(&context.to_queue, TOPIC_TO_B_QUERY_RESP, "", msg);
+						}
+						//自己发出的查询和查询回复,回复给对端的删除结果.不需要处理
+						(MsgType::Query, Some(_)) |
+						(MsgType::QueryResp, Some(_)) |
+						(MsgType::CancelResp, Some(_)) => {}
 						(MsgType::Terminate, _) => {
 							log::info!("通道关闭操作。msg:{}", msg);
 
@@ -367,6 +780,7 @@ async fn handle_from_channel_rx(msg: Option<JsonValue>, context: &mut EntityRunC
 										can_write: msg[CAN_WRITE].as_bool().unwrap_or(true),
 										entity_to_channel_priority_tx,
 										entity_to_channel_common_tx,
+										in_flight: 0,
 									});
 
 									context.now_conn_num.swap(context.send_channels.len() as u8, SeqCst);
@@ -431,82 +845,6 @@ fn insert_into_wait_receipt(wait_receipt_map: &mut HashMap<u64, JsonValue>, json
 	}
 }
 
-fn handle_long_sms(context: &mut EntityRunContext, msg: JsonValue, total: u8) -> Option<JsonValue> {
-	let key = String::from(msg[SRC_ID].as_str().unwrap_or("")).
-		add(msg[DEST_ID].as_str().unwrap_or(msg[DEST_IDS][0].as_str().unwrap_or("")));
-
-	let index = match msg[LONG_SMS_NOW_NUMBER].as_u8() {
-		None => {
-			log::error!("没有当前是长短信的第几个的信息。不能放入。msg:{}", msg);
-			return None;
-		}
-		Some(index) => {
-			if index > total || total < 2 {
-				log::error!("当前索引超过最大长度,或者总长度小于2。不能放入。msg:{}", msg);
-				return None;
-			}
-
-			(index - 1) as usize
-		}
-	};
-
-	let vec = match context.long_sms_cache.get_mut(&key) {
-		None => {
-			let mut vec = vec![None; total as usize];
-
-			vec[index] = Some(msg);
-			context.long_sms_cache.insert(key, vec);
-
-			return None;
-		}
-		Some(vec) => {
-			//当上一次未收完全。可能导致后续另外收消息时候出现此问题。出现此问题。重置数组
-			if vec.len() != total as usize {
-				*vec = vec![None; total as usize];
-			}
-
-			vec[index] = Some(msg);
-
-			vec
-		}
-	};
-
-	//没满,退出
-	if let Some(_) = vec.iter().find(|json| json.is_none()) {
-		return None;
-	}
-
-	//满了的处理方式
-	let mut msg_ids = Vec::with_capacity(total as usize);
-	let mut msg_content = String::new();
-
-	for item in vec.iter() {
-		let item = item.as_ref().unwrap();
-		let msg_id = match item[MSG_ID].as_str() {
-			None => {
-				log::error!("从数据里面没有找到.msg_id字段...{}", item);
-				""
-			}
-			Some(msg_id) => msg_id
-		};
-
-		msg_ids.push(msg_id.to_owned());
-		msg_content.push_str(item[MSG_CONTENT].as_str().unwrap_or(""));
-	}
-
-	let mut json = vec.remove(0).unwrap();
-
-	json[MSG_CONTENT] = msg_content.as_str().into();
-	json[MSG_IDS] = msg_ids.into();
-	json.remove(MSG_ID);
-	json.remove(LONG_SMS_TOTAL);
-	json.remove(LONG_SMS_NOW_NUMBER);
-
-	context.long_sms_cache.remove(&key);
-
-	Some(json)
-}
-
 ///entity处理来自于管理器端的消息
 async fn handle_from_manager_rx(msg: Option<JsonValue>, context: &mut EntityRunContext) -> bool {
 	match msg {
@@ -517,7 +855,14 @@ async fn handle_from_manager_rx(msg: Option<JsonValue>, context: &mut EntityRunC
 		Some(msg) => {
 			match msg[MANAGER_TYPE].as_str() {
 				Some("send") => {
-					send_to_channels(msg, context).await;
+					match msg[MSG_TYPE_STR].as_str() {
+						Some("Submit") => schedule_or_send(msg, context).await,
+						//没有passage_msg_id的删除请求是删除网关保存的定时短信
+						Some("Cancel") if msg[PASSAGE_MSG_ID].is_null() => cancel_schedule(msg, context).await,
+						//转给其他实体删除的客户请求的结果.回复到原通道
+						Some("CancelResp") => reply_to_channel(msg, context).await,
+						_ => send_to_channels(msg, context).await,
+					}
 				}
 				Some("passage.request.state") => {
 					log::info!("收到需要状态的消息。id:{}", context.entity_id);
@@ -563,60 +908,84 @@ async fn send_to_channels(msg: JsonValue, context: &mut EntityRunContext) {
 		send_msg[SERVICE_ID] = context.service_id.as_str().into();
 	}
 
+	//记录第一次发送的时间.用来判断重发是否超过期限
+	if send_msg[FIRST_SEND_TIME].is_null() {
+		send_msg[FIRST_SEND_TIME] = chrono::Local::now().timestamp().into();
+	}
+
+	//消息里面没有指定的计费字段使用通道的默认值
+	if send_msg[MSG_TYPE_STR] == "Submit" {
+		//超过有效期的不再发送.包括重发和超速压回来的
+		let now = chrono::Local::now().timestamp();
+		fill_expire_time(&mut send_msg, now);
+		if is_expired(&send_msg, now) {
+			send_expired(send_msg).await;
+			return;
+		}
+
+		for (field, value) in context.fee_defaults.entries() {
+			if send_msg[field].is_null() {
+				send_msg[field] = value.clone();
+			}
+		}
+	}
+
+	//按允许的字符集选择内容的编码.都不能编码时直接返回失败.二进制短信不需要选择
+	let is_binary = send_msg[MSG_FMT].as_u8().map(is_binary_msg_fmt).unwrap_or(false);
+	if let (Some(msg_content), false) = (send_msg[MSG_CONTENT].as_str(), is_binary) {
+		match select_msg_fmt(msg_content, send_msg[MSG_FMT].as_u8(), &context.msg_fmts) {
+			Some(msg_fmt) => send_msg[MSG_FMT] = msg_fmt.into(),
+			None => {
+				log::error!("没有可以编码短信内容的字符集.返回失败.id:{}.msg_fmts:{:?}.msg:{}", context.entity_id, context.msg_fmts, send_msg);
+				context.to_queue.send(TOPIC_TO_B_FAILURE, "4", send_msg.to_string()).await;
+				return;
+			}
+		}
+	}
+
 	log::debug!("选择一个可用的channel发送.id:{}..现有通道数:{},msg:{}", context.entity_id, context.send_channels.len(),&send_msg);
 
 	let mut failure: Option<(JsonValue, usize)> = None;
 	let size = context.send_channels.len();
 	let priority = send_msg[IS_PRIORITY].as_bool().unwrap_or(false);
 	for _ in 0..size {
-		if context.index == usize::MAX {
-			context.index = 1;
-		}
-		context.index += 1;
-
-		match context.send_channels.get(context.index % context.send_channels.len()) {
-			Some(select) => {
-				if select.can_write {
-					log::debug!("收到消息.分channel发送。通道:{}", &select.id);
-					
-					if context.entity_type == EntityType::Server && context.send_buff_cap < CHANNEL_BUFF_NUM {
-						//判断是否已经超出指定缓冲区大小
-						let buff_num = CHANNEL_BUFF_NUM - select.entity_to_channel_priority_tx.capacity() + CHANNEL_BUFF_NUM - select.entity_to_channel_common_tx.capacity();
-					
-						log::trace!("打印看一下，buff_num：{},send_buff_cap：{},is_buff_full：{}",
-							buff_num,context.send_buff_cap,context.is_buff_full
-						);
-						if buff_num > context.send_buff_cap && !context.is_buff_full 
-								// || buff_num % context.send_buff_cap == 0)  //每超过整数倍的时候发送一次
-						{
-							log::trace!("向消息队列发通道满状态，buff_num：{},send_buff_cap：{},is_buff_full：{}",
-								buff_num,context.send_buff_cap,context.is_buff_full
-							);
-							context.is_buff_full = true;
-							send_entity_state!(context);
-						}
-					}
-
-					let send = if priority {
-						&select.entity_to_channel_priority_tx
-					} else {
-						&select.entity_to_channel_common_tx
-					};
-
-					if let Err(e) = send.send(send_msg).await {
-						log::error!("发送正常消息出现异常.e:{}", e);
-						failure = Some((e.0, select.id));
-					}
+		let select = match select_channel(context) {
+			Some(index) => &context.send_channels[index],
+			//没有可写的通道
+			None => break,
+		};
 
-				} else {
-					continue;
-				}
-			},
-			None => {
-				continue;
+		log::debug!("收到消息.分channel发送。通道:{}", &select.id);
+
+		if context.entity_type == EntityType::Server && context.send_buff_cap < CHANNEL_BUFF_NUM {
+			//判断是否已经超出指定缓冲区大小
+			let buff_num = select.queued();
+
+			log::trace!("打印看一下，buff_num：{},send_buff_cap：{},is_buff_full：{}",
+				buff_num,context.send_buff_cap,context.is_buff_full
+			);
+			if buff_num > context.send_buff_cap && !context.is_buff_full 
+					// || buff_num % context.send_buff_cap == 0)  //每超过整数倍的时候发送一次
+			{
+				log::trace!("向消息队列发通道满状态，buff_num：{},send_buff_cap：{},is_buff_full：{}",
+					buff_num,context.send_buff_cap,context.is_buff_full
+				);
+				context.is_buff_full = true;
+				send_entity_state!(context);
 			}
+		}
+
+		let send = if priority {
+			&select.entity_to_channel_priority_tx
+		} else {
+			&select.entity_to_channel_common_tx
 		};
 
+		if let Err(e) = send.send(send_msg).await {
+			log::error!("发送正常消息出现异常.e:{}", e);
+			failure = Some((e.0, select.id));
+		}
+
 		//当某一个通道端已经退出了，将对应的也退出
 		if let Some((json, channel_id)) = failure {
 			context.send_channels.retain(|item| item.id != channel_id);
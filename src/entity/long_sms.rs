@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use json::JsonValue;
+
+use crate::protocol::names::{DEST_ID, DEST_IDS, LONG_SMS_MISSING, LONG_SMS_NOW_NUMBER, LONG_SMS_PARTIAL, LONG_SMS_REF, LONG_SMS_TOTAL, MSG_CONTENT, MSG_HEX, MSG_ID, MSG_IDS, PK_NUMBER, PK_TOTAL, SRC_ID, UDH_HEX};
+
+///长短信等待其他部分的默认超时时间(秒)
+pub const DEFAULT_LONG_SMS_TIMEOUT: i64 = 600;
+
+///长短信缓存的key.缓存放在每个实体里面,所以实体本身不需要放在key里.
+///参考号相同但总条数不同的认为是不同的长短信
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct LongSmsKey {
+	src_id: String,
+	dest_id: String,
+	reference: Option<u16>,
+	total: u8,
+}
+
+#[derive(Debug)]
+struct LongSmsParts {
+	///收到第一条的时间
+	first_time: i64,
+	items: Vec<Option<JsonValue>>,
+}
+
+///长短信的合并缓存.按(主叫,被叫,UDH参考号)缓存每一条,全部收到以后合并成一条返回.
+///超时未收全的按已经收到的部分合并,并加上l_s_partial标记
+#[derive(Debug)]
+pub struct LongSmsCache {
+	timeout: i64,
+	cache: HashMap<LongSmsKey, LongSmsParts>,
+}
+
+impl LongSmsCache {
+	pub fn new(timeout: i64) -> Self {
+		LongSmsCache {
+			timeout: if timeout > 0 { timeout } else { DEFAULT_LONG_SMS_TIMEOUT },
+			cache: HashMap::new(),
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.cache.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.cache.is_empty()
+	}
+
+	///放入长短信的一条.全部收到以后返回合并好的消息
+	pub fn add(&mut self, msg: JsonValue, total: u8) -> Option<JsonValue> {
+		let index = match msg[LONG_SMS_NOW_NUMBER].as_u8() {
+			None => {
+				log::error!("没有当前是长短信的第几个的信息。不能放入。msg:{}", msg);
+				return None;
+			}
+			Some(index) => {
+				if index > total || index == 0 || total < 2 {
+					log::error!("当前索引超过最大长度,或者总长度小于2。不能放入。msg:{}", msg);
+					return None;
+				}
+
+				(index - 1) as usize
+			}
+		};
+
+		let key = LongSmsKey {
+			src_id: msg[SRC_ID].as_str().unwrap_or("").to_owned(),
+			dest_id: msg[DEST_ID].as_str().unwrap_or(msg[DEST_IDS][0].as_str().unwrap_or("")).to_owned(),
+			reference: msg[LONG_SMS_REF].as_u16(),
+			total,
+		};
+
+		let parts = self.cache.entry(key.clone()).or_insert_with(|| LongSmsParts {
+			first_time: chrono::Local::now().timestamp(),
+			items: vec![None; total as usize],
+		});
+
+		if parts.items[index].is_some() {
+			log::warn!("收到重复的长短信.使用后收到的.msg:{}", msg);
+		}
+		parts.items[index] = Some(msg);
+
+		//没满,退出
+		if parts.items.iter().any(|item| item.is_none()) {
+			return None;
+		}
+
+		let parts = self.cache.remove(&key)?;
+
+		Some(merge_parts(parts.items, total))
+	}
+
+	///取出超时未收全的长短信.每一组按已经收到的部分合并成一条
+	pub fn take_expired(&mut self) -> Vec<JsonValue> {
+		let now = chrono::Local::now().timestamp();
+		let timeout = self.timeout;
+		let mut expired = Vec::new();
+
+		self.cache.retain(|key, parts| {
+			if parts.first_time + timeout > now {
+				return true;
+			}
+
+			expired.push(merge_parts(std::mem::take(&mut parts.items), key.total));
+			false
+		});
+
+		expired
+	}
+}
+
+///把收到的部分按顺序合并.有缺少的部分时加上l_s_partial和缺少的序号
+fn merge_parts(items: Vec<Option<JsonValue>>, total: u8) -> JsonValue {
+	let mut msg_ids = Vec::with_capacity(total as usize);
+	let mut missing = Vec::new();
+	let mut msg_content = String::new();
+	let mut msg_hex = String::new();
+	let mut first = None;
+
+	for (i, item) in items.into_iter().enumerate() {
+		let item = match item {
+			Some(item) => item,
+			None => {
+				missing.push(i + 1);
+				continue;
+			}
+		};
+
+		let msg_id = match item[MSG_ID].as_str() {
+			None => {
+				log::error!("从数据里面没有找到.msg_id字段...{}", item);
+				""
+			}
+			Some(msg_id) => msg_id
+		};
+
+		msg_ids.push(msg_id.to_owned());
+		msg_content.push_str(item[MSG_CONTENT].as_str().unwrap_or(""));
+		msg_hex.push_str(item[MSG_HEX].as_str().unwrap_or(""));
+
+		if first.is_none() {
+			first = Some(item);
+		}
+	}
+
+	//至少有一条才会放进缓存
+	let mut json = first.unwrap_or_else(|| json::object! {});
+
+	//二进制的长短信合并16进制的内容.每一条的UDH不再需要
+	if json[MSG_HEX].is_null() {
+		json[MSG_CONTENT] = msg_content.as_str().into();
+	} else {
+		json[MSG_HEX] = msg_hex.as_str().into();
+		json.remove(UDH_HEX);
+	}
+	json[MSG_IDS] = msg_ids.into();
+	json.remove(MSG_ID);
+	json.remove(LONG_SMS_TOTAL);
+	json.remove(LONG_SMS_NOW_NUMBER);
+	json.remove(LONG_SMS_REF);
+	json.remove(PK_TOTAL);
+	json.remove(PK_NUMBER);
+
+	if !missing.is_empty() {
+		log::warn!("长短信超时未收全.缺少:{:?}.按已经收到的部分发送.json:{}", missing, json);
+		json[LONG_SMS_PARTIAL] = true.into();
+		json[LONG_SMS_TOTAL] = total.into();
+		json[LONG_SMS_MISSING] = missing.into();
+	}
+
+	json
+}
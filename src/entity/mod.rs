@@ -1,17 +1,24 @@
 use std::fmt::Debug;
 use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use json::JsonValue;
 use tokio::sync::{mpsc};
 
-use crate::protocol::names::{AUTHENTICATOR, TIMESTAMP, VERSION};
+use crate::protocol::names::{AUTHENTICATOR, CHANNEL_SELECT, GATEWAY_SCHEDULE, WINDOW_SIZE, READ_BURST, WRITE_BURST, MAX_RETRIES, MAX_RETRY_INTERVAL, RETRY_DEADLINE, CHARGE_ID, DEST_TERM_TYPE, FEE_CODE, FEE_DEFAULTS, FEE_TERM_TYPE, FEE_TYPE, FEE_USER_TYPE, FIXED_FEE, LONG_SMS_TIMEOUT, MO_RELATE_MT, MSG_FMTS, MSG_LEVEL, TIMESTAMP, VERSION};
+use crate::protocol::implements::DEFAULT_MSG_FMTS;
 use crate::protocol::{Protocol, SmsStatus};
+use crate::global::CHANNEL_BUFF_NUM;
 
 pub use self::as_custom::CustomEntity;
 pub use self::entity_manager::EntityManager;
 pub use self::services::ServersManager;
 pub use self::entity_running::start_entity;
+pub use self::long_sms::LongSmsCache;
+pub use self::schedule::SmsSchedule;
+pub use self::token_bucket::{AdaptiveRate, TokenBucket};
+pub use self::retry::RetryPolicy;
 
 
 #[macro_use]
@@ -21,12 +28,17 @@ pub mod as_server;
 mod services;
 mod entity_manager;
 mod entity_running;
+pub mod statistics;
+mod long_sms;
+pub mod schedule;
+pub mod token_bucket;
+pub mod retry;
 
 #[async_trait]
 pub trait Entity: Send + Sync + Debug {
 	/// 返回值依次为:
-	/// id,登录状态,rx_limit,tx_limit,entity_to_channel_priority_rx,entity_to_channel_common_rx,channel_to_entity_tx
-	async fn login_attach(&self,can_write: bool) -> (usize, SmsStatus, u32, u32, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Sender<JsonValue>>);
+	/// id,登录状态,接收令牌桶,发送令牌桶,window_size,entity_to_channel_priority_rx,entity_to_channel_common_rx,channel_to_entity_tx
+	async fn login_attach(&self,can_write: bool) -> (usize, SmsStatus, Option<Arc<TokenBucket>>, Option<Arc<TokenBucket>>, usize, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Receiver<JsonValue>>, Option<mpsc::Sender<JsonValue>>);
 	fn get_id(&self) -> u32;
 	fn get_login_name(&self) -> &str;
 	fn get_password(&self) -> &str;
@@ -35,6 +47,21 @@ pub trait Entity: Send + Sync + Debug {
 	///当前entity是否允许登录
 	fn can_login(&self) -> bool;
 	fn login(&self,json: &JsonValue, protocol: &mut Protocol, ip_addr: IpAddr) -> Option<(SmsStatus, JsonValue)>{
+		//先根据对方版本号更换协议.保证后面返回的错误也使用对方的版本进行编码
+		let version = match json[VERSION].as_u32() {
+			Some(version) => version,
+			None => {
+				log::error!("附加至CustomEntity通道异常。json里面没有version。。json:{}", json);
+				return Some((SmsStatus::OtherError, json.clone()));
+			}
+		};
+
+		if !protocol.has(version) {
+			return Some((SmsStatus::VersionError, json.clone()));
+		}
+
+		*protocol = protocol.match_version(version);
+
 		//进行地址允许判断
 		if !check_addr_range(self.get_allow_ips(), ip_addr) {
 			return Some((SmsStatus::AddError, json.clone()));
@@ -50,23 +77,126 @@ pub trait Entity: Send + Sync + Debug {
 			return Some((SmsStatus::AuthError, json.clone()));
 		}
 	
-		//进行版本检查.并且根据对方版本号进行修改
-		if let Some(version) = json[VERSION].as_u32() {
-			if !protocol.has(version) {
-				return Some((SmsStatus::VersionError, json.clone()));
-			} else {
-				*protocol = protocol.match_version(version);
-			}
-		} else {
-			log::error!("附加至CustomEntity通道异常。json里面没有version。。json:{}", json);
-			return Some((SmsStatus::OtherError, json.clone()));
-		}
-	
 		None
 	}
 }
 
 
+///从配置里取得允许使用的字符集.可以是数组或者逗号分隔的字串.未配置时使用默认顺序
+fn get_msg_fmts(config: &JsonValue) -> Vec<u8> {
+	let msg_fmts: Vec<u8> = match &config[MSG_FMTS] {
+		JsonValue::Array(array) => array.iter().filter_map(|v| v.as_u8()).collect(),
+		v => v.as_str().unwrap_or("").split(',').filter_map(|v| v.trim().parse().ok()).collect()
+	};
+
+	let msg_fmts: Vec<u8> = msg_fmts.into_iter().filter(|v| DEFAULT_MSG_FMTS.contains(v)).collect();
+	if msg_fmts.is_empty() {
+		DEFAULT_MSG_FMTS.to_vec()
+	} else {
+		msg_fmts
+	}
+}
+
+///从配置里取得长短信等待其他部分的超时时间(秒).可以是数字或者字串.未配置时使用默认值
+fn get_long_sms_timeout(config: &JsonValue) -> i64 {
+	config[LONG_SMS_TIMEOUT].as_i64()
+		.or_else(|| config[LONG_SMS_TIMEOUT].as_str().and_then(|v| v.trim().parse().ok()))
+		.unwrap_or(long_sms::DEFAULT_LONG_SMS_TIMEOUT)
+}
+
+///从配置里取得发送时选择连接的方式.未配置或者不认识的使用轮询
+fn get_channel_select(config: &JsonValue) -> ChannelSelect {
+	config[CHANNEL_SELECT].as_str().unwrap_or("").into()
+}
+
+///从配置里取得每个连接发出未收到回复的最大请求数.可以是数字或者字串.未配置时为0,不限制
+fn get_window_size(config: &JsonValue) -> usize {
+	config[WINDOW_SIZE].as_usize()
+		.or_else(|| config[WINDOW_SIZE].as_str().and_then(|v| v.trim().parse().ok()))
+		.unwrap_or(0)
+}
+
+///按配置的速度和突发数量生成接收和发送的令牌桶.突发数量可以是数字或者字串
+fn get_token_buckets(config: &JsonValue, read_limit: u32, write_limit: u32) -> (Arc<TokenBucket>, Arc<TokenBucket>) {
+	let burst = |key: &str| config[key].as_u32()
+		.or_else(|| config[key].as_str().and_then(|v| v.trim().parse().ok()))
+		.unwrap_or(0);
+
+	(Arc::new(TokenBucket::new(read_limit, burst(READ_BURST))), Arc::new(TokenBucket::new(write_limit, burst(WRITE_BURST))))
+}
+
+///从配置里取得重发策略.可以是数字或者字串.未配置或者为负数的使用默认值
+fn get_retry_policy(config: &JsonValue) -> RetryPolicy {
+	let get = |key: &str| config[key].as_i64()
+		.or_else(|| config[key].as_str().and_then(|v| v.trim().parse().ok()))
+		.filter(|v| *v >= 0);
+
+	RetryPolicy {
+		max_retries: get(MAX_RETRIES).map(|v| v as u32).unwrap_or(retry::DEFAULT_MAX_RETRIES),
+		max_interval: get(MAX_RETRY_INTERVAL).unwrap_or(retry::DEFAULT_MAX_RETRY_INTERVAL),
+		deadline: get(RETRY_DEADLINE).unwrap_or(retry::DEFAULT_RETRY_DEADLINE),
+	}
+}
+
+///从配置里取得at_time是否由网关保存.可以是布尔值或者字串.未配置时由网关保存
+fn get_gateway_schedule(config: &JsonValue) -> bool {
+	config[GATEWAY_SCHEDULE].as_bool()
+		.or_else(|| config[GATEWAY_SCHEDULE].as_str().and_then(|v| v.trim().parse().ok()))
+		.unwrap_or(true)
+}
+
+///可以在通道配置里面指定默认值的计费字段
+static FEE_FIELDS: [&str; 9] = [FEE_TYPE, FEE_CODE, FIXED_FEE, CHARGE_ID, FEE_USER_TYPE, FEE_TERM_TYPE, DEST_TERM_TYPE, MSG_LEVEL, MO_RELATE_MT];
+
+///从配置里取得计费字段的默认值.可以是对象或者json字串.只保留计费相关的字段
+fn get_fee_defaults(config: &JsonValue) -> JsonValue {
+	let defaults = match &config[FEE_DEFAULTS] {
+		JsonValue::Object(_) => config[FEE_DEFAULTS].clone(),
+		v => v.as_str().and_then(|v| json::parse(v).ok()).unwrap_or_else(JsonValue::new_object),
+	};
+
+	let mut result = JsonValue::new_object();
+	for field in FEE_FIELDS.iter() {
+		if !defaults[*field].is_null() {
+			result[*field] = defaults[*field].clone();
+		}
+	}
+
+	result
+}
+
+///实体运行时使用的通道配置.由通道配置和实体的发送令牌桶生成,传给start_entity
+#[derive(Debug, Clone)]
+pub struct EntityConfig {
+	///允许使用的字符集.按优先顺序排列
+	pub msg_fmts: Vec<u8>,
+	///长短信等待其他部分的超时时间(秒)
+	pub long_sms_timeout: i64,
+	///下行短信计费字段的默认值
+	pub fee_defaults: JsonValue,
+	///发送时选择连接的方式
+	pub channel_select: ChannelSelect,
+	///所有连接共用的发送令牌桶
+	pub write_bucket: Arc<TokenBucket>,
+	///未收到回执时的重发策略
+	pub retry_policy: RetryPolicy,
+	///at_time是否由网关保存.false时直接发往通道
+	pub gateway_schedule: bool,
+}
+
+///从配置里取得实体运行时使用的配置
+fn get_entity_config(config: &JsonValue, write_bucket: Arc<TokenBucket>) -> EntityConfig {
+	EntityConfig {
+		msg_fmts: get_msg_fmts(config),
+		long_sms_timeout: get_long_sms_timeout(config),
+		fee_defaults: get_fee_defaults(config),
+		channel_select: get_channel_select(config),
+		write_bucket,
+		retry_policy: get_retry_policy(config),
+		gateway_schedule: get_gateway_schedule(config),
+	}
+}
+
 ///检查ip地址是否在允许的范围内。true 在。false 不在
 fn check_addr_range(allow_ips: &str, now_ip: IpAddr) -> bool {
 	log::debug!("进行地址检查.来源地址:{}..允许的地址.{}", now_ip, allow_ips);
@@ -108,6 +238,36 @@ pub struct ChannelStates {
 	entity_to_channel_priority_tx: mpsc::Sender<JsonValue>,
 	///普通发送通道
 	entity_to_channel_common_tx: mpsc::Sender<JsonValue>,
+	///已经发出还未收到回执的下行短信数量
+	in_flight: usize,
+}
+
+impl ChannelStates {
+	///通道队列里面等待发送的消息数量
+	fn queued(&self) -> usize {
+		CHANNEL_BUFF_NUM - self.entity_to_channel_priority_tx.capacity() + CHANNEL_BUFF_NUM - self.entity_to_channel_common_tx.capacity()
+	}
+}
+
+///实体发送消息时选择连接的方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelSelect {
+	///轮询
+	RoundRobin,
+	///队列里面等待发送的消息最少的
+	LeastQueued,
+	///已经发出还未收到回执的消息最少的
+	LeastInFlight,
+}
+
+impl From<&str> for ChannelSelect {
+	fn from(name: &str) -> Self {
+		match name {
+			"leastQueued" => ChannelSelect::LeastQueued,
+			"leastInFlight" => ChannelSelect::LeastInFlight,
+			_ => ChannelSelect::RoundRobin,
+		}
+	}
 }
 
 
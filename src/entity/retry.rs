@@ -0,0 +1,45 @@
+use json::JsonValue;
+
+use crate::protocol::names::{FIRST_SEND_TIME, RETRY_COUNT};
+
+///发往sms.send.failure的原因.重发次数用完
+pub static RETRY_EXHAUSTED: &'static str = "retry_exhausted";
+///发往sms.send.failure的原因.超过重发期限
+pub static RETRY_DEADLINE_EXCEEDED: &'static str = "retry_deadline";
+
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_MAX_RETRY_INTERVAL: i64 = 300;
+pub const DEFAULT_RETRY_DEADLINE: i64 = 3600;
+
+///未收到回执或者回执为可重发错误时的重发策略
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+	///最多重发次数
+	pub max_retries: u32,
+	///等待回执的时间每次重发加倍.不超过这个值(秒)
+	pub max_interval: i64,
+	///从第一次发送开始超过这个时间(秒)不再重发.0为不限制
+	pub deadline: i64,
+}
+
+impl RetryPolicy {
+	///下一次等待回执的时间
+	pub fn next_duration(&self, duration: i64) -> i64 {
+		(duration * 2).min(self.max_interval).max(1)
+	}
+
+	///重发次数加一.已经不能再重发时返回原因
+	pub fn add_retry(&self, msg: &mut JsonValue, now: i64) -> Option<&'static str> {
+		let retry_count = msg[RETRY_COUNT].as_u32().unwrap_or(0) + 1;
+		msg[RETRY_COUNT] = retry_count.into();
+
+		if retry_count > self.max_retries {
+			return Some(RETRY_EXHAUSTED);
+		}
+
+		match msg[FIRST_SEND_TIME].as_i64() {
+			Some(time) if self.deadline > 0 && time + self.deadline < now => Some(RETRY_DEADLINE_EXCEEDED),
+			_ => None,
+		}
+	}
+}
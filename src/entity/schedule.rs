@@ -0,0 +1,222 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, TimeZone, Utc};
+use json::JsonValue;
+
+use crate::global::{message_sender, TOPIC_TO_B_FAILURE, TOPIC_TO_B_REPORT};
+use crate::protocol::names::{ACCOUNT_MSG_ID, AT_TIME, DEST_ID, DEST_IDS, DONE_TIME, ENTITY_ID, EXPIRE_TIME, IS_REPORT, IS_SYNTHETIC, MSG_IDS, REASON, RECEIVE_TIME, REPORT_STATUS, SERVICE_ID, SRC_ID, STATE, SUBMIT_TIME, VALID_TIME};
+use crate::protocol::report_status::EXPIRED;
+
+///网关保存定时短信的目录.每个实体一个文件
+pub static SCHEDULE_DIR: &'static str = "data/schedule";
+
+///at_time在这个时间(秒)以内的直接发送,不放入定时
+const SCHEDULE_MIN_DELAY: i64 = 5;
+
+///网关自己保存的定时短信.at_time到了以后再发往通道.
+///变化时只做标记,由实体在每次循环时调用save整体写入文件.实体重新启动时从文件恢复
+#[derive(Debug)]
+pub struct SmsSchedule {
+	file: PathBuf,
+	///(发送时间,消息).按发送时间排序
+	items: Vec<(i64, JsonValue)>,
+	///有未写入文件的变化
+	changed: bool,
+}
+
+impl SmsSchedule {
+	///从实体对应的文件恢复定时短信.文件不存在时为空
+	pub fn load(dir: &str, entity_id: u32) -> Self {
+		let file = PathBuf::from(dir).join(format!("{}.json", entity_id));
+		let mut items = Vec::new();
+
+		if let Ok(text) = fs::read_to_string(&file) {
+			match json::parse(text.as_str()) {
+				Ok(json) => {
+					for item in json.members() {
+						if let Some(time) = item["time"].as_i64() {
+							items.push((time, item["msg"].clone()));
+						}
+					}
+				}
+				Err(e) => log::error!("定时短信文件格式错误.不恢复.file:{:?}.e:{}", file, e),
+			}
+		}
+
+		items.sort_by_key(|(time, _)| *time);
+
+		SmsSchedule { file, items, changed: false }
+	}
+
+	pub fn len(&self) -> usize {
+		self.items.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.items.is_empty()
+	}
+
+	///全部定时短信的msg_ids
+	pub fn msg_ids(&self) -> Vec<String> {
+		self.items.iter()
+			.flat_map(|(_, msg)| msg[MSG_IDS].members().filter_map(|id| id.as_str().map(|id| id.to_owned())))
+			.collect()
+	}
+
+	///at_time为以后的时间时放入定时并返回None.其他的原样返回,直接发送
+	pub fn add(&mut self, msg: JsonValue, now: i64) -> Option<JsonValue> {
+		let time = match msg[AT_TIME].as_str().and_then(|at_time| parse_at_time(at_time, now)) {
+			Some(time) if time > now + SCHEDULE_MIN_DELAY => time,
+			_ => return Some(msg),
+		};
+
+		let index = self.items.partition_point(|(item_time, _)| *item_time <= time);
+		self.items.insert(index, (time, msg));
+		self.changed = true;
+
+		None
+	}
+
+	///取出已经到时间的定时短信.at_time已经由网关处理,发往通道时不再带上
+	pub fn take_due(&mut self, now: i64) -> Vec<JsonValue> {
+		let count = self.items.partition_point(|(time, _)| *time <= now);
+		if count == 0 {
+			return Vec::new();
+		}
+
+		let result = self.items.drain(..count).map(|(_, mut msg)| {
+			msg[AT_TIME] = "".into();
+			msg
+		}).collect();
+		self.changed = true;
+
+		result
+	}
+
+	///按msg_id删除还未发送的定时短信.长短信的任意一个msg_id都删除整条
+	pub fn remove(&mut self, msg_id: &str) -> Option<JsonValue> {
+		let index = self.items.iter().position(|(_, msg)| msg[MSG_IDS].members().any(|id| id.as_str() == Some(msg_id)))?;
+		let (_, msg) = self.items.remove(index);
+		self.changed = true;
+
+		Some(msg)
+	}
+
+	///到下一条定时短信发送的秒数
+	pub fn next_wait(&self, now: i64) -> Option<i64> {
+		self.items.first().map(|(time, _)| (*time - now).max(0))
+	}
+
+	///有变化时写入文件.一次循环内的多次变化只写一次.
+	///文件操作放到阻塞线程里执行,不占用异步任务的线程.写入失败时下次再写
+	pub async fn save(&mut self) {
+		if !self.changed {
+			return;
+		}
+
+		let mut json = JsonValue::new_array();
+		for (time, msg) in self.items.iter() {
+			let _ = json.push(json::object! {time: *time, msg: msg.clone()});
+		}
+
+		let file = self.file.clone();
+		let result = tokio::task::spawn_blocking(move || write_file(&file, json.dump())).await
+			.unwrap_or_else(|e| Err(io::Error::new(io::ErrorKind::Other, e.to_string())));
+
+		match result {
+			Ok(_) => self.changed = false,
+			Err(e) => log::error!("保存定时短信文件出错.file:{:?}.e:{}", self.file, e),
+		}
+	}
+}
+
+///先写临时文件再改名.避免写一半的时候退出造成文件损坏
+fn write_file(file: &Path, text: String) -> io::Result<()> {
+	let tmp = file.with_extension("tmp");
+
+	file.parent().map(fs::create_dir_all).unwrap_or(Ok(()))?;
+	fs::write(&tmp, text)?;
+	fs::rename(&tmp, file)
+}
+
+///解析at_time为时间戳.
+///16位为协议格式YYMMDDhhmmsstnnp.p为+\-时是nn个15分钟时区的绝对时间,为R时是相对当前的时间.
+///14位为本地时间yyyyMMddHHmmss,12位为本地时间YYMMDDhhmmss.其他的返回None
+pub fn parse_at_time(at_time: &str, now: i64) -> Option<i64> {
+	let at_time = at_time.trim();
+	if !at_time.is_ascii() {
+		return None;
+	}
+
+	let num = |start: usize, end: usize| at_time.get(start..end).and_then(|v| v.parse::<u32>().ok());
+
+	match at_time.len() {
+		16 => {
+			let (yy, mm, dd, hh, mi, ss, nn) = (num(0, 2)?, num(2, 4)?, num(4, 6)?, num(6, 8)?, num(8, 10)?, num(10, 12)?, num(13, 15)?);
+
+			match &at_time[15..] {
+				"R" => Some(now + (yy as i64 * 365 + mm as i64 * 30 + dd as i64) * 86400 + hh as i64 * 3600 + mi as i64 * 60 + ss as i64),
+				p @ "+" | p @ "-" => {
+					let offset = nn as i64 * 15 * 60;
+					let time = Utc.with_ymd_and_hms(2000 + yy as i32, mm, dd, hh, mi, ss).single()?.timestamp();
+
+					Some(if p == "+" { time - offset } else { time + offset })
+				}
+				_ => None,
+			}
+		}
+		14 => Some(Local.with_ymd_and_hms(num(0, 4)? as i32, num(4, 6)?, num(6, 8)?, num(8, 10)?, num(10, 12)?, num(12, 14)?).single()?.timestamp()),
+		12 => Some(Local.with_ymd_and_hms(2000 + num(0, 2)? as i32, num(2, 4)?, num(4, 6)?, num(6, 8)?, num(8, 10)?, num(10, 12)?).single()?.timestamp()),
+		_ => None,
+	}
+}
+
+///按valid_time计算过期时间并记录在消息里.已经有的不再计算,相对时间按第一次发送的时间计算
+pub fn fill_expire_time(msg: &mut JsonValue, now: i64) {
+	if !msg[EXPIRE_TIME].is_null() {
+		return;
+	}
+
+	if let Some(time) = msg[VALID_TIME].as_str().and_then(|valid_time| parse_at_time(valid_time, now)) {
+		msg[EXPIRE_TIME] = time.into();
+	}
+}
+
+///消息是否已经超过有效期
+pub fn is_expired(msg: &JsonValue, now: i64) -> bool {
+	msg[EXPIRE_TIME].as_i64().map(|time| time < now).unwrap_or(false)
+}
+
+///超过有效期的消息不再发送.发往sms.send.failure,同时给每个msg_id生成一个EXPIRED的状态报告
+pub async fn send_expired(mut msg: JsonValue) {
+	log::warn!("短信超过有效期.不再发送.msg:{}", msg);
+
+	let now = chrono::Local::now();
+	let time = now.format("%y%m%d%H%M").to_string();
+	let sender = message_sender();
+
+	for dest_id in msg[DEST_IDS].members() {
+		for msg_id in msg[MSG_IDS].members() {
+			let mut report = json::object! {msg_type: "Report"};
+			report[IS_REPORT] = true.into();
+			report[IS_SYNTHETIC] = true.into();
+			report[SRC_ID] = dest_id.clone();
+			report[DEST_ID] = msg[SRC_ID].clone();
+			report[SUBMIT_TIME] = time.as_str().into();
+			report[DONE_TIME] = time.as_str().into();
+			report[STATE] = "EXPIRED".into();
+			report[REPORT_STATUS] = EXPIRED.into();
+			report[ACCOUNT_MSG_ID] = msg_id.clone();
+			report[SERVICE_ID] = msg[SERVICE_ID].clone();
+			report[RECEIVE_TIME] = now.timestamp().into();
+			report[ENTITY_ID] = msg[ENTITY_ID].clone();
+
+			sender.send(TOPIC_TO_B_REPORT, "", report.to_string()).await;
+		}
+	}
+
+	msg[REASON] = EXPIRED.into();
+	sender.send(TOPIC_TO_B_FAILURE, "5", msg.to_string()).await;
+}
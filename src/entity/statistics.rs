@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use json::JsonValue;
+
+use crate::protocol::names::{MO_FL, MO_SCS, MO_WT, MT_FL, MT_SCS, MT_TL_MSG, MT_TL_USR, MT_WT, QUERY_CODE, QUERY_TIME, QUERY_TYPE};
+
+///估算用户数使用的寄存器个数的位数.2^10个寄存器,误差约3%
+const USER_BITS: u32 = 10;
+const USER_REGISTERS: usize = 1 << USER_BITS;
+
+///下行用户数的估算(HyperLogLog).不保存号码,占用的内存固定,号码再多也不会增长
+#[derive(Debug, Clone)]
+struct UserCounter {
+	registers: Vec<u8>,
+}
+
+impl Default for UserCounter {
+	fn default() -> Self {
+		UserCounter {
+			registers: vec![0; USER_REGISTERS],
+		}
+	}
+}
+
+impl UserCounter {
+	fn insert(&mut self, user: &str) {
+		let mut hasher = DefaultHasher::new();
+		user.hash(&mut hasher);
+		let hash = hasher.finish();
+
+		let index = (hash >> (64 - USER_BITS)) as usize;
+		let rank = ((hash << USER_BITS).leading_zeros() + 1).min(64 - USER_BITS + 1) as u8;
+		if self.registers[index] < rank {
+			self.registers[index] = rank;
+		}
+	}
+
+	///合并另外一个统计.合并后的估算值为两边用户的并集
+	fn merge(&mut self, other: &UserCounter) {
+		for (a, b) in self.registers.iter_mut().zip(other.registers.iter()) {
+			*a = (*a).max(*b);
+		}
+	}
+
+	fn count(&self) -> u32 {
+		let m = USER_REGISTERS as f64;
+		let sum: f64 = self.registers.iter().map(|v| 2f64.powi(-(*v as i32))).sum();
+		let estimate = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+
+		//数量少时按空寄存器的个数估算更准确
+		let zeros = self.registers.iter().filter(|v| **v == 0).count();
+		if estimate <= 2.5 * m && zeros > 0 {
+			(m * (m / zeros as f64).ln()).round() as u32
+		} else {
+			estimate.round() as u32
+		}
+	}
+}
+
+///单个日期单个业务类型的计数
+#[derive(Debug, Default)]
+struct Counter {
+	mt_total: u32,
+	mt_users: UserCounter,
+	mt_success: u32,
+	mt_failure: u32,
+	mo_total: u32,
+	mo_success: u32,
+	mo_failure: u32,
+}
+
+///实体的收发统计.按日期(YYYYMMDD)和业务类型分别计数.用来回复查询请求
+#[derive(Debug, Default)]
+pub struct Statistics {
+	counters: HashMap<(String, String), Counter>,
+}
+
+impl Statistics {
+	pub fn new() -> Self {
+		Statistics {
+			counters: HashMap::new(),
+		}
+	}
+
+	fn counter(&mut self, service_id: &str) -> &mut Counter {
+		let date = chrono::Local::now().format("%Y%m%d").to_string();
+
+		self.counters.entry((date, service_id.to_owned())).or_default()
+	}
+
+	///收到或者发出的下行短信.num为短信条数.用户数为估算值
+	pub fn add_mt(&mut self, service_id: &str, num: u32, dest_ids: &JsonValue) {
+		let counter = self.counter(service_id);
+
+		counter.mt_total += num;
+		for dest_id in dest_ids.members() {
+			if let Some(dest_id) = dest_id.as_str() {
+				counter.mt_users.insert(dest_id);
+			}
+		}
+	}
+
+	///下行短信的状态报告
+	pub fn add_mt_result(&mut self, service_id: &str, success: bool) {
+		let counter = self.counter(service_id);
+
+		if success {
+			counter.mt_success += 1;
+		} else {
+			counter.mt_failure += 1;
+		}
+	}
+
+	///收到或者发出的上行短信
+	pub fn add_mo(&mut self, service_id: &str) {
+		self.counter(service_id).mo_total += 1;
+	}
+
+	///上行短信的回执
+	pub fn add_mo_result(&mut self, service_id: &str, success: bool) {
+		let counter = self.counter(service_id);
+
+		if success {
+			counter.mo_success += 1;
+		} else {
+			counter.mo_failure += 1;
+		}
+	}
+
+	///清除指定天数以前的统计数据
+	pub fn clear(&mut self, keep_days: i64) {
+		let limit = (chrono::Local::now() - chrono::Duration::days(keep_days)).format("%Y%m%d").to_string();
+
+		self.counters.retain(|(date, _), _| *date >= limit);
+	}
+
+	///根据查询消息填充查询结果.query_type为0时统计全部业务类型.为1时只统计query_code对应的业务类型
+	pub fn fill_query_resp(&self, json: &mut JsonValue) {
+		let date = json[QUERY_TIME].as_str().unwrap_or("").to_owned();
+		let query_code = json[QUERY_CODE].as_str().unwrap_or("").to_owned();
+		let by_service = json[QUERY_TYPE].as_u8().unwrap_or(0) == 1;
+
+		let mut mt_total = 0u32;
+		let mut mt_users = UserCounter::default();
+		let mut mt_success = 0u32;
+		let mut mt_failure = 0u32;
+		let mut mo_total = 0u32;
+		let mut mo_success = 0u32;
+		let mut mo_failure = 0u32;
+
+		for ((counter_date, service_id), counter) in self.counters.iter() {
+			if *counter_date != date || (by_service && *service_id != query_code) {
+				continue;
+			}
+
+			mt_total += counter.mt_total;
+			mt_users.merge(&counter.mt_users);
+			mt_success += counter.mt_success;
+			mt_failure += counter.mt_failure;
+			mo_total += counter.mo_total;
+			mo_success += counter.mo_success;
+			mo_failure += counter.mo_failure;
+		}
+
+		json[MT_TL_MSG] = mt_total.into();
+		json[MT_TL_USR] = mt_users.count().into();
+		json[MT_SCS] = mt_success.into();
+		json[MT_WT] = mt_total.saturating_sub(mt_success + mt_failure).into();
+		json[MT_FL] = mt_failure.into();
+		json[MO_SCS] = mo_success.into();
+		json[MO_WT] = mo_total.saturating_sub(mo_success + mo_failure).into();
+		json[MO_FL] = mo_failure.into();
+	}
+}
@@ -0,0 +1,168 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+///未配置突发数量时.按这个时间(毫秒)内可以发送的数量作为突发数量
+const DEFAULT_BURST_MILLIS: u32 = 100;
+
+///收到超速回复后多少秒内不再降速.同一批消息的回复只降一次
+const DECREASE_INTERVAL: i64 = 2;
+///最后一次降速或者升速以后多少秒再升速
+const INCREASE_INTERVAL: i64 = 5;
+///每次升速增加配置速度的几分之一
+const INCREASE_PART: u32 = 10;
+///最低降到配置速度的几分之一
+const MIN_RATE_PART: u32 = 10;
+
+///令牌桶.一个实体的所有连接共用一个,保证连接数大于1时总的速度也不超过限制.
+///令牌按速度连续补充,最多存放突发数量个.不会在每秒开始的时候一次发完
+#[derive(Debug)]
+pub struct TokenBucket {
+	state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+	///每秒补充的令牌数
+	rate: f64,
+	///最多存放的令牌数
+	burst: f64,
+	tokens: f64,
+	last: Instant,
+}
+
+impl BucketState {
+	fn refill(&mut self) {
+		let now = Instant::now();
+		self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * self.rate).min(self.burst);
+		self.last = now;
+	}
+
+	///取num个令牌需要桶里有的数量.长短信条数大于突发数量时桶满就可以取,不足的部分后面补上
+	fn need(&self, num: u32) -> f64 {
+		(num as f64).min(self.burst).max(1.0)
+	}
+}
+
+impl TokenBucket {
+	///rate为每秒的速度.burst为0时使用默认的突发数量
+	pub fn new(rate: u32, burst: u32) -> Self {
+		let burst = if burst > 0 {
+			burst
+		} else if rate > 0 {
+			(rate * DEFAULT_BURST_MILLIS / 1000).max(1)
+		} else {
+			0
+		};
+
+		TokenBucket {
+			state: Mutex::new(BucketState {
+				rate: rate as f64,
+				burst: burst as f64,
+				tokens: burst as f64,
+				last: Instant::now(),
+			}),
+		}
+	}
+
+	///当前是否可以取num个令牌
+	pub fn has_tokens(&self, num: u32) -> bool {
+		let mut state = self.state.lock().unwrap();
+		state.refill();
+
+		state.tokens >= state.need(num)
+	}
+
+	///取num个令牌.不够时不取,返回false
+	pub fn try_take(&self, num: u32) -> bool {
+		let mut state = self.state.lock().unwrap();
+		state.refill();
+
+		if state.tokens >= state.need(num) {
+			state.tokens -= num as f64;
+			true
+		} else {
+			false
+		}
+	}
+
+	///到可以取num个令牌还需要等待的时间.速度为0时每秒再检查一次
+	pub fn wait_time(&self, num: u32) -> Duration {
+		let mut state = self.state.lock().unwrap();
+		state.refill();
+
+		if state.rate <= 0.0 {
+			return Duration::from_secs(1);
+		}
+
+		let lack = (state.need(num) - state.tokens).max(0.0);
+		Duration::from_secs_f64(lack / state.rate).max(Duration::from_millis(1))
+	}
+
+	///当前每秒的速度
+	pub fn rate(&self) -> u32 {
+		self.state.lock().unwrap().rate as u32
+	}
+
+	///修改每秒的速度.已经补充的令牌按原来的速度计算
+	pub fn set_rate(&self, rate: u32) {
+		let mut state = self.state.lock().unwrap();
+		state.refill();
+		state.rate = rate as f64;
+	}
+}
+
+///收到对端超速回复时自动调整发送速度.超速时减半,之后每隔一段时间增加一点,直到配置的速度
+#[derive(Debug)]
+pub struct AdaptiveRate {
+	///配置的速度
+	max_rate: u32,
+	///当前实际使用的速度
+	rate: u32,
+	///最后一次调整的时间
+	last_change: i64,
+	///最后一次降速的时间
+	last_decrease: i64,
+}
+
+impl AdaptiveRate {
+	pub fn new(max_rate: u32) -> Self {
+		AdaptiveRate {
+			max_rate,
+			rate: max_rate,
+			last_change: 0,
+			last_decrease: 0,
+		}
+	}
+
+	pub fn rate(&self) -> u32 {
+		self.rate
+	}
+
+	///收到超速回复.速度减半.返回速度是否有变化
+	pub fn on_speed_limit(&mut self, now: i64) -> bool {
+		if now - self.last_decrease < DECREASE_INTERVAL {
+			return false;
+		}
+
+		let rate = (self.rate / 2).max(self.max_rate / MIN_RATE_PART).max(1).min(self.max_rate);
+		self.last_decrease = now;
+		self.last_change = now;
+		if rate == self.rate {
+			return false;
+		}
+
+		self.rate = rate;
+		true
+	}
+
+	///一段时间没有超速时逐渐恢复速度.返回速度是否有变化
+	pub fn on_tick(&mut self, now: i64) -> bool {
+		if self.rate >= self.max_rate || now - self.last_change < INCREASE_INTERVAL {
+			return false;
+		}
+
+		self.rate = (self.rate + (self.max_rate / INCREASE_PART).max(1)).min(self.max_rate);
+		self.last_change = now;
+		true
+	}
+}
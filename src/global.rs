@@ -27,6 +27,8 @@ pub static TOPIC_TO_B_DELIVER_RESP: &'static str = "toB.deliver.response";
 pub static TOPIC_TO_B_REPORT: &'static str = "toB.report";
 pub static TOPIC_TO_B_REPORT_RESP: &'static str = "toB.deliver.response";
 pub static TOPIC_TO_B_FAILURE: &'static str = "sms.send.failure";
+pub static TOPIC_TO_B_QUERY_RESP: &'static str = "toB.query.response";
+pub static TOPIC_TO_B_CANCEL_RESP: &'static str = "toB.cancel.response";
 
 
 
@@ -39,6 +41,8 @@ pub static TOPIC_TO_B_LOWER_COMPUTER_INIT: &'static str = "lower.computer.init";
 pub static TOPIC_FROM_B_SUBMIT: &'static str = "send.submit";
 pub static TOPIC_FROM_B_DELIVER: &'static str = "send.deliver";
 pub static TOPIC_FROM_B_REPORT: &'static str = "send.report";
+pub static TOPIC_FROM_B_QUERY: &'static str = "send.query";
+pub static TOPIC_FROM_B_CANCEL: &'static str = "send.cancel";
 
 /// 通道指定的最大的缓冲区数量。
 pub static CHANNEL_BUFF_NUM: usize = 0xFFFFFFFF;
@@ -50,6 +54,10 @@ lazy_static! {
 	pub static ref FILL_ZERO: Vec<u8> = vec![0;200];
 	pub static ref ISMG_ID: u32 = rand::random::<u32>() % 1000000;
 	pub static ref TEMP_SAVE:RwLock<HashMap<u32,(mpsc::Sender<JsonValue>,mpsc::Sender<JsonValue>)>> = RwLock::new(HashMap::new());
+	///定时短信的通道msg_id对应的通道id和收到回执的时间.用来把删除请求发到对应通道
+	pub static ref SCHEDULED_MSG_INDEX:RwLock<HashMap<String,(u32,i64)>> = RwLock::new(HashMap::new());
+	///网关保存的定时短信的msg_id对应的实体id.用来把删除请求发到对应实体
+	pub static ref SCHEDULE_SMS_INDEX:RwLock<HashMap<String,u32>> = RwLock::new(HashMap::new());
 	// static ref SERVERS_CONFIG: RwLock<JsonValue> = RwLock::new(load_config_file("smsServer.json"));
 }
 
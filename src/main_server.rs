@@ -4,7 +4,7 @@ use std::{io, net::{IpAddr, Ipv4Addr}};
 
 use futures::{SinkExt, StreamExt};
 use json::JsonValue;
-use sms_gate::{entity::{CustomEntity}, get_runtime, global::{get_sequence_id, load_config_file}, protocol::{Cmpp48, MsgType, Protocol, ProtocolImpl, SmsStatus, cmpp32::Cmpp32, names::{DEST_IDS, ID, MSG_TYPE_STR, WAIT_RECEIPT}}};
+use sms_gate::{entity::{CustomEntity}, get_runtime, global::{get_sequence_id, load_config_file}, protocol::{Cmpp48, MsgType, Protocol, ProtocolImpl, SmsStatus, cmpp20::Cmpp20, names::{DEST_IDS, ID, MSG_TYPE_STR, WAIT_RECEIPT}}};
 use tokio::{net::{TcpListener, TcpStream}, sync::mpsc::{self, Sender}, time::{self, Duration, timeout}};
 use tokio_util::codec::Framed;
 use std::time::{Instant};
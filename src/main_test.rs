@@ -5,8 +5,8 @@ use sms_gate::entity::channel::Channel;
 use tokio::net::{TcpSocket, TcpStream};
 use sms_gate::get_runtime;
 use tokio_util::codec::Framed;
-use sms_gate::protocol::Protocol::{CMPP32, CMPP48, SMGP};
-use sms_gate::protocol::cmpp32::Cmpp32;
+use sms_gate::protocol::Protocol::{CMPP20, CMPP48, SMGP};
+use sms_gate::protocol::cmpp20::Cmpp20;
 use sms_gate::protocol::{Cmpp48,Sgip, ProtocolImpl, MsgType, SmsStatus, Protocol};
 use futures::{SinkExt, StreamExt};
 use tokio::io;
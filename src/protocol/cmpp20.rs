@@ -1,27 +1,33 @@
 use tokio_util::codec::{LengthDelimitedCodec, Decoder};
-use crate::protocol::implements::{ProtocolImpl, create_cmpp_msg_id, fill_bytes_zero, load_utf8_string, decode_msg_content, cmpp_msg_id_u64_to_str, cmpp_msg_id_str_to_u64};
+use crate::protocol::implements::{ProtocolImpl, check_len, create_cmpp_msg_id, fill_bytes_zero, load_utf8_string, decode_msg_content, cmpp_msg_id_u64_to_str, cmpp_msg_id_str_to_u64};
 use json::JsonValue;
 use bytes::{BytesMut, BufMut, Buf};
-use crate::protocol::names::{SEQ_ID, VERSION, MSG_ID, SERVICE_ID, STATE, SUBMIT_TIME, DONE_TIME, SMSC_SEQUENCE, SRC_ID, DEST_ID, SEQ_IDS, MSG_CONTENT, SP_ID, VALID_TIME, AT_TIME, DEST_IDS, MSG_TYPE_U32, RESULT, MSG_FMT, IS_REPORT, MSG_IDS};
-use crate::protocol::{MsgType, SmsStatus};
+use crate::protocol::names::{SEQ_ID, VERSION, MSG_ID, SERVICE_ID, STATE, SUBMIT_TIME, DONE_TIME, SMSC_SEQUENCE, SRC_ID, DEST_ID, SEQ_IDS, SP_ID, VALID_TIME, AT_TIME, DEST_IDS, MSG_TYPE_U32, RESULT, MSG_FMT, IS_REPORT, MSG_IDS, PASSAGE_MSG_ID, SPEED_LIMIT, PK_TOTAL, PK_NUMBER, MSG_LEVEL, FEE_USER_TYPE, CHARGE_ID, FEE_TYPE, FEE_CODE};
+use crate::protocol::{MsgType, SmsStatus, ResultCode};
 use std::io::Error;
 use crate::protocol::msg_type::MsgType::SubmitResp;
 use tokio::io;
 use crate::global::get_sequence_id;
 use crate::global::FILL_ZERO;
+use crate::protocol::cmpp48::{CMPP_HEAD_LEN, CMPP_MAX_LEN};
 
-use super::implements::encode_msg_content;
+use super::implements::{Alphabet, get_msg_content_code, get_pk, get_tp_pid, split_msg_content};
 
 ///CMPP协议2.0的处理
 #[derive(Debug, Default)]
-pub struct Cmpp32 {
+pub struct Cmpp20 {
 	version: u32,
 	codec: LengthDelimitedCodec,
 }
 
-impl ProtocolImpl for Cmpp32 {
+impl ProtocolImpl for Cmpp20 {
 	fn get_framed(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
-		self.codec.decode(buf)
+		let frame = self.codec.decode(buf);
+		self.check_frame(frame, CMPP_HEAD_LEN)
+	}
+
+	fn get_result_table(&self) -> &'static [ResultCode] {
+		CMPP20_RESULT_TABLE
 	}
 
 	///根据对方给的请求,处理以后的编码消息
@@ -103,6 +109,29 @@ impl ProtocolImpl for Cmpp32 {
 		Some(buf)
 	}
 
+	///cmpp2.0的Success_Id只有1个字节
+	fn encode_cancel_resp(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		let mut dst = BytesMut::with_capacity(13);
+
+		dst.put_u32(13);
+		dst.put_u32(self.get_type_id(MsgType::CancelResp));
+		dst.put_u32(seq_id);
+		dst.put_u8(match status {
+			SmsStatus::Success => 0,
+			_ => 1
+		});
+
+		Some(dst)
+	}
+
 	fn encode_report(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
 		//只检测一下.如果没有后续不处理.
 		let msg_id = match json[MSG_ID].as_str() {
@@ -198,23 +227,16 @@ impl ProtocolImpl for Cmpp32 {
 	}
 
 	fn encode_deliver(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
-		let msg_content = match json[MSG_CONTENT].as_str() {
-			None => {
-				log::error!("没有内容字串.退出..json:{}", json);
-				return Err(io::Error::new(io::ErrorKind::NotFound, "没有内容字串"));
-			}
-			Some(v) => v
-		};
-
 		let msg_fmt = json[MSG_FMT].as_u8().unwrap_or(8);
 		//编码以后的消息内容
-		let msg_content_code = match encode_msg_content(msg_fmt,msg_content) {
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_fmt, json, Alphabet::Ascii) {
 			Ok(v) => v,
 			Err(e) => {
 				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
 				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
 			}
 		};
+		let tp_pid = get_tp_pid(msg_fmt, json);
 
 		let service_id = match json[SERVICE_ID].as_str() {
 			Some(v) => v,
@@ -243,17 +265,17 @@ impl ProtocolImpl for Cmpp32 {
 		let msg_content_len = msg_content_code.len();
 
 		let mut msg_content_head_len: usize = 0;
-		let mut one_content_len: usize = 140;
+		//按字符集拆分以后的每一条内容
+		let contents = split_msg_content(msg_fmt, &msg_content_code, Alphabet::Ascii);
 		let mut msg_content_seq_id: u8 = 0;
 		//整个消息的长度
-		let sms_len = if msg_content_len <= 140 {
+		let sms_len = if contents.len() == 1 {
 			1 as usize
 		} else {
 			msg_content_head_len = 6;
-			one_content_len = one_content_len - 6;
 			msg_content_seq_id = get_sequence_id(1) as u8;
 
-			((msg_content_len as f32) / one_content_len as f32).ceil() as usize
+			contents.len()
 		};
 
 		//长短信的话,一次性生成多条记录
@@ -264,13 +286,7 @@ impl ProtocolImpl for Cmpp32 {
 		let mut seq_ids = Vec::with_capacity(sms_len);
 		let mut msg_ids = Vec::with_capacity(sms_len);
 
-		for i in 0..sms_len {
-			let this_msg_content = if i == sms_len - 1 {
-				&msg_content_code[(i * one_content_len)..msg_content_code.len()]
-			} else {
-				&msg_content_code[(i * one_content_len)..((i + 1) * one_content_len)]
-			};
-
+		for (i, this_msg_content) in contents.iter().enumerate() {
 			dst.put_u32((85 + msg_content_head_len + this_msg_content.len()) as u32);
 			dst.put_u32(self.get_type_id(MsgType::Deliver));
 			let seq_id = get_sequence_id(1);
@@ -282,8 +298,8 @@ impl ProtocolImpl for Cmpp32 {
 			dst.put_u64(msg_id); //Msg_Id 8
 			fill_bytes_zero(&mut dst, dest_id, 21);//dest_id 21
 			fill_bytes_zero(&mut dst, service_id, 10);//service_id 10
-			dst.put_u8(0); //TP_pid 1
-			dst.put_u8(if sms_len == 1 { 0 } else { 1 }); //tp_udhi 1
+			dst.put_u8(tp_pid); //TP_pid 1
+			dst.put_u8(if sms_len == 1 && !has_udh { 0 } else { 1 }); //tp_udhi 1
 			dst.put_u8(msg_fmt); //Msg_Fmt 1
 			fill_bytes_zero(&mut dst, src_id, 21);  //src_id 21
 			dst.put_u8(0); //Registered_Delivery 1 0 非状态报告
@@ -306,14 +322,6 @@ impl ProtocolImpl for Cmpp32 {
 	}
 
 	fn encode_submit(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
-		let msg_content = match json[MSG_CONTENT].as_str() {
-			None => {
-				log::error!("没有内容字串.退出..json:{}", json);
-				return Err(io::Error::new(io::ErrorKind::NotFound, "没有内容字串"));
-			}
-			Some(v) => v
-		};
-
 		//进行一下检测
 		if !json[MSG_IDS].is_array() {
 			log::error!("没有msg_ids.退出..json:{}", json);
@@ -322,13 +330,14 @@ impl ProtocolImpl for Cmpp32 {
 
 		let msg_fmt = json[MSG_FMT].as_u8().unwrap_or(8);
 		//编码以后的消息内容
-		let msg_content_code = match encode_msg_content(msg_fmt,msg_content) {
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_fmt, json, Alphabet::Ascii) {
 			Ok(v) => v,
 			Err(e) => {
 				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
 				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
 			}
 		};
+		let tp_pid = get_tp_pid(msg_fmt, json);
 
 		let service_id = match json[SERVICE_ID].as_str() {
 			Some(v) => v,
@@ -378,16 +387,16 @@ impl ProtocolImpl for Cmpp32 {
 		let msg_content_len = msg_content_code.len();
 
 		let mut msg_content_head_len: usize = 0;
-		let mut one_content_len: usize = 140;
+		//按字符集拆分以后的每一条内容
+		let contents = split_msg_content(msg_fmt, &msg_content_code, Alphabet::Ascii);
 		let mut msg_content_seq_id: u8 = 0;
 		//整个消息的长度
-		let sms_len = if msg_content_len <= 140 {
+		let sms_len = if contents.len() == 1 {
 			1 as usize
 		} else {
 			msg_content_head_len = 6;
-			one_content_len = one_content_len - 6;
 			msg_content_seq_id = get_sequence_id(1) as u8;
-			((msg_content_len as f32) / one_content_len as f32).ceil() as usize
+			contents.len()
 		};
 
 		//长短信的话,一次性生成多条记录
@@ -397,33 +406,35 @@ impl ProtocolImpl for Cmpp32 {
 		let mut dst = BytesMut::with_capacity(total_len);
 		let mut seq_ids = Vec::with_capacity(sms_len);
 
-		for i in 0..sms_len {
-			let this_msg_content = if i == sms_len - 1 {
-				&msg_content_code[(i * one_content_len)..msg_content_code.len()]
-			} else {
-				&msg_content_code[(i * one_content_len)..((i + 1) * one_content_len)]
-			};
+		//计费相关.没有指定时使用原来的默认值
+		let msg_level = json[MSG_LEVEL].as_u8().unwrap_or(1);
+		let fee_user_type = json[FEE_USER_TYPE].as_u8().unwrap_or(3);
+		let charge_id = json[CHARGE_ID].as_str().unwrap_or("");
+		let fee_type = json[FEE_TYPE].as_str().unwrap_or("01");
+		let fee_code = json[FEE_CODE].as_str().unwrap_or("000001");
 
+		for (i, this_msg_content) in contents.iter().enumerate() {
 			dst.put_u32((138 + dest_ids.len() * 21 + msg_content_head_len + this_msg_content.len()) as u32);
 			dst.put_u32(self.get_type_id(MsgType::Submit));
 			let seq_id = get_sequence_id(dest_ids.len() as u32);
 			seq_ids.push(seq_id);
 			dst.put_u32(seq_id);
 
+			let (pk_total, pk_number) = get_pk(json, sms_len, i);
 			dst.put_u64(0); //Msg_Id 8
-			dst.put_u8(1); //Pk_total
-			dst.put_u8(1); //Pk_number
+			dst.put_u8(pk_total); //Pk_total
+			dst.put_u8(pk_number); //Pk_number
 			dst.put_u8(1); //Registered_Delivery
-			dst.put_u8(1); //Msg_level
+			dst.put_u8(msg_level); //Msg_level
 			fill_bytes_zero(&mut dst, service_id, 10);//Service_Id
-			dst.put_u8(3); //Fee_UserType
-			dst.extend_from_slice(&FILL_ZERO[0..21]);//Fee_terminal_Id
-			dst.put_u8(0); //TP_pId
-			dst.put_u8(if sms_len == 1 { 0 } else { 1 }); //tp_udhi
+			dst.put_u8(fee_user_type); //Fee_UserType
+			fill_bytes_zero(&mut dst, charge_id, 21);//Fee_terminal_Id
+			dst.put_u8(tp_pid); //TP_pId
+			dst.put_u8(if sms_len == 1 && !has_udh { 0 } else { 1 }); //tp_udhi
 			dst.put_u8(msg_fmt); //Msg_Fmt
 			dst.extend_from_slice(sp_id[0..6].as_ref()); //sp_id
-			dst.extend_from_slice("01".as_ref()); //FeeType
-			dst.extend_from_slice("000001".as_ref()); //FeeCode
+			fill_bytes_zero(&mut dst, fee_type, 2); //FeeType
+			fill_bytes_zero(&mut dst, fee_code, 6); //FeeCode
 			fill_bytes_zero(&mut dst, valid_time, 17);  //valid_time
 			fill_bytes_zero(&mut dst, at_time, 17);  //at_time
 			fill_bytes_zero(&mut dst, src_id, 21);  //src_id
@@ -452,8 +463,14 @@ impl ProtocolImpl for Cmpp32 {
 
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
+		check_len(buf, 9)?;
 		json[MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into();//msg_id 8
-		json[RESULT] = (buf.get_u8() as u32).into();//result 1
+		let result = buf.get_u8() as u32;//result 1
+		json[RESULT] = result.into();
+
+		if self.is_speed_limit(result) {
+			json[SPEED_LIMIT] = true.into();
+		};
 
 		Ok(json)
 	}
@@ -464,6 +481,8 @@ impl ProtocolImpl for Cmpp32 {
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
 
+		//到Registered_Delivery为止的固定长度
+		check_len(buf, 64)?;
 		json[MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into(); //msg_id 8
 		json[DEST_ID] = load_utf8_string(buf, 21).into(); //dest_id 21
 		json[SERVICE_ID] = load_utf8_string(buf, 10).into(); //service_id 10
@@ -473,14 +492,17 @@ impl ProtocolImpl for Cmpp32 {
 		json[SRC_ID] = load_utf8_string(buf, 21).into(); //src_id 21
 		let is_report = buf.get_u8(); //Registered_Delivery	1
 		if is_report == 0 {
+			check_len(buf, 1)?;
 			//长短信的处理 tp_udhi != 0 说明是长短信
 			json[MSG_FMT] = msg_fmt.into(); //Msg_Fmt 1
 			let msg_content_len = buf.get_u8(); //Msg_Length	1
-			decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0)?;
+			decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0, Alphabet::Ascii)?;
 		} else {
+			//Msg_Length 1 加上状态报告的内容 56
+			check_len(buf, 57)?;
 			buf.advance(1); //Msg_Length 1
 			json[IS_REPORT] = true.into(); //状态报告增加.
-			json[MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into(); // Msg_Id
+			json[PASSAGE_MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into(); // PASSAGE_MSG_ID
 			json[STATE] = load_utf8_string(buf, 7).into(); // Stat
 			json[SUBMIT_TIME] = load_utf8_string(buf, 10).into(); // Submit_time
 			json[DONE_TIME] = load_utf8_string(buf, 10).into(); // Done_time
@@ -498,19 +520,29 @@ impl ProtocolImpl for Cmpp32 {
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
 
+		//到DestUsr_tl为止的固定长度
+		check_len(buf, 117)?;
 		json[MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into(); //msg_id 8
-		buf.advance(4); //Pk_total 1 Pk_number 1 Registered_Delivery 1 Msg_level 1
+		json[PK_TOTAL] = buf.get_u8().into(); //Pk_total 1
+		json[PK_NUMBER] = buf.get_u8().into(); //Pk_number 1
+		buf.advance(1); //Registered_Delivery 1
+		json[MSG_LEVEL] = buf.get_u8().into(); //Msg_level 1
 		json[SERVICE_ID] = load_utf8_string(buf, 10).into(); //service_id 10
-		buf.advance(23); //Fee_UserType 1  Fee_terminal_Id 21 TP_pId	1
+		json[FEE_USER_TYPE] = buf.get_u8().into(); //Fee_UserType 1
+		json[CHARGE_ID] = load_utf8_string(buf, 21).into(); //Fee_terminal_Id 21
+		buf.advance(1); //TP_pId 1
 		let tp_udhi = buf.get_u8(); //是否长短信
 		let msg_fmt = buf.get_u8();
 		json[MSG_FMT] = msg_fmt.into(); //Msg_Fmt 1
 		json[SP_ID] = load_utf8_string(buf, 6).into(); //sp_id 6
-		buf.advance(8); //FeeType	2  FeeCode	6
+		json[FEE_TYPE] = load_utf8_string(buf, 2).into(); //FeeType 2
+		json[FEE_CODE] = load_utf8_string(buf, 6).into(); //FeeCode 6
 		json[VALID_TIME] = load_utf8_string(buf, 17).into(); //valid_time 17
 		json[AT_TIME] = load_utf8_string(buf, 17).into(); //at_time 17
 		json[SRC_ID] = load_utf8_string(buf, 21).into(); //src_id 21
 		let dest_len = buf.get_u8(); //DestUsr_tl 1
+		//dest_id 21 Msg_Length 1
+		check_len(buf, dest_len as usize * 21 + 1)?;
 
 		//dest_id 21
 		let mut dest_ids: Vec<String> = Vec::new();
@@ -522,27 +554,41 @@ impl ProtocolImpl for Cmpp32 {
 
 		//长短信的处理 tp_udhi != 0 说明是长短信
 		let msg_content_len = buf.get_u8(); //Msg_Length	1
-		decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0)?;
+		decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0, Alphabet::Ascii)?;
 
 		Ok(json)
 	}
 }
 
-impl Clone for Cmpp32 {
+impl Clone for Cmpp20 {
 	fn clone(&self) -> Self {
-		Cmpp32::new()
+		Cmpp20::new()
 	}
 }
 
-impl Cmpp32 {
+impl Cmpp20 {
 	pub fn new() -> Self {
-		Cmpp32 {
+		Cmpp20 {
 			version: 32,
 			codec: LengthDelimitedCodec::builder()
 				.length_field_offset(0)
 				.length_field_length(4)
 				.length_adjustment(-4)
+				.max_frame_length(CMPP_MAX_LEN)
 				.new_codec(),
 		}
 	}
 }
+
+///CMPP2.0的submit\deliver回复result对照表.9以后为其他错误
+static CMPP20_RESULT_TABLE: &[ResultCode] = &[
+	(0, SmsStatus::Success, false, "正确"),
+	(1, SmsStatus::MessageError, false, "消息结构错"),
+	(2, SmsStatus::CommandError, false, "命令字错"),
+	(3, SmsStatus::SeqError, false, "消息序号重复"),
+	(4, SmsStatus::LengthError, false, "消息长度错"),
+	(5, SmsStatus::FeeError, false, "资费代码错"),
+	(6, SmsStatus::LengthError, false, "超过最大信息长"),
+	(7, SmsStatus::ServiceError, false, "业务代码错"),
+	(8, SmsStatus::TrafficRestrictions, true, "流量控制错"),
+];
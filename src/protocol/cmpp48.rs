@@ -3,6 +3,11 @@ use tokio_util::codec::{LengthDelimitedCodec, Decoder};
 use bytes::BytesMut;
 use tokio::io;
 
+///CMPP的消息头长度(Total_Length + Command_Id + Sequence_Id)
+pub const CMPP_HEAD_LEN: usize = 12;
+///CMPP消息的最大长度.100个接收号码的Submit也不会超过这个长度
+pub const CMPP_MAX_LEN: usize = 4096;
+
 ///CMPP协议3.0的处理
 #[derive(Debug, Default)]
 pub struct Cmpp48 {
@@ -12,7 +17,8 @@ pub struct Cmpp48 {
 
 impl ProtocolImpl for Cmpp48 {
 	fn get_framed(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>>  {
-		self.codec.decode(buf)
+		let frame = self.codec.decode(buf);
+		self.check_frame(frame, CMPP_HEAD_LEN)
 	}
 }
 
@@ -30,6 +36,7 @@ impl Cmpp48 {
 				.length_field_offset(0)
 				.length_field_length(4)
 				.length_adjustment(-4)
+				.max_frame_length(CMPP_MAX_LEN)
 				.new_codec(),
 		}
 	}
@@ -0,0 +1,994 @@
+use bytes::{Buf, BufMut, BytesMut};
+use json::JsonValue;
+use tokio::io;
+use tokio::io::Error;
+use tokio_util::codec::{Decoder, LengthDelimitedCodec};
+
+use crate::global::get_sequence_id;
+use crate::protocol::{MsgType, SmsStatus, ResultCode};
+use crate::protocol::implements::{Alphabet, create_smgp_msg_id, decode_error_json, decode_msg_content, fill_bytes_zero, get_msg_content_code, get_time, get_tp_pid, hex_to_bytes, load_utf8_string, smgp_msg_id_buf_to_str, smgp_msg_id_str_to_buf, split_msg_content, ProtocolImpl};
+use crate::protocol::names::{AT_TIME, AUTHENTICATOR, CAN_WRITE, CHARGE_ID, DEST_ID, DEST_IDS, DONE_TIME, ERROR_CODE, FEE_CODE, FEE_TYPE, FEE_USER_TYPE, IS_REPORT, LOGIN_NAME, MSG_FMT, MSG_LEVEL, MSG_ID, MSG_IDS, MSG_TYPE_STR, MSG_TYPE_U32, PASSAGE_MSG_ID, PASSWORD, RESULT, SEQ_ID, SEQ_IDS, SERVICE_ID, SMGP_RECEIVE_TIME, SPEED_LIMIT, SP_ID, SRC_ID, STATE, STATUS, SUBMIT_TIME, TIMESTAMP, TLVS, TP_PID, VALID_TIME, VERSION};
+
+///cngp 2.0的版本号
+const CNGP_VERSION: u8 = 0x20;
+///CNGP的消息头长度(Total_Length + Command_Id + Command_Status + Sequence_Id)
+const CNGP_HEAD_LEN: usize = 16;
+///CNGP消息的最大长度.100个接收号码加上TLV也不会超过这个长度
+const CNGP_MAX_LEN: usize = 4096;
+
+///CNGP(中国网通)协议2.0的处理.消息头比SMGP多一个Command_Status
+#[derive(Debug, Default)]
+pub struct Cngp {
+	version: u32,
+	length_codec: LengthDelimitedCodec,
+}
+
+impl ProtocolImpl for Cngp {
+	fn get_framed(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+		let frame = self.length_codec.decode(buf);
+		self.check_frame(frame, CNGP_HEAD_LEN)
+	}
+
+	///cngp的消息头多一个command_status.所以这里单独处理.
+	fn decode_read_msg(&mut self, buf: &mut BytesMut) -> io::Result<Option<JsonValue>> {
+		match self.get_framed(buf) {
+			Ok(Some(mut buf)) => {
+				log::trace!("收到的消息. src:{:X}", buf);
+				if buf.len() < 12 {
+					log::error!("消息长度不够消息头长度.跳过.src:{:X}", buf);
+					return Ok(None);
+				}
+
+				let tp = buf.get_u32();
+				let status = buf.get_u32();
+				let seq = buf.get_u32();
+
+				let head = buf[..buf.len().min(10)].to_vec();
+				let (msg_type, mut msg) = match self.decode_pdu(&mut buf, status, seq, tp) {
+					Ok(v) => v,
+					//解码失败的消息交给上层回复消息结构错误.不断开连接
+					Err(e) => (self.get_type_enum(tp), self.decode_error(&head, seq, tp, e)),
+				};
+
+				let msg_type_str: &str = msg_type.into();
+				msg[MSG_TYPE_STR] = msg_type_str.into();
+				buf.truncate(0);
+
+				Ok(Some(msg))
+			}
+			Ok(None) => Ok(None),
+			//分帧出错时后面的数据已经无法对齐.返回错误让上层断开连接
+			Err(e) => Err(e)
+		}
+	}
+
+	fn get_result_table(&self) -> &'static [ResultCode] {
+		CNGP_RESULT_TABLE
+	}
+
+	///解码失败时MsgID在消息体的开头.deliver_resp需要带回
+	fn decode_error(&self, head: &[u8], seq: u32, tp: u32, e: io::Error) -> JsonValue {
+		let mut json = decode_error_json(seq, tp, e);
+		if head.len() >= 10 {
+			json[MSG_ID] = smgp_msg_id_buf_to_str(&BytesMut::from(&head[..10])).into();
+		}
+
+		json
+	}
+
+	fn get_type_id(&self, t: MsgType) -> u32 {
+		match t {
+			MsgType::Connect => 0x00000001,
+			MsgType::ConnectResp => 0x80000001,
+			MsgType::Submit => 0x00000002,
+			MsgType::SubmitResp => 0x80000002,
+			MsgType::Deliver => 0x00000003,
+			MsgType::DeliverResp => 0x80000003,
+			//cngp的状态报告也是使用deliver
+			MsgType::Report => 0x00000003,
+			MsgType::ReportResp => 0x80000003,
+			MsgType::ActiveTest => 0x00000004,
+			MsgType::ActiveTestResp => 0x80000004,
+			MsgType::Terminate => 0x00000006,
+			MsgType::TerminateResp => 0x80000006,
+			_ => 0,
+		}
+	}
+
+	fn get_type_enum(&self, v: u32) -> MsgType {
+		match v {
+			0x00000001 => MsgType::Connect,
+			0x80000001 => MsgType::ConnectResp,
+			0x00000002 => MsgType::Submit,
+			0x80000002 => MsgType::SubmitResp,
+			0x00000003 => MsgType::Deliver,
+			0x80000003 => MsgType::DeliverResp,
+			0x00000004 => MsgType::ActiveTest,
+			0x80000004 => MsgType::ActiveTestResp,
+			0x00000006 => MsgType::Terminate,
+			0x80000006 => MsgType::TerminateResp,
+			_ => MsgType::UNKNOWN,
+		}
+	}
+
+	fn get_status_id(&self, status: &SmsStatus) -> u32 {
+		match status {
+			SmsStatus::Success => 0,
+			SmsStatus::MessageError => 10, //消息结构错
+			SmsStatus::AddError => 20, //IP地址错
+			SmsStatus::AuthError => 21, //认证错
+			SmsStatus::VersionError => 22, //版本太高
+			SmsStatus::TrafficRestrictions => 1, //系统忙
+			SmsStatus::OtherError => 2, //超过最大连接数
+			SmsStatus::UNKNOWN => 99,
+			_ => self.get_result_id(status).unwrap_or(2),
+		}
+	}
+
+	fn get_status_enum(&self, v: u32) -> SmsStatus {
+		match v {
+			0 => SmsStatus::Success,
+			10..=12 => SmsStatus::MessageError,
+			20 => SmsStatus::AddError,
+			21 => SmsStatus::AuthError,
+			22 => SmsStatus::VersionError,
+			1 => SmsStatus::TrafficRestrictions,
+			_ => SmsStatus::OtherError
+		}
+	}
+
+	///AuthenticatorClient = MD5(ClientID + 7字节的0 + shared secret + timestamp)
+	/// timestamp格式为：MMDDHHMMSS，即月日时分秒，10位。
+	fn get_auth(&self, client_id: &str, password: &str, timestamp: u32) -> [u8; 16] {
+		let time_str = format!("{:010}", timestamp);
+
+		let mut src = BytesMut::with_capacity(client_id.len() + 7 + password.len() + 10);
+
+		src.put_slice(client_id.as_bytes());
+		src.put_slice(&[0u8; 7]);
+		src.put_slice(password.as_bytes());
+		src.put_slice(time_str.as_bytes());
+
+		md5::compute(&src[..]).0
+	}
+
+	///生成登录操作的消息
+	fn encode_connect(&self, json: &mut JsonValue) -> Result<BytesMut, io::Error> {
+		let client_id = match json[LOGIN_NAME].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有login_name.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有login_name"));
+			}
+		};
+
+		let password = match json[PASSWORD].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有password.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有password"));
+			}
+		};
+
+		let version = json[VERSION].as_u8().unwrap_or(self.version as u8);
+		let time = get_time();
+
+		let mut dst = BytesMut::with_capacity(48);
+		dst.put_u32(48);
+		dst.put_u32(self.get_type_id(MsgType::Connect));
+		dst.put_u32(0);
+		dst.put_u32(get_sequence_id(1));
+
+		fill_bytes_zero(&mut dst, client_id, 10); //ClientID 10
+		dst.extend_from_slice(self.get_auth(client_id, password, time).as_ref()); //AuthenticatorClient 16
+		dst.put_u8(2); //LoginMode 1 2:收发消息
+		dst.put_u32(time); //TimeStamp 4
+		dst.put_u8(version); //Version 1
+
+		Ok(dst)
+	}
+
+	fn encode_connect_rep(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		let mut dst = BytesMut::with_capacity(33);
+		dst.put_u32(33);
+		dst.put_u32(self.get_type_id(MsgType::ConnectResp));
+		dst.put_u32(self.get_status_id(&status));
+		dst.put_u32(seq_id);
+
+		dst.put_slice(&[0u8; 16]); //AuthenticatorServer 16
+		dst.put_u8(CNGP_VERSION); //Version 1
+
+		Some(dst)
+	}
+
+	fn encode_submit_resp(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		let msg_id = create_smgp_msg_id(json[DEST_IDS].len().max(1) as u32);
+		json[MSG_ID] = smgp_msg_id_buf_to_str(&msg_id).into();
+
+		Some(self.encode_resp_with_msg_id(MsgType::SubmitResp, status, seq_id, &msg_id))
+	}
+
+	fn encode_report_resp(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		self.encode_deliver_resp(status, json)
+	}
+
+	fn encode_deliver_resp(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		let msg_id = match json[MSG_ID].as_str() {
+			None => {
+				log::error!("没有msg_id字串.退出..json:{}", json);
+				return None;
+			}
+			Some(v) => smgp_msg_id_str_to_buf(v)
+		};
+
+		Some(self.encode_resp_with_msg_id(MsgType::DeliverResp, status, seq_id, &msg_id))
+	}
+
+	fn encode_active_test_resp(&self, _status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		Some(self.encode_nobody(MsgType::ActiveTestResp, 0, seq_id))
+	}
+
+	fn encode_active_test(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let seq_id = get_sequence_id(1);
+		json[SEQ_ID] = seq_id.into();
+
+		Ok(self.encode_nobody(MsgType::ActiveTest, 0, seq_id))
+	}
+
+	///对方发来的退出请求.回复以后再关闭
+	fn encode_terminate_resp(&self, _status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		Some(self.encode_nobody(MsgType::TerminateResp, 0, seq_id))
+	}
+
+	fn encode_terminate(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let seq_id = get_sequence_id(1);
+		json[SEQ_ID] = seq_id.into();
+
+		Ok(self.encode_nobody(MsgType::Terminate, 0, seq_id))
+	}
+
+	///状态报告使用deliver发送.IsReport为1.内容格式和SMGP一样
+	fn encode_report(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let msg_id = match json[MSG_ID].as_str() {
+			None => {
+				log::error!("没有msg_id字串.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有msg_id字串"));
+			}
+			Some(v) => smgp_msg_id_str_to_buf(v)
+		};
+
+		let stat = match json[STATE].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有stat.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有stat"));
+			}
+		};
+
+		let submit_time = match json[SUBMIT_TIME].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有submit_time.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有submit_time"));
+			}
+		};
+
+		let done_time = match json[DONE_TIME].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有done_time.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有done_time"));
+			}
+		};
+
+		let src_id = match json[SRC_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有src_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有src_id"));
+			}
+		};
+
+		let dest_id = match json[DEST_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有dest_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有dest_id"));
+			}
+		};
+
+		let receive_time = match json[SMGP_RECEIVE_TIME].as_str() {
+			Some(v) => v.to_owned(),
+			None => chrono::Local::now().format("%Y%m%d%H%M%S").to_string()
+		};
+
+		let error_code = match &json[ERROR_CODE] {
+			JsonValue::Number(_) => format!("{:03}", json[ERROR_CODE].as_u32().unwrap_or(0)),
+			v => v.as_str().unwrap_or("000").to_owned()
+		};
+
+		//状态报告长度固定
+		let mut dst = BytesMut::with_capacity(186);
+
+		dst.put_u32(186);
+		dst.put_u32(self.get_type_id(MsgType::Deliver));
+		dst.put_u32(0);
+		let seq_id = get_sequence_id(1);
+		dst.put_u32(seq_id);
+
+		dst.put(create_smgp_msg_id(1)); //MsgID 10
+		dst.put_u8(1); //IsReport
+		dst.put_u8(0); //MsgFormat
+		fill_bytes_zero(&mut dst, receive_time.as_str(), 14); //RecvTime 14
+		fill_bytes_zero(&mut dst, src_id, 21); //SrcTermID 21
+		fill_bytes_zero(&mut dst, dest_id, 21); //DestTermID 21
+
+		// id:XXXXXXXXXX sub:001 dlvrd:001 Submit_Date:0901151559 done_date:0901151559 stat:DELIVRD err:000 Txt:
+		dst.put_u8(101); //MsgLength 状态报告长度固定
+		dst.extend_from_slice("id:".as_bytes());
+		dst.put(msg_id); //10位的msg_id
+		dst.extend_from_slice(" sub:001 dlvrd:001 Submit_Date:".as_bytes());
+		fill_bytes_zero(&mut dst, submit_time, 10); //submit_time 10
+		dst.extend_from_slice(" done_date:".as_bytes());
+		fill_bytes_zero(&mut dst, done_time, 10); //done_time 10
+		dst.extend_from_slice(" stat:".as_bytes());
+		fill_bytes_zero(&mut dst, stat, 7); //Stat 7
+		dst.extend_from_slice(" err:".as_bytes());
+		fill_bytes_zero(&mut dst, error_code.as_str(), 3); //err 3
+		dst.extend_from_slice(" Txt:".as_bytes());
+
+		json[SEQ_IDS] = vec![seq_id].into();
+
+		Ok(dst)
+	}
+
+	fn encode_deliver(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let msg_fmt = json[MSG_FMT].as_u8().unwrap_or(8);
+		//编码以后的消息内容
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_fmt, json, Alphabet::Ascii) {
+			Ok(v) => v,
+			Err(e) => {
+				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
+				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
+			}
+		};
+
+		let src_id = match json[SRC_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有src_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有src_id"));
+			}
+		};
+
+		let dest_id = match json[DEST_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有dest_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有dest_id"));
+			}
+		};
+
+		let receive_time = match json[SMGP_RECEIVE_TIME].as_str() {
+			Some(v) => v.to_owned(),
+			None => chrono::Local::now().format("%Y%m%d%H%M%S").to_string()
+		};
+
+		let contents = split_cngp_content(msg_fmt, &msg_content_code);
+		let tlv_buf = encode_tlvs(msg_fmt, json, has_udh || contents.len() > 1);
+
+		//85是除开内容	lv之后所有长度加在一起
+		let one_len = 85 + tlv_buf.len();
+		let mut dst = BytesMut::with_capacity(contents.len() * (one_len + 6) + msg_content_code.len());
+		let mut seq_ids = Vec::with_capacity(contents.len());
+		let mut msg_ids = Vec::with_capacity(contents.len());
+
+		for content in contents.iter() {
+			dst.put_u32((one_len + content.len()) as u32);
+			dst.put_u32(self.get_type_id(MsgType::Deliver));
+			dst.put_u32(0);
+			let seq_id = get_sequence_id(1);
+			seq_ids.push(seq_id);
+			dst.put_u32(seq_id);
+
+			let msg_id = create_smgp_msg_id(1);
+			msg_ids.push(smgp_msg_id_buf_to_str(&msg_id));
+			dst.put(msg_id); //MsgID 10
+			dst.put_u8(0); //IsReport
+			dst.put_u8(msg_fmt); //MsgFormat
+			fill_bytes_zero(&mut dst, receive_time.as_str(), 14); //RecvTime 14
+			fill_bytes_zero(&mut dst, src_id, 21); //SrcTermID 21
+			fill_bytes_zero(&mut dst, dest_id, 21); //DestTermID 21
+			dst.put_u8(content.len() as u8); //MsgLength
+			dst.extend_from_slice(content); //MsgContent
+			dst.extend_from_slice(&tlv_buf);
+		}
+
+		json[SEQ_IDS] = seq_ids.into();
+		Ok(dst)
+	}
+
+	fn encode_submit(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		//进行一下检测
+		if !json[MSG_IDS].is_array() {
+			log::error!("没有msg_ids.退出..json:{}", json);
+			return Err(io::Error::new(io::ErrorKind::NotFound, "没有msg_ids"));
+		};
+
+		let msg_fmt = json[MSG_FMT].as_u8().unwrap_or(8);
+
+		//编码以后的消息内容
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_fmt, json, Alphabet::Ascii) {
+			Ok(v) => v,
+			Err(e) => {
+				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
+				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
+			}
+		};
+
+		let sp_id = match json[SP_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有sp_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有sp_id"));
+			}
+		};
+
+		let service_id = match json[SERVICE_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有service_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有service_id"));
+			}
+		};
+
+		let src_id = match json[SRC_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有src_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有src_id"));
+			}
+		};
+
+		let dest_ids = if json[DEST_IDS].is_array() && !json[DEST_IDS].is_empty() {
+			let mut dest_ids = Vec::with_capacity(json[DEST_IDS].len());
+			json[DEST_IDS].members().for_each(|item| dest_ids.push(item.as_str().unwrap_or("")));
+
+			dest_ids
+		} else {
+			log::error!("没有dest_ids.退出..json:{}", json);
+			return Err(io::Error::new(io::ErrorKind::NotFound, "没有dest_ids"));
+		};
+
+		let valid_time = json[VALID_TIME].as_str().unwrap_or("");
+		let at_time = json[AT_TIME].as_str().unwrap_or("");
+
+		//计费相关字段.没有时使用原来的默认值
+		let msg_level = json[MSG_LEVEL].as_u8().unwrap_or(1);
+		let fee_type = json[FEE_TYPE].as_str().unwrap_or("00");
+		let fee_user_type = json[FEE_USER_TYPE].as_u8().unwrap_or(0);
+		let fee_code = json[FEE_CODE].as_str().unwrap_or("000000");
+		let charge_id = json[CHARGE_ID].as_str().unwrap_or("");
+
+		let contents = split_cngp_content(msg_fmt, &msg_content_code);
+		let tlv_buf = encode_tlvs(msg_fmt, json, has_udh || contents.len() > 1);
+
+		//127是除开内容\接收号码\tlv之后所有长度加在一起
+		let one_len = 127 + dest_ids.len() * 21 + tlv_buf.len();
+		let mut dst = BytesMut::with_capacity(contents.len() * (one_len + 6) + msg_content_code.len());
+		let mut seq_ids = Vec::with_capacity(contents.len());
+
+		for content in contents.iter() {
+			dst.put_u32((one_len + content.len()) as u32);
+			dst.put_u32(self.get_type_id(MsgType::Submit));
+			dst.put_u32(0);
+			let seq_id = get_sequence_id(dest_ids.len() as u32);
+			seq_ids.push(seq_id);
+			dst.put_u32(seq_id);
+
+			fill_bytes_zero(&mut dst, sp_id, 10); //SPID 10
+			dst.put_u8(2); //SubType 2:点播下发
+			dst.put_u8(1); //NeedReport
+			dst.put_u8(msg_level); //Priority
+			fill_bytes_zero(&mut dst, service_id, 10); //ServiceID 10
+			fill_bytes_zero(&mut dst, fee_type, 2); //FeeType 2
+			dst.put_u8(fee_user_type); //FeeUserType 1
+			fill_bytes_zero(&mut dst, fee_code, 6); //FeeCode 6
+			dst.put_u8(msg_fmt); //MsgFormat
+			fill_bytes_zero(&mut dst, valid_time, 17); //ValidTime 17
+			fill_bytes_zero(&mut dst, at_time, 17); //AtTime 17
+			fill_bytes_zero(&mut dst, src_id, 21); //SrcTermID 21
+			fill_bytes_zero(&mut dst, charge_id, 21); //ChargeTermID 21
+			dst.put_u8(dest_ids.len() as u8); //DestTermIDCount
+			dest_ids.iter().for_each(|dest_id| fill_bytes_zero(&mut dst, dest_id, 21)); //DestTermID 21
+			dst.put_u8(content.len() as u8); //MsgLength
+			dst.extend_from_slice(content); //MsgContent
+			dst.extend_from_slice(&tlv_buf);
+		}
+
+		json[SEQ_IDS] = seq_ids.into();
+		Ok(dst)
+	}
+
+	fn decode_connect_resp(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		//登录失败的时候可能没有消息体
+		if buf.len() >= 17 {
+			buf.advance(16); //AuthenticatorServer 16
+			json[VERSION] = (buf.get_u8() as u32).into();
+		}
+
+		Ok(json)
+	}
+
+	fn decode_submit_or_deliver_resp(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		if buf.len() >= 10 {
+			json[MSG_ID] = smgp_msg_id_buf_to_str(&buf.split_to(10)).into(); //MsgID 10
+		}
+
+		for tlv in decode_tlvs(buf) {
+			if let CngpTLV::CongestionState(v) = tlv {
+				log::debug!("对端拥塞状态:{}.seq:{}", v, seq);
+			}
+		}
+
+		Ok(json)
+	}
+
+	fn decode_deliver(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//MsgID 10 IsReport 1 MsgFormat 1 RecvTime 14 SrcTermID 21 DestTermID 21 MsgLength 1
+		if buf.len() < 69 {
+			log::warn!("消息结构出错.没有足够的长度读消息.");
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "没有足够的长度读消息."));
+		}
+
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		json[MSG_ID] = smgp_msg_id_buf_to_str(&buf.split_to(10)).into(); //MsgID 10
+		let is_report = buf.get_u8(); //IsReport 1
+		let msg_fmt = buf.get_u8(); //MsgFormat 1
+		json[SMGP_RECEIVE_TIME] = load_utf8_string(buf, 14).into(); //RecvTime 14
+		json[SRC_ID] = load_utf8_string(buf, 21).into(); //SrcTermID 21
+		json[DEST_ID] = load_utf8_string(buf, 21).into(); //DestTermID 21
+
+		let msg_content_len = buf.get_u8() as usize; //MsgLength 1
+		if msg_content_len > buf.len() {
+			log::warn!("消息结构出错.没有足够的长度读消息.");
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "没有足够的长度读消息."));
+		}
+		let mut content_buf = buf.split_to(msg_content_len); //MsgContent
+		let udhi = put_tlvs_to_json(decode_tlvs(buf), &mut json);
+
+		if is_report == 0 {
+			json[MSG_FMT] = msg_fmt.into();
+			let is_long_sms = udhi.unwrap_or_else(|| is_long_sms_content(&content_buf));
+			decode_msg_content(&mut content_buf, msg_fmt, msg_content_len as u8, &mut json, is_long_sms, Alphabet::Ascii)?;
+		} else {
+			// id:XXXXXXXXXX sub:001 dlvrd:001 Submit_Date:0901151559 done_date:0901151559 stat:DELIVRD err:000 Txt:
+			if content_buf.len() < 96 {
+				log::warn!("状态报告结构出错.没有足够的长度读消息.");
+				return Err(io::Error::new(io::ErrorKind::InvalidData, "状态报告没有足够的长度."));
+			}
+
+			json[IS_REPORT] = true.into();
+
+			content_buf.advance(3); //"id:"
+			json[PASSAGE_MSG_ID] = smgp_msg_id_buf_to_str(&content_buf.split_to(10)).into();
+			content_buf.advance(31); //" sub:001 dlvrd:001 Submit_Date:"
+			json[SUBMIT_TIME] = load_utf8_string(&mut content_buf, 10).into();
+			content_buf.advance(11); //" done_date:"
+			json[DONE_TIME] = load_utf8_string(&mut content_buf, 10).into();
+			content_buf.advance(6); //" stat:"
+			json[STATE] = load_utf8_string(&mut content_buf, 7).into();
+			content_buf.advance(5); //" err:"
+			json[ERROR_CODE] = load_utf8_string(&mut content_buf, 3).into();
+
+			//是状态报告.修改一下返回的类型.
+			json[MSG_TYPE_U32] = self.get_type_id(MsgType::Report).into();
+		}
+
+		Ok(json)
+	}
+
+	fn decode_submit(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//除去接收号码和内容的固定长度
+		if buf.len() < 111 {
+			log::warn!("消息结构出错.没有足够的长度读消息.");
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "没有足够的长度读消息."));
+		}
+
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		json[SP_ID] = load_utf8_string(buf, 10).into(); //SPID 10
+		buf.advance(2); //SubType 1 NeedReport 1
+		json[MSG_LEVEL] = buf.get_u8().into(); //Priority 1
+		json[SERVICE_ID] = load_utf8_string(buf, 10).into(); //ServiceID 10
+		json[FEE_TYPE] = load_utf8_string(buf, 2).into(); //FeeType 2
+		json[FEE_USER_TYPE] = buf.get_u8().into(); //FeeUserType 1
+		json[FEE_CODE] = load_utf8_string(buf, 6).into(); //FeeCode 6
+		let msg_fmt = buf.get_u8();
+		json[MSG_FMT] = msg_fmt.into(); //MsgFormat 1
+		json[VALID_TIME] = load_utf8_string(buf, 17).into(); //ValidTime 17
+		json[AT_TIME] = load_utf8_string(buf, 17).into(); //AtTime 17
+		json[SRC_ID] = load_utf8_string(buf, 21).into(); //SrcTermID 21
+		json[CHARGE_ID] = load_utf8_string(buf, 21).into(); //ChargeTermID 21
+		let dest_len = buf.get_u8() as usize; //DestTermIDCount 1
+
+		if buf.len() < dest_len * 21 + 1 {
+			log::warn!("消息结构出错.没有足够的长度读接收号码.");
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "没有足够的长度读接收号码."));
+		}
+
+		let mut dest_ids: Vec<String> = Vec::with_capacity(dest_len);
+		for _ in 0..dest_len {
+			dest_ids.push(load_utf8_string(buf, 21)); //DestTermID 21
+		}
+		json[DEST_IDS] = dest_ids.into();
+
+		let msg_content_len = buf.get_u8() as usize; //MsgLength 1
+		if msg_content_len > buf.len() {
+			log::warn!("消息结构出错.没有足够的长度读消息.");
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "没有足够的长度读消息."));
+		}
+		let mut content_buf = buf.split_to(msg_content_len); //MsgContent
+		let udhi = put_tlvs_to_json(decode_tlvs(buf), &mut json);
+
+		let is_long_sms = udhi.unwrap_or_else(|| is_long_sms_content(&content_buf));
+		decode_msg_content(&mut content_buf, msg_fmt, msg_content_len as u8, &mut json, is_long_sms, Alphabet::Ascii)?;
+
+		Ok(json)
+	}
+
+	///实际的解码连接消息
+	fn decode_connect(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//ClientID 10 AuthenticatorClient 16 LoginMode 1 TimeStamp 4 Version 1
+		if buf.len() < 32 {
+			log::warn!("登录消息结构出错.len:{}", buf.len());
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "登录消息长度不足."));
+		}
+
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		json[LOGIN_NAME] = load_utf8_string(buf, 10).into();
+		//现在取2次,相当于只保存8位.
+		json[AUTHENTICATOR] = buf.get_u64().into();
+		json[AUTHENTICATOR] = buf.get_u64().into();
+		//0:只发送短信.不能往这个连接上写消息
+		json[CAN_WRITE] = (buf.get_u8() != 0).into();
+		json[TIMESTAMP] = buf.get_u32().into();
+		json[VERSION] = (buf.get_u8() as u32).into();
+
+		Ok(json)
+	}
+}
+
+impl Clone for Cngp {
+	fn clone(&self) -> Self {
+		Cngp::new()
+	}
+}
+
+impl Cngp {
+	pub fn new() -> Self {
+		Cngp {
+			version: CNGP_VERSION as u32,
+			length_codec: LengthDelimitedCodec::builder()
+				.length_field_offset(0)
+				.length_field_length(4)
+				.length_adjustment(-4)
+				.max_frame_length(CNGP_MAX_LEN)
+				.new_codec(),
+		}
+	}
+
+	///按消息类型解码消息体.status为消息头里的command_status.返回实际的消息类型(状态报告和上行使用同一个command)
+	fn decode_pdu(&self, buf: &mut BytesMut, status: u32, seq: u32, tp: u32) -> io::Result<(MsgType, JsonValue)> {
+		let mut msg_type = self.get_type_enum(tp);
+		let msg = match msg_type {
+			MsgType::Connect => self.decode_connect(buf, seq, tp)?,
+			MsgType::ConnectResp => {
+				let mut json = self.decode_connect_resp(buf, seq, tp)?;
+				json[STATUS] = status.into();
+
+				json
+			}
+			MsgType::Submit => self.decode_submit(buf, seq, tp)?,
+			MsgType::SubmitResp | MsgType::DeliverResp => {
+				let mut json = self.decode_submit_or_deliver_resp(buf, seq, tp)?;
+				//cngp的状态是放在消息头里的
+				json[RESULT] = status.into();
+				if self.is_speed_limit(status) {
+					json[SPEED_LIMIT] = true.into();
+				};
+
+				json
+			}
+			MsgType::Deliver => {
+				let json = self.decode_deliver(buf, seq, tp)?;
+				if let Some(true) = json[IS_REPORT].as_bool() {
+					msg_type = MsgType::Report;
+				}
+
+				json
+			}
+			MsgType::ActiveTest | MsgType::ActiveTestResp => self.decode_nobody(buf, seq, tp)?,
+			MsgType::Terminate | MsgType::TerminateResp => self.decode_nobody(buf, seq, tp)?,
+			_ => {
+				log::warn!("收到未支持的命令.command_id:{:X}.seq:{}", tp, seq);
+				return Err(io::Error::new(io::ErrorKind::Other, "还未实现"));
+			}
+		};
+
+		Ok((msg_type, msg))
+	}
+
+	///只有消息头的消息
+	fn encode_nobody(&self, t: MsgType, status: u32, seq_id: u32) -> BytesMut {
+		let mut dst = BytesMut::with_capacity(16);
+		dst.put_u32(16);
+		dst.put_u32(self.get_type_id(t));
+		dst.put_u32(status);
+		dst.put_u32(seq_id);
+
+		dst
+	}
+
+	///submit_resp和deliver_resp.只有MsgID
+	fn encode_resp_with_msg_id(&self, t: MsgType, status: SmsStatus, seq_id: u32, msg_id: &[u8]) -> BytesMut {
+		let mut dst = BytesMut::with_capacity(26);
+		dst.put_u32(26);
+		dst.put_u32(self.get_type_id(t));
+		dst.put_u32(self.get_status_id(&status));
+		dst.put_u32(seq_id);
+		fill_msg_id(&mut dst, msg_id);
+
+		dst
+	}
+}
+
+///写入10位的MsgID.不够的补0
+fn fill_msg_id(dst: &mut BytesMut, msg_id: &[u8]) {
+	let len = msg_id.len().min(10);
+	dst.extend_from_slice(&msg_id[0..len]);
+	dst.extend_from_slice(&[0u8; 10][len..]);
+}
+
+///拆分短信内容.超过单条长度的使用UDH拆分成多条
+fn split_cngp_content(msg_fmt: u8, msg_content_code: &[u8]) -> Vec<Vec<u8>> {
+	let chunks = split_msg_content(msg_fmt, msg_content_code, Alphabet::Ascii);
+	if chunks.len() == 1 {
+		return vec![msg_content_code.to_vec()];
+	}
+
+	let total = chunks.len() as u8;
+	let msg_content_seq_id = get_sequence_id(1) as u8;
+
+	chunks.iter().enumerate().map(|(i, chunk)| {
+		let mut content = Vec::with_capacity(6 + chunk.len());
+		content.extend_from_slice(&[5, 0, 3, msg_content_seq_id, total, (i + 1) as u8]);
+		content.extend_from_slice(chunk);
+
+		content
+	}).collect()
+}
+
+///对端没有发送TP_udhi时使用.根据内容是否以长短信的UDH开头猜测是否为长短信.
+///普通内容正好以这几个字节开头时会判断错误,所以只作为没有TP_udhi时的补充
+fn is_long_sms_content(content: &[u8]) -> bool {
+	if content.len() < 6 {
+		return false;
+	}
+
+	match (content[0], content[1], content[2]) {
+		(5, 0, 3) => content[4] > 1 && content[5] >= 1 && content[5] <= content[4],
+		(6, 8, 4) => content.len() >= 7 && content[5] > 1 && content[6] >= 1 && content[6] <= content[5],
+		_ => false
+	}
+}
+
+///读取剩下的所有tlv.
+fn decode_tlvs(buf: &mut BytesMut) -> Vec<CngpTLV> {
+	let mut tlvs = Vec::new();
+
+	//tag和length共4位.值可以为空
+	while buf.len() >= 4 {
+		let tag = buf.get_u16();
+		let length = buf.get_u16() as usize;
+		if buf.len() < length {
+			log::error!("解码tlv失败..没有足够的长度.tag:{:X}", tag);
+			break;
+		}
+
+		tlvs.push(CngpTLV::decode(tag, buf.split_to(length)));
+	}
+
+	tlvs
+}
+
+///把收到的tlv放入json.返回TP_udhi的值,对端没有发送时为None.没有单独字段的tlv以16进制放入tlvs里面
+fn put_tlvs_to_json(tlvs: Vec<CngpTLV>, json: &mut JsonValue) -> Option<bool> {
+	let mut udhi = None;
+	for tlv in tlvs.into_iter() {
+		match tlv {
+			CngpTLV::TPUdhi(v) => udhi = Some(v == 1),
+			CngpTLV::TPPid(v) => {
+				if v != 0 {
+					json[TP_PID] = v.into();
+				}
+			}
+			_ => json[TLVS][format!("{:04X}", tlv.get_u16())] = format!("{:X}", tlv.value()).into(),
+		}
+	}
+
+	udhi
+}
+
+///编码需要发送的tlv.每一条都一样.二进制短信需要的TP_pid,带有UDH时的TP_udhi,以及json里面tlvs指定的
+fn encode_tlvs(msg_fmt: u8, json: &JsonValue, has_udh: bool) -> BytesMut {
+	let mut tlvs = Vec::new();
+
+	let tp_pid = get_tp_pid(msg_fmt, json);
+	if tp_pid != 0 {
+		tlvs.push(CngpTLV::TPPid(tp_pid));
+	}
+
+	if has_udh {
+		tlvs.push(CngpTLV::TPUdhi(1));
+	}
+
+	for (tag, value) in json[TLVS].entries() {
+		match (u16::from_str_radix(tag, 16), value.as_str().and_then(hex_to_bytes)) {
+			(Ok(tag), Some(value)) => tlvs.push(CngpTLV::Unknown(tag, BytesMut::from(&value[..]))),
+			_ => log::warn!("tlv格式错误.跳过.tag:{}.value:{}", tag, value),
+		}
+	}
+
+	let mut buf = BytesMut::new();
+	tlvs.iter().for_each(|tlv| tlv.encode(&mut buf));
+
+	buf
+}
+
+///cngp的可选参数.TP_pid和TP_udhi使用和SMGP 3.0相同的tag
+#[derive(Debug)]
+enum CngpTLV {
+	TPPid(u8),
+	TPUdhi(u8),
+	///GSM协议类型
+	Protocol(u8),
+	///网关拥塞状态.0-100
+	CongestionState(u8),
+	///未识别的tlv.原样保存
+	Unknown(u16, BytesMut),
+}
+
+impl CngpTLV {
+	fn get_u16(&self) -> u16 {
+		match self {
+			CngpTLV::TPPid(_) => 0x0001,
+			CngpTLV::TPUdhi(_) => 0x0002,
+			CngpTLV::Protocol(_) => 0x0100,
+			CngpTLV::CongestionState(_) => 0x0428,
+			CngpTLV::Unknown(tag, _) => *tag,
+		}
+	}
+
+	fn value(&self) -> BytesMut {
+		let mut value = BytesMut::new();
+		match self {
+			CngpTLV::TPPid(v) |
+			CngpTLV::TPUdhi(v) |
+			CngpTLV::Protocol(v) |
+			CngpTLV::CongestionState(v) => value.put_u8(*v),
+			CngpTLV::Unknown(_, v) => value.extend_from_slice(v),
+		}
+
+		value
+	}
+
+	fn encode(&self, buf: &mut BytesMut) {
+		let value = self.value();
+		buf.put_u16(self.get_u16());
+		buf.put_u16(value.len() as u16);
+		buf.extend_from_slice(&value);
+	}
+
+	///长度不对的按未识别的处理
+	fn decode(tag: u16, value: BytesMut) -> Self {
+		match (tag, value.len()) {
+			(0x0001, 1) => CngpTLV::TPPid(value[0]),
+			(0x0002, 1) => CngpTLV::TPUdhi(value[0]),
+			(0x0100, 1) => CngpTLV::Protocol(value[0]),
+			(0x0428, 1) => CngpTLV::CongestionState(value[0]),
+			_ => CngpTLV::Unknown(tag, value),
+		}
+	}
+}
+
+///CNGP的回复Status对照表.1系统忙按流量控制处理
+static CNGP_RESULT_TABLE: &[ResultCode] = &[
+	(0, SmsStatus::Success, false, "成功"),
+	(1, SmsStatus::TrafficRestrictions, true, "系统忙"),
+	(2, SmsStatus::SystemBusy, true, "超过最大连接数"),
+	(10, SmsStatus::MessageError, false, "消息结构错"),
+	(11, SmsStatus::CommandError, false, "命令字错"),
+	(12, SmsStatus::SeqError, false, "序列号重复"),
+	(20, SmsStatus::AddError, false, "IP地址错"),
+	(21, SmsStatus::AuthError, false, "认证错"),
+	(22, SmsStatus::VersionError, false, "版本太高"),
+	(30, SmsStatus::MessageError, false, "非法消息类型"),
+	(31, SmsStatus::MessageError, false, "非法优先级"),
+	(32, SmsStatus::FeeError, false, "非法资费类型"),
+	(33, SmsStatus::FeeError, false, "非法资费代码"),
+	(34, SmsStatus::MessageError, false, "非法短消息格式"),
+	(35, SmsStatus::MessageError, false, "非法时间格式"),
+	(36, SmsStatus::LengthError, false, "非法短消息长度"),
+	(37, SmsStatus::OtherError, false, "有效期已过"),
+	(38, SmsStatus::MessageError, false, "非法查询类别"),
+	(39, SmsStatus::OtherError, false, "路由错误"),
+	(40, SmsStatus::FeeError, false, "非法包月费/封顶费"),
+	(41, SmsStatus::MessageError, false, "非法更新类型"),
+	(42, SmsStatus::MessageError, false, "非法路由编号"),
+	(43, SmsStatus::ServiceError, false, "非法服务代码"),
+	(44, SmsStatus::MessageError, false, "非法有效期"),
+	(45, SmsStatus::MessageError, false, "非法定时发送时间"),
+	(46, SmsStatus::SrcIdError, false, "非法发送用户号码"),
+	(47, SmsStatus::DestIdError, false, "非法接收用户号码"),
+	(48, SmsStatus::FeeTermError, false, "非法计费用户号码"),
+	(49, SmsStatus::ServiceError, false, "非法SP服务代码"),
+];
@@ -0,0 +1,193 @@
+use tokio::io;
+
+///GSM 03.38的扩展字符的转义符
+const ESCAPE: u8 = 0x1B;
+
+///单条短信可以放的septet个数
+pub const ONE_LEN: usize = 160;
+///拆分后每一条短信可以放的septet个数.前面的UDH占7个septet
+pub const PART_LEN: usize = 153;
+
+///GSM 03.38的默认字母表.下标为septet的值
+const BASIC_TABLE: [char; 128] = [
+	'@', '£', '$', '¥', 'è', 'é', 'ù', 'ì', 'ò', 'Ç', '\n', 'Ø', 'ø', '\r', 'Å', 'å',
+	'Δ', '_', 'Φ', 'Γ', 'Λ', 'Ω', 'Π', 'Ψ', 'Σ', 'Θ', 'Ξ', '\u{1B}', 'Æ', 'æ', 'ß', 'É',
+	' ', '!', '"', '#', '¤', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/',
+	'0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?',
+	'¡', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+	'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'Ä', 'Ö', 'Ñ', 'Ü', '§',
+	'¿', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+	'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'ä', 'ö', 'ñ', 'ü', 'à',
+];
+
+///GSM 03.38的扩展表.需要在前面加上转义符.每个字符占2个septet
+const EXTENSION_TABLE: [(u8, char); 10] = [
+	(0x0A, '\u{0C}'),
+	(0x14, '^'),
+	(0x28, '{'),
+	(0x29, '}'),
+	(0x2F, '\\'),
+	(0x3C, '['),
+	(0x3D, '~'),
+	(0x3E, ']'),
+	(0x40, '|'),
+	(0x65, '€'),
+];
+
+fn basic_septet(c: char) -> Option<u8> {
+	//转义符不能当做普通字符编码
+	if c == '\u{1B}' {
+		return None;
+	}
+
+	BASIC_TABLE.iter().position(|v| *v == c).map(|v| v as u8)
+}
+
+fn extension_septet(c: char) -> Option<u8> {
+	EXTENSION_TABLE.iter().find(|(_, v)| *v == c).map(|(k, _)| *k)
+}
+
+///判断字串是否都可以使用GSM 7bit编码
+pub fn can_encode(content: &str) -> bool {
+	content.chars().all(|c| basic_septet(c).is_some() || extension_septet(c).is_some())
+}
+
+///字串使用GSM 7bit编码以后的septet个数.扩展字符占2个
+pub fn septet_len(content: &str) -> usize {
+	content.chars().map(|c| if extension_septet(c).is_some() { 2 } else { 1 }).sum()
+}
+
+///把字串编码成septet.每个septet占一个字节(不压缩).
+pub fn encode(content: &str) -> Result<Vec<u8>, io::Error> {
+	let mut result = Vec::with_capacity(content.len());
+
+	for c in content.chars() {
+		if let Some(v) = basic_septet(c) {
+			result.push(v);
+		} else if let Some(v) = extension_septet(c) {
+			result.push(ESCAPE);
+			result.push(v);
+		} else {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("GSM 7bit字符集不支持的字符:{}", c)));
+		}
+	}
+
+	Ok(result)
+}
+
+///把每个字节一个septet的内容解码成字串.未知的扩展字符按默认字母表处理
+pub fn decode(septets: &[u8]) -> Result<String, io::Error> {
+	let mut result = String::with_capacity(septets.len());
+	let mut iter = septets.iter();
+
+	while let Some(&v) = iter.next() {
+		if v > 0x7F {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, format!("不是GSM 7bit字符:{:X}", v)));
+		}
+
+		if v != ESCAPE {
+			result.push(BASIC_TABLE[v as usize]);
+			continue;
+		}
+
+		match iter.next() {
+			Some(&ext) if ext <= 0x7F => {
+				match EXTENSION_TABLE.iter().find(|(k, _)| *k == ext) {
+					Some((_, c)) => result.push(*c),
+					None if ext == ESCAPE => result.push(' '),
+					None => result.push(BASIC_TABLE[ext as usize]),
+				}
+			}
+			Some(&ext) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("不是GSM 7bit字符:{:X}", ext))),
+			//最后一个转义符没有后续字符.直接丢弃
+			None => {}
+		}
+	}
+
+	Ok(result)
+}
+
+///把septet压缩成7bit的字节流.fill_bits为前面需要填充的位数(有UDH时用来对齐)
+pub fn pack(septets: &[u8], fill_bits: usize) -> Vec<u8> {
+	let total_bits = fill_bits + septets.len() * 7;
+	let mut result = vec![0u8; total_bits.div_ceil(8)];
+
+	for (i, septet) in septets.iter().enumerate() {
+		let septet = septet & 0x7F;
+		let bit_pos = fill_bits + i * 7;
+		let index = bit_pos / 8;
+		let shift = bit_pos % 8;
+
+		result[index] |= septet << shift;
+		if shift > 1 {
+			result[index + 1] |= septet >> (8 - shift);
+		}
+	}
+
+	result
+}
+
+///把7bit压缩的字节流解压成每个字节一个septet.septet_count为septet的个数
+pub fn unpack(buf: &[u8], septet_count: usize, fill_bits: usize) -> Vec<u8> {
+	let mut result = Vec::with_capacity(septet_count);
+
+	for i in 0..septet_count {
+		let bit_pos = fill_bits + i * 7;
+		let index = bit_pos / 8;
+		let shift = bit_pos % 8;
+		if index >= buf.len() {
+			break;
+		}
+
+		let mut septet = buf[index] >> shift;
+		if shift > 1 && index + 1 < buf.len() {
+			septet |= buf[index + 1] << (8 - shift);
+		}
+
+		result.push(septet & 0x7F);
+	}
+
+	result
+}
+
+///UDH后面需要填充的位数.让第一个septet从7bit的边界开始
+pub fn fill_bits(udh_len: usize) -> usize {
+	(7 - udh_len * 8 % 7) % 7
+}
+
+///7bit压缩的字节流里面的septet个数.
+///最后剩下正好7个0位时无法区分是填充还是'@'.按填充处理,GSM 03.38建议这时发送方用CR填充
+pub fn septet_count(buf: &[u8], fill_bits: usize) -> usize {
+	let total_bits = (buf.len() * 8).saturating_sub(fill_bits);
+	let count = total_bits / 7;
+
+	if count > 0 && total_bits % 7 == 0 && buf.last().map(|v| v >> 1) == Some(0) {
+		count - 1
+	} else {
+		count
+	}
+}
+
+///按septet个数拆分.转义符和后面的扩展字符要在同一条里
+pub fn split(septets: &[u8], part_len: usize) -> Vec<&[u8]> {
+	let mut result = Vec::with_capacity(septets.len() / part_len + 1);
+	let mut rest = septets;
+
+	while rest.len() > part_len {
+		let mut len = part_len;
+		let escapes = rest[0..len].iter().rev().take_while(|v| **v == ESCAPE).count();
+		if escapes % 2 == 1 {
+			len -= 1;
+		}
+
+		let (this, other) = rest.split_at(len);
+		result.push(this);
+		rest = other;
+	}
+
+	if !rest.is_empty() || result.is_empty() {
+		result.push(rest);
+	}
+
+	result
+}
@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use bytes::{BytesMut, Buf, Bytes, BufMut};
 use encoding::all::{UTF_16BE, GBK};
 use encoding::{Encoding, DecoderTrap, EncoderTrap};
@@ -10,9 +12,10 @@ use crate::global::ISMG_ID;
 use crate::global::FILL_ZERO;
 use crate::protocol::msg_type::MsgType::{Connect, SubmitResp};
 use tokio::io::Error;
-use crate::protocol::msg_type::SmsStatus;
-use crate::protocol::names::{SEQ_ID, PASSAGE_MSG_ID, AUTHENTICATOR, VERSION, STATUS, MSG_TYPE_U32, MSG_CONTENT, MSG_ID, SERVICE_ID, TP_UDHI, SP_ID, VALID_TIME, AT_TIME, SRC_ID, MSG_FMT, DEST_IDS, RESULT, DEST_ID, STATE, SUBMIT_TIME, DONE_TIME, SMSC_SEQUENCE, IS_REPORT, MSG_TYPE_STR, LONG_SMS_TOTAL, LONG_SMS_NOW_NUMBER, SEQ_IDS, LOGIN_NAME, PASSWORD, TIMESTAMP, MSG_IDS};
+use crate::protocol::msg_type::{SmsStatus, ResultCode};
+use crate::protocol::names::{SEQ_ID, PASSAGE_MSG_ID, AUTHENTICATOR, VERSION, STATUS, MSG_TYPE_U32, MSG_CONTENT, MSG_ID, SERVICE_ID, TP_UDHI, SP_ID, VALID_TIME, AT_TIME, SRC_ID, MSG_FMT, DEST_IDS, RESULT, DEST_ID, STATE, SUBMIT_TIME, DONE_TIME, SMSC_SEQUENCE, IS_REPORT, MSG_TYPE_STR, LONG_SMS_TOTAL, LONG_SMS_NOW_NUMBER, SEQ_IDS, LOGIN_NAME, PASSWORD, TIMESTAMP, MSG_IDS, QUERY_TIME, QUERY_TYPE, QUERY_CODE, MT_TL_MSG, MT_TL_USR, MT_SCS, MT_WT, MT_FL, MO_SCS, MO_WT, MO_FL, MSG_HEX, UDH_HEX, TP_PID, LONG_SMS_REF, SRC_PORT, DEST_PORT, DECODE_ERROR, PK_TOTAL, PK_NUMBER, MSG_LEVEL, FEE_USER_TYPE, CHARGE_ID, FEE_TERM_TYPE, DEST_TERM_TYPE, FEE_TYPE, FEE_CODE};
 use crate::protocol::MsgType;
+use crate::protocol::gsm7;
 
 use super::names::SPEED_LIMIT;
 
@@ -26,55 +29,21 @@ pub trait ProtocolImpl: Send + Sync {
 			//这里直接用
 			Ok(Some(mut buf)) => {
 				log::trace!("收到的消息. src:{:X}", buf);
+				if buf.len() < 8 {
+					log::error!("消息长度不够消息头长度.跳过.src:{:X}", buf);
+					return Ok(None);
+				}
+
 				//拿command
 				let tp = buf.get_u32();
 				//拿掉seq.对sgip来说.这里只是拿了node_id.还有2个没拿
 				let seq = buf.get_u32();
 
-				let mut msg_type = self.get_type_enum(tp);
-				let mut msg = match msg_type {
-					MsgType::Submit => self.decode_submit(&mut buf, seq, tp)?,
-					MsgType::DeliverResp => self.decode_submit_or_deliver_resp(&mut buf, seq, tp)?,
-					MsgType::SubmitResp => self.decode_submit_or_deliver_resp(&mut buf, seq, tp)?,
-					MsgType::Deliver => {
-						let json = self.decode_deliver(&mut buf, seq, tp)?;
-						if let Some(true) = json[IS_REPORT].as_bool() {
-							msg_type = MsgType::Report;
-						}
-
-						json
-					}
-					MsgType::ActiveTest | MsgType::ActiveTestResp => self.decode_nobody(&mut buf, seq, tp)?,
-					MsgType::Connect => self.decode_connect(&mut buf, seq, tp)?,
-					MsgType::ConnectResp => self.decode_connect_resp(&mut buf, seq, tp)?,
-					MsgType::Terminate | MsgType::TerminateResp => self.decode_nobody(&mut buf, seq, tp)?,
-					MsgType::Report => self.decode_report(&mut buf, seq, tp)?,
-					MsgType::ReportResp => self.decode_report_resp(&mut buf, seq, tp)?,
-					MsgType::Query |
-					MsgType::QueryResp |
-					MsgType::Cancel |
-					MsgType::CancelResp |
-					MsgType::Fwd |
-					MsgType::FwdResp |
-					MsgType::MtRoute |
-					MsgType::MtRouteResp |
-					MsgType::MoRoute |
-					MsgType::MoRouteResp |
-					MsgType::GetMtRoute |
-					MsgType::GetMtRouteResp |
-					MsgType::MtRouteUpdate |
-					MsgType::MtRouteUpdateResp |
-					MsgType::MoRouteUpdate |
-					MsgType::MoRouteUpdateResp |
-					MsgType::PushMtRouteUpdate |
-					MsgType::PushMtRouteUpdateResp |
-					MsgType::PushMoRouteUpdate |
-					MsgType::PushMoRouteUpdateResp |
-					MsgType::GetMoRoute |
-					MsgType::UNKNOWN |
-					MsgType::GetMoRouteResp =>
-						return Err(io::Error::new(io::ErrorKind::Other, "还未实现")),
-					// _ => return Err(io::Error::new(io::ErrorKind::NotFound, "type没值,或者无法转换")),
+				let head = buf[..buf.len().min(10)].to_vec();
+				let (msg_type, mut msg) = match self.decode_body(&mut buf, seq, tp) {
+					Ok(v) => v,
+					//解码失败的消息交给上层回复消息结构错误.不断开连接
+					Err(e) => (self.get_type_enum(tp), self.decode_error(&head, seq, tp, e)),
 				};
 
 				//这句是把对应的消息转成字符串用来处理。因为id每一个协议不一样。而且枚举在json结构里面又不支持。
@@ -85,11 +54,92 @@ pub trait ProtocolImpl: Send + Sync {
 				Ok(Some(msg))
 			}
 			Ok(None) => Ok(None),
-			//当出现异常时这里直接返回空.让上层应用不进行处理.
-			Err(_) => Ok(None)
+			//分帧出错时后面的数据已经无法对齐.返回错误让上层断开连接
+			Err(e) => Err(e)
 		}
 	}
 
+	///按消息类型解码消息体.返回实际的消息类型(状态报告和上行使用同一个command)
+	fn decode_body(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> io::Result<(MsgType, JsonValue)> {
+		let mut msg_type = self.get_type_enum(tp);
+		let msg = match msg_type {
+			MsgType::Submit => self.decode_submit(buf, seq, tp)?,
+			MsgType::DeliverResp => self.decode_submit_or_deliver_resp(buf, seq, tp)?,
+			MsgType::SubmitResp => self.decode_submit_or_deliver_resp(buf, seq, tp)?,
+			MsgType::Deliver => {
+				let json = self.decode_deliver(buf, seq, tp)?;
+				if let Some(true) = json[IS_REPORT].as_bool() {
+					msg_type = MsgType::Report;
+				}
+
+				json
+			}
+			MsgType::ActiveTest | MsgType::ActiveTestResp => self.decode_nobody(buf, seq, tp)?,
+			MsgType::Connect => self.decode_connect(buf, seq, tp)?,
+			MsgType::ConnectResp => self.decode_connect_resp(buf, seq, tp)?,
+			MsgType::Terminate | MsgType::TerminateResp => self.decode_nobody(buf, seq, tp)?,
+			MsgType::Report => self.decode_report(buf, seq, tp)?,
+			MsgType::ReportResp => self.decode_report_resp(buf, seq, tp)?,
+			MsgType::Query => self.decode_query(buf, seq, tp)?,
+			MsgType::QueryResp => self.decode_query_resp(buf, seq, tp)?,
+			MsgType::Cancel => self.decode_cancel(buf, seq, tp)?,
+			MsgType::CancelResp => self.decode_cancel_resp(buf, seq, tp)?,
+			MsgType::Fwd |
+			MsgType::FwdResp |
+			MsgType::MtRoute |
+			MsgType::MtRouteResp |
+			MsgType::MoRoute |
+			MsgType::MoRouteResp |
+			MsgType::GetMtRoute |
+			MsgType::GetMtRouteResp |
+			MsgType::MtRouteUpdate |
+			MsgType::MtRouteUpdateResp |
+			MsgType::MoRouteUpdate |
+			MsgType::MoRouteUpdateResp |
+			MsgType::PushMtRouteUpdate |
+			MsgType::PushMtRouteUpdateResp |
+			MsgType::PushMoRouteUpdate |
+			MsgType::PushMoRouteUpdateResp |
+			MsgType::GetMoRoute |
+			MsgType::GenericNack |
+			MsgType::UNKNOWN |
+			MsgType::GetMoRouteResp =>
+				return Err(io::Error::new(io::ErrorKind::Other, "还未实现")),
+			// _ => return Err(io::Error::new(io::ErrorKind::NotFound, "type没值,或者无法转换")),
+		};
+
+		Ok((msg_type, msg))
+	}
+
+	///解码失败时生成的消息.带有decode_error,用来回复消息结构错误.head为消息体开头的最多10个字节,用来取得回复需要的msg_id
+	fn decode_error(&self, head: &[u8], seq: u32, tp: u32, e: io::Error) -> JsonValue {
+		let mut json = decode_error_json(seq, tp, e);
+		if head.len() >= 8 {
+			let mut msg_id = [0u8; 8];
+			msg_id.copy_from_slice(&head[..8]);
+			json[MSG_ID] = cmpp_msg_id_u64_to_str(u64::from_be_bytes(msg_id)).into();
+		}
+
+		json
+	}
+
+	///检查分帧的结果.frame为分帧器的返回值,不包含开头4个字节的长度.head_len为包含长度字段的消息头长度.
+	///消息超过最大长度(分帧器返回错误)、不够消息头长度或者命令不认识时记录原因并返回错误,连接会被断开
+	fn check_frame(&self, frame: io::Result<Option<BytesMut>>, head_len: usize) -> io::Result<Option<BytesMut>> {
+		let frame = match check_frame_len(frame, head_len)? {
+			Some(frame) => frame,
+			None => return Ok(None),
+		};
+
+		let tp = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]);
+		if self.get_type_enum(tp) == MsgType::UNKNOWN {
+			log::error!("收到不认识的命令.断开连接.command_id:{:#010X}", tp);
+			return Err(frame_error(FrameError::UnknownCommand, format!("command_id:{:#010X}", tp)));
+		}
+
+		Ok(Some(frame))
+	}
+
 	fn get_type_id(&self, t: MsgType) -> u32 {
 		match t {
 			MsgType::Submit => 0x00000004,
@@ -126,6 +176,7 @@ pub trait ProtocolImpl: Send + Sync {
 			MsgType::PushMoRouteUpdateResp => 0x80000016,
 			MsgType::GetMoRoute => 0x00000017,
 			MsgType::GetMoRouteResp => 0x80000017,
+			MsgType::GenericNack => 0,
 			MsgType::UNKNOWN => 0,
 		}
 	}
@@ -181,6 +232,7 @@ pub trait ProtocolImpl: Send + Sync {
 			SmsStatus::TrafficRestrictions => 8,
 			SmsStatus::OtherError => 5,
 			SmsStatus::UNKNOWN => 999,
+			_ => self.get_result_id(status).unwrap_or(5),
 		}
 	}
 
@@ -197,6 +249,24 @@ pub trait ProtocolImpl: Send + Sync {
 		}
 	}
 
+	///submit\deliver\report回复的result对照表
+	fn get_result_table(&self) -> &'static [ResultCode] {
+		CMPP_RESULT_TABLE
+	}
+
+	///按result取得统一状态和是否可以重发.对照表里没有的为UNKNOWN,不重发
+	fn get_result_enum(&self, code: u32) -> (SmsStatus, bool) {
+		match self.get_result_table().iter().find(|item| item.0 == code) {
+			Some(item) => (item.1, item.2),
+			None => (SmsStatus::UNKNOWN, false),
+		}
+	}
+
+	///按统一状态取得回复使用的result.取对照表里第一个对应的值
+	fn get_result_id(&self, status: &SmsStatus) -> Option<u32> {
+		self.get_result_table().iter().find(|item| item.1 == *status).map(|item| item.0)
+	}
+
 	///通过给定的账号密码。计算实际向客户发送的消息，也是用来进行校验密码是否正确。
 	fn get_auth(&self, sp_id: &str, password: &str, timestamp: u32) -> [u8; 16] {
 		let time_str = format!("{:010}", timestamp);
@@ -403,6 +473,11 @@ pub trait ProtocolImpl: Send + Sync {
 		Ok(dst)
 	}
 
+	///对方发来的断开请求的回执.默认不回复.
+	fn encode_terminate_resp(&self, _status: SmsStatus, _json: &mut JsonValue) -> Option<BytesMut> {
+		None
+	}
+
 	fn encode_terminate(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
 		let mut dst = BytesMut::with_capacity(12);
 
@@ -415,6 +490,102 @@ pub trait ProtocolImpl: Send + Sync {
 		Ok(dst)
 	}
 
+	///向对端发送查询请求.
+	fn encode_query(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let time = match json[QUERY_TIME].as_str() {
+			Some(v) => v.to_owned(),
+			None => Local::now().format("%Y%m%d").to_string()
+		};
+
+		let query_type = json[QUERY_TYPE].as_u8().unwrap_or(0);
+		let query_code = json[QUERY_CODE].as_str().unwrap_or("");
+
+		let mut dst = BytesMut::with_capacity(39);
+
+		dst.put_u32(39);
+		dst.put_u32(self.get_type_id(MsgType::Query));
+		let seq_id = get_sequence_id(1);
+		dst.put_u32(seq_id);
+		fill_bytes_zero(&mut dst, time.as_str(), 8); //Time 8
+		dst.put_u8(query_type); //Query_Type 1
+		fill_bytes_zero(&mut dst, query_code, 10); //Query_Code 10
+		fill_bytes_zero(&mut dst, "", 8); //Reserve 8
+
+		json[QUERY_TIME] = time.into();
+		json[SEQ_ID] = seq_id.into();
+		Ok(dst)
+	}
+
+	///对查询请求的回复.统计数据由实体填充以后送过来
+	fn encode_query_resp(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有seq_id"));
+			}
+		};
+
+		let mut dst = BytesMut::with_capacity(63);
+
+		dst.put_u32(63);
+		dst.put_u32(self.get_type_id(MsgType::QueryResp));
+		dst.put_u32(seq_id);
+		fill_bytes_zero(&mut dst, json[QUERY_TIME].as_str().unwrap_or(""), 8); //Time 8
+		dst.put_u8(json[QUERY_TYPE].as_u8().unwrap_or(0)); //Query_Type 1
+		fill_bytes_zero(&mut dst, json[QUERY_CODE].as_str().unwrap_or(""), 10); //Query_Code 10
+		for name in [MT_TL_MSG, MT_TL_USR, MT_SCS, MT_WT, MT_FL, MO_SCS, MO_WT, MO_FL].iter() {
+			dst.put_u32(json[*name].as_u32().unwrap_or(0));
+		}
+
+		Ok(dst)
+	}
+
+	///删除已经提交但还未下发的短信.使用提交回执里收到的msg_id
+	fn encode_cancel(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let msg_id = match json[PASSAGE_MSG_ID].as_str() {
+			None => {
+				log::error!("没有passage_msg_id字串.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有passage_msg_id字串"));
+			}
+			Some(v) => cmpp_msg_id_str_to_u64(v)
+		};
+
+		let mut dst = BytesMut::with_capacity(20);
+
+		dst.put_u32(20);
+		dst.put_u32(self.get_type_id(MsgType::Cancel));
+		let seq_id = get_sequence_id(1);
+		dst.put_u32(seq_id);
+		dst.put_u64(msg_id);
+
+		json[SEQ_ID] = seq_id.into();
+		Ok(dst)
+	}
+
+	///删除请求的回复.Success_Id 0:成功 1:失败
+	fn encode_cancel_resp(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		let mut dst = BytesMut::with_capacity(16);
+
+		dst.put_u32(16);
+		dst.put_u32(self.get_type_id(MsgType::CancelResp));
+		dst.put_u32(seq_id);
+		dst.put_u32(match status {
+			SmsStatus::Success => 0,
+			_ => 1
+		});
+
+		Some(dst)
+	}
+
 	fn encode_report(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
 		let msg_id = match json[MSG_ID].as_str() {
 			None => {
@@ -517,23 +688,16 @@ pub trait ProtocolImpl: Send + Sync {
 			return Err(io::Error::new(io::ErrorKind::NotFound, "没有msg_id"));
 		};
 
-		let msg_content = match json[MSG_CONTENT].as_str() {
-			None => {
-				log::error!("没有内容字串.退出..json:{}", json);
-				return Err(io::Error::new(io::ErrorKind::NotFound, "没有内容字串"));
-			}
-			Some(v) => v
-		};
-
 		let msg_fmt = json[MSG_FMT].as_u8().unwrap_or(8);
 		//编码以后的消息内容
-		let msg_content_code = match encode_msg_content(msg_fmt,msg_content) {
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_fmt, json, Alphabet::Ascii) {
 			Ok(v) => v,
 			Err(e) => {
 				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
 				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
 			}
 		};
+		let tp_pid = get_tp_pid(msg_fmt, json);
 
 		let service_id = match json[SERVICE_ID].as_str() {
 			Some(v) => v,
@@ -562,17 +726,17 @@ pub trait ProtocolImpl: Send + Sync {
 		let msg_content_len = msg_content_code.len();
 
 		let mut msg_content_head_len: usize = 0;
-		let mut one_content_len: usize = 140;
+		//按字符集拆分以后的每一条内容
+		let contents = split_msg_content(msg_fmt, &msg_content_code, Alphabet::Ascii);
 		let mut msg_content_seq_id: u8 = 0;
 		//整个消息的长度
-		let sms_len = if msg_content_len <= 140 {
+		let sms_len = if contents.len() == 1 {
 			1 as usize
 		} else {
 			msg_content_head_len = 6;
-			one_content_len = one_content_len - 6;
 			msg_content_seq_id = get_sequence_id(1) as u8;
 
-			((msg_content_len as f32) / one_content_len as f32).ceil() as usize
+			contents.len()
 		};
 
 		//长短信的话,一次性生成多条记录
@@ -583,13 +747,7 @@ pub trait ProtocolImpl: Send + Sync {
 		let mut seq_ids = Vec::with_capacity(sms_len);
 		let mut msg_ids = Vec::with_capacity(sms_len);
 
-		for i in 0..sms_len {
-			let this_msg_content = if i == sms_len - 1 {
-				&msg_content_code[(i * one_content_len)..msg_content_code.len()]
-			} else {
-				&msg_content_code[(i * one_content_len)..((i + 1) * one_content_len)]
-			};
-
+		for (i, this_msg_content) in contents.iter().enumerate() {
 			dst.put_u32((109 + msg_content_head_len + this_msg_content.len()) as u32);
 			dst.put_u32(self.get_type_id(MsgType::Deliver));
 			let seq_id = get_sequence_id(1);
@@ -602,8 +760,8 @@ pub trait ProtocolImpl: Send + Sync {
 
 			fill_bytes_zero(&mut dst, dest_id, 21);//dest_id 21
 			fill_bytes_zero(&mut dst, service_id, 10);//service_id 10
-			dst.put_u8(0); //TP_pid 1
-			dst.put_u8(if sms_len == 1 { 0 } else { 1 }); //tp_udhi 1
+			dst.put_u8(tp_pid); //TP_pid 1
+			dst.put_u8(if sms_len == 1 && !has_udh { 0 } else { 1 }); //tp_udhi 1
 			dst.put_u8(msg_fmt); //Msg_Fmt 1
 			fill_bytes_zero(&mut dst, src_id, 32);  //src_id 32
 			dst.put_u8(0); //Src_terminal_type 1
@@ -627,23 +785,16 @@ pub trait ProtocolImpl: Send + Sync {
 	}
 
 	fn encode_submit(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
-		let msg_content = match json[MSG_CONTENT].as_str() {
-			None => {
-				log::error!("没有内容字串.退出..json:{}", json);
-				return Err(io::Error::new(io::ErrorKind::NotFound, "没有内容字串"));
-			}
-			Some(v) => v
-		};
-
 		let msg_fmt = json[MSG_FMT].as_u8().unwrap_or(8);
 		//编码以后的消息内容
-		let msg_content_code = match encode_msg_content(msg_fmt,msg_content) {
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_fmt, json, Alphabet::Ascii) {
 			Ok(v) => v,
 			Err(e) => {
 				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
 				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
 			}
 		};
+		let tp_pid = get_tp_pid(msg_fmt, json);
 
 		let service_id = match json[SERVICE_ID].as_str() {
 			Some(v) => v,
@@ -695,19 +846,28 @@ pub trait ProtocolImpl: Send + Sync {
 			return Err(io::Error::new(io::ErrorKind::NotFound, "没有msg_ids"));
 		};
 
+		//计费相关.没有指定时使用原来的默认值
+		let msg_level = json[MSG_LEVEL].as_u8().unwrap_or(1);
+		let fee_user_type = json[FEE_USER_TYPE].as_u8().unwrap_or(3);
+		let charge_id = json[CHARGE_ID].as_str().unwrap_or("");
+		let fee_term_type = json[FEE_TERM_TYPE].as_u8().unwrap_or(0);
+		let dest_term_type = json[DEST_TERM_TYPE].as_u8().unwrap_or(0);
+		let fee_type = json[FEE_TYPE].as_str().unwrap_or("01");
+		let fee_code = json[FEE_CODE].as_str().unwrap_or("000001");
+
 		let msg_content_len = msg_content_code.len();
 
 		let mut msg_content_head_len: usize = 0;
-		let mut one_content_len: usize = 140;
+		//按字符集拆分以后的每一条内容
+		let contents = split_msg_content(msg_fmt, &msg_content_code, Alphabet::Ascii);
 		let mut msg_content_seq_id: u8 = 0;
 		//整个消息的长度
-		let sms_len = if msg_content_len <= 140 {
+		let sms_len = if contents.len() == 1 {
 			1 as usize
 		} else {
 			msg_content_head_len = 6;
-			one_content_len = one_content_len - 6;
 			msg_content_seq_id = get_sequence_id(1) as u8;
-			((msg_content_len as f32) / one_content_len as f32).ceil() as usize
+			contents.len()
 		};
 
 		//长短信的话,一次性生成多条记录
@@ -718,41 +878,36 @@ pub trait ProtocolImpl: Send + Sync {
 		let mut dst = BytesMut::with_capacity(total_len);
 		let mut seq_ids = Vec::with_capacity(sms_len);
 
-		for i in 0..sms_len {
-			let this_msg_content = if i == sms_len - 1 {
-				&msg_content_code[(i * one_content_len)..msg_content_code.len()]
-			} else {
-				&msg_content_code[(i * one_content_len)..((i + 1) * one_content_len)]
-			};
-
+		for (i, this_msg_content) in contents.iter().enumerate() {
 			dst.put_u32((163 + dest_ids.len() * 32 + msg_content_head_len + this_msg_content.len()) as u32);
 			dst.put_u32(self.get_type_id(MsgType::Submit));
 			let seq_id = get_sequence_id(dest_ids.len() as u32);
 			seq_ids.push(seq_id);
 			dst.put_u32(seq_id);
 
+			let (pk_total, pk_number) = get_pk(json, sms_len, i);
 			dst.put_u64(0); //Msg_Id 8
-			dst.put_u8(1); //Pk_total
-			dst.put_u8(1); //Pk_number
+			dst.put_u8(pk_total); //Pk_total
+			dst.put_u8(pk_number); //Pk_number
 			dst.put_u8(1); //Registered_Delivery
-			dst.put_u8(1); //Msg_level
+			dst.put_u8(msg_level); //Msg_level
 			fill_bytes_zero(&mut dst, service_id, 10);//Service_Id
-			dst.put_u8(3); //Fee_UserType
-			dst.extend_from_slice(&FILL_ZERO[0..32]);//Fee_terminal_Id
-			dst.put_u8(0); //Fee_terminal_type
-			dst.put_u8(0); //TP_pId
-			dst.put_u8(if sms_len == 1 { 0 } else { 1 }); //tp_udhi
+			dst.put_u8(fee_user_type); //Fee_UserType
+			fill_bytes_zero(&mut dst, charge_id, 32);//Fee_terminal_Id
+			dst.put_u8(fee_term_type); //Fee_terminal_type
+			dst.put_u8(tp_pid); //TP_pId
+			dst.put_u8(if sms_len == 1 && !has_udh { 0 } else { 1 }); //tp_udhi
 			dst.put_u8(msg_fmt); //Msg_Fmt
 			dst.extend_from_slice(sp_id[0..6].as_ref()); //sp_id
-			dst.extend_from_slice("01".as_ref()); //FeeType
-			dst.extend_from_slice("000001".as_ref()); //FeeCode
+			fill_bytes_zero(&mut dst, fee_type, 2); //FeeType
+			fill_bytes_zero(&mut dst, fee_code, 6); //FeeCode
 			fill_bytes_zero(&mut dst, valid_time, 17);  //valid_time
 			fill_bytes_zero(&mut dst, at_time, 17);  //at_time
 			fill_bytes_zero(&mut dst, src_id, 21);  //src_id
 			dst.put_u8(dest_ids.len() as u8); //DestUsr_tl
 			dest_ids.iter().for_each(|dest_id| fill_bytes_zero(&mut dst, dest_id, 32));  //dest_id
 
-			dst.put_u8(0); //Dest_terminal_type
+			dst.put_u8(dest_term_type); //Dest_terminal_type
 			dst.put_u8(this_msg_content.len() as u8 + msg_content_head_len as u8); //Msg_Length
 			if msg_content_head_len > 0 {
 				dst.put_u8(5);
@@ -777,6 +932,9 @@ pub trait ProtocolImpl: Send + Sync {
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
 
+		//cmpp2.0是18.cmpp3.0是21
+		check_len(buf, 18)?;
+
 		//21是已经减掉头的长度.cmpp3.0是21
 		if buf.len() < 21 {
 			//cmpp 2.0
@@ -795,6 +953,7 @@ pub trait ProtocolImpl: Send + Sync {
 
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
+		check_len(buf, 12)?;
 		json[MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into();//msg_id 8
 		let result = buf.get_u32();//result 4
 		json[RESULT] = result.into();
@@ -806,8 +965,8 @@ pub trait ProtocolImpl: Send + Sync {
 		Ok(json)
 	}
 
-	fn is_speed_limit(&self, code: u32) -> bool{
-		return code == 8
+	fn is_speed_limit(&self, code: u32) -> bool {
+		self.get_result_enum(code).0 == SmsStatus::TrafficRestrictions
 	}
 
 	fn decode_nobody(&self, _buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
@@ -818,6 +977,72 @@ pub trait ProtocolImpl: Send + Sync {
 		Ok(json)
 	}
 
+	fn decode_query(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//Time 8,Query_Type 1,Query_Code 10,Reserve 8
+		if buf.len() < 27 {
+			log::error!("查询消息长度不足.len:{}", buf.len());
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "查询消息长度不足"));
+		}
+
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+		json[QUERY_TIME] = load_utf8_string(buf, 8).into();
+		json[QUERY_TYPE] = buf.get_u8().into();
+		json[QUERY_CODE] = load_utf8_string(buf, 10).into();
+
+		Ok(json)
+	}
+
+	fn decode_query_resp(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//Time 8,Query_Type 1,Query_Code 10,再加8个4字节的统计数
+		if buf.len() < 51 {
+			log::error!("查询回复消息长度不足.len:{}", buf.len());
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "查询回复消息长度不足"));
+		}
+
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+		json[QUERY_TIME] = load_utf8_string(buf, 8).into();
+		json[QUERY_TYPE] = buf.get_u8().into();
+		json[QUERY_CODE] = load_utf8_string(buf, 10).into();
+		for name in [MT_TL_MSG, MT_TL_USR, MT_SCS, MT_WT, MT_FL, MO_SCS, MO_WT, MO_FL].iter() {
+			json[*name] = buf.get_u32().into();
+		}
+
+		Ok(json)
+	}
+
+	fn decode_cancel(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		if buf.len() < 8 {
+			log::error!("删除消息长度不足.len:{}", buf.len());
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "删除消息长度不足"));
+		}
+
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+		json[MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into();
+
+		Ok(json)
+	}
+
+	fn decode_cancel_resp(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		//cmpp3.0是4个字节.cmpp2.0是1个字节
+		json[RESULT] = match buf.len() {
+			0 => return Err(io::Error::new(io::ErrorKind::InvalidData, "删除回复消息长度不足")),
+			1..=3 => buf.get_u8() as u32,
+			_ => buf.get_u32()
+		}.into();
+
+		Ok(json)
+	}
+
 	fn decode_report(&self, _buf: &mut BytesMut, _seq: u32, _tp: u32) -> Result<JsonValue, io::Error> {
 		log::error!("此协议应该不会收到这个消息ID");
 		Err(io::Error::new(io::ErrorKind::InvalidData, "此协议应该不会收到这个消息ID"))
@@ -828,6 +1053,8 @@ pub trait ProtocolImpl: Send + Sync {
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
 
+		//到Registered_Delivery为止的固定长度
+		check_len(buf, 76)?;
 		json[MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into(); //msg_id 8
 		json[DEST_ID] = load_utf8_string(buf, 21).into(); //dest_id 21
 		json[SERVICE_ID] = load_utf8_string(buf, 10).into(); //service_id 10
@@ -839,11 +1066,14 @@ pub trait ProtocolImpl: Send + Sync {
 
 		let is_report = buf.get_u8(); //Registered_Delivery	1
 		if is_report == 0 {
+			check_len(buf, 1)?;
 			//长短信的处理 tp_udhi != 0 说明是长短信
 			json[MSG_FMT] = msg_fmt.into(); //Msg_Fmt 1
 			let msg_content_len = buf.get_u8(); //Msg_Length	1
-			decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0)?;
+			decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0, Alphabet::Ascii)?;
 		} else {
+			//Msg_Length 1 加上状态报告的内容 67
+			check_len(buf, 68)?;
 			buf.advance(1); //Msg_Length 1
 			json[IS_REPORT] = true.into(); //状态报告增加.
 			json[PASSAGE_MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into(); // PASSAGE_MSG_ID
@@ -869,19 +1099,30 @@ pub trait ProtocolImpl: Send + Sync {
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
 
+		//到DestUsr_tl为止的固定长度
+		check_len(buf, 129)?;
 		json[MSG_ID] = cmpp_msg_id_u64_to_str(buf.get_u64()).into(); //msg_id 8
-		buf.advance(4); //Pk_total 1 Pk_number 1 Registered_Delivery 1 Msg_level 1
+		json[PK_TOTAL] = buf.get_u8().into(); //Pk_total 1
+		json[PK_NUMBER] = buf.get_u8().into(); //Pk_number 1
+		buf.advance(1); //Registered_Delivery 1
+		json[MSG_LEVEL] = buf.get_u8().into(); //Msg_level 1
 		json[SERVICE_ID] = load_utf8_string(buf, 10).into(); //service_id 10
-		buf.advance(35); //Fee_UserType 1  Fee_terminal_Id 32 Fee_terminal_type	1 TP_pId	1
+		json[FEE_USER_TYPE] = buf.get_u8().into(); //Fee_UserType 1
+		json[CHARGE_ID] = load_utf8_string(buf, 32).into(); //Fee_terminal_Id 32
+		json[FEE_TERM_TYPE] = buf.get_u8().into(); //Fee_terminal_type 1
+		buf.advance(1); //TP_pId 1
 		let tp_udhi = buf.get_u8(); //是否长短信
 		let msg_fmt = buf.get_u8();
 		json[MSG_FMT] = msg_fmt.into(); //Msg_Fmt 1
 		json[SP_ID] = load_utf8_string(buf, 6).into(); //sp_id 6
-		buf.advance(8); //FeeType	2  FeeCode	6
+		json[FEE_TYPE] = load_utf8_string(buf, 2).into(); //FeeType 2
+		json[FEE_CODE] = load_utf8_string(buf, 6).into(); //FeeCode 6
 		json[VALID_TIME] = load_utf8_string(buf, 17).into(); //valid_time 17
 		json[AT_TIME] = load_utf8_string(buf, 17).into(); //at_time 17
 		json[SRC_ID] = load_utf8_string(buf, 21).into(); //src_id 21
 		let dest_len = buf.get_u8(); //DestUsr_tl 1
+		//dest_id 32 Dest_terminal_type 1 Msg_Length 1
+		check_len(buf, dest_len as usize * 32 + 2)?;
 
 		//dest_id 32
 		let mut dest_ids: Vec<String> = Vec::new();
@@ -891,11 +1132,11 @@ pub trait ProtocolImpl: Send + Sync {
 
 		json[DEST_IDS] = dest_ids.into();
 
-		buf.advance(1); //Dest_terminal_type	1
+		json[DEST_TERM_TYPE] = buf.get_u8().into(); //Dest_terminal_type	1
 
 		//长短信的处理 tp_udhi != 0 说明是长短信
 		let msg_content_len = buf.get_u8(); //Msg_Length	1
-		decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0)?;
+		decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0, Alphabet::Ascii)?;
 
 		Ok(json)
 	}
@@ -905,6 +1146,8 @@ pub trait ProtocolImpl: Send + Sync {
 		let mut json = JsonValue::new_object();
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
+		//Source_Addr 6 AuthenticatorSource 16 Version 1 Timestamp 4
+		check_len(buf, 27)?;
 		json[LOGIN_NAME] = load_utf8_string(buf, 6).into();
 		//现在取2次,相当于只保存8位.
 		json[AUTHENTICATOR] = buf.get_u64().into();
@@ -926,11 +1169,129 @@ pub fn fill_bytes_zero(dest: &mut BytesMut, slice: &str, len: usize) {
 	}
 }
 
-///读一个长度的utf-8 字符编码.确保是utf-8.并且当前值是有效值.
+///读一个长度的utf-8 字符编码.不是有效的utf-8时替换掉无效的部分.
 pub fn load_utf8_string(buf: &mut BytesMut, len: usize) -> String {
-	unsafe {
-		String::from_utf8_unchecked(Vec::from(copy_to_bytes(buf, len).as_ref())).trim_end_matches(char::from(0)).to_owned()
+	String::from_utf8_lossy(copy_to_bytes(buf, len).as_ref()).trim_end_matches(char::from(0)).to_owned()
+}
+
+///向缓冲写入一个以0结尾的字串(C-Octet String).max_len包含结尾的0.超长的部分截掉.
+pub fn fill_c_string(dest: &mut BytesMut, slice: &str, max_len: usize) {
+	let len = slice.len().min(max_len - 1);
+	dest.extend_from_slice(&slice.as_bytes()[0..len]);
+	dest.put_u8(0);
+}
+
+///读一个以0结尾的字串(C-Octet String).最多读max_len个字节(包含结尾的0).没有结尾时返回消息结构错误
+pub fn load_c_string(buf: &mut BytesMut, max_len: usize) -> Result<String, io::Error> {
+	let len = match buf.iter().take(max_len).position(|b| *b == 0) {
+		Some(v) => v,
+		None => {
+			log::error!("得到C字串出现错误.没有找到结尾.可用长度:{}.最大长度{}", buf.len(), max_len);
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "消息结构出错.C字串没有结尾"));
+		}
+	};
+
+	let result = String::from_utf8_lossy(&buf[0..len]).into_owned();
+	buf.advance(len + 1);
+
+	Ok(result)
+}
+
+///解码失败时回复需要的基本信息.
+pub fn decode_error_json(seq: u32, tp: u32, e: io::Error) -> JsonValue {
+	log::warn!("解码消息出现错误.command:{:X}.seq:{}.e:{}", tp, seq, e);
+
+	let mut json = JsonValue::new_object();
+	json[MSG_TYPE_U32] = tp.into();
+	json[SEQ_ID] = seq.into();
+	json[DEST_IDS] = JsonValue::new_array();
+	json[DECODE_ERROR] = e.to_string().into();
+
+	json
+}
+
+///分帧时断开连接的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+	TooLong,
+	TooShort,
+	UnknownCommand,
+}
+
+static FRAME_TOO_LONG: AtomicU64 = AtomicU64::new(0);
+static FRAME_TOO_SHORT: AtomicU64 = AtomicU64::new(0);
+static FRAME_UNKNOWN_COMMAND: AtomicU64 = AtomicU64::new(0);
+
+impl FrameError {
+	fn counter(&self) -> &'static AtomicU64 {
+		match self {
+			FrameError::TooLong => &FRAME_TOO_LONG,
+			FrameError::TooShort => &FRAME_TOO_SHORT,
+			FrameError::UnknownCommand => &FRAME_UNKNOWN_COMMAND,
+		}
+	}
+
+	///启动以后因为这个原因断开的连接数
+	pub fn count(&self) -> u64 {
+		self.counter().load(Ordering::Relaxed)
+	}
+}
+
+///检查分帧的长度.消息超过最大长度(分帧器返回错误)或者不够消息头长度时记录原因并返回错误
+pub fn check_frame_len(frame: io::Result<Option<BytesMut>>, head_len: usize) -> io::Result<Option<BytesMut>> {
+	let frame = match frame {
+		Ok(Some(frame)) => frame,
+		Ok(None) => return Ok(None),
+		Err(e) => {
+			log::error!("消息长度超过协议的最大长度.断开连接.e:{}", e);
+			return Err(frame_error(FrameError::TooLong, e.to_string()));
+		}
+	};
+
+	if frame.len() + 4 < head_len {
+		log::error!("消息长度不够消息头长度.断开连接.src:{:X}", frame);
+		return Err(frame_error(FrameError::TooShort, format!("消息长度:{}.消息头长度:{}", frame.len() + 4, head_len)));
+	}
+
+	Ok(Some(frame))
+}
+
+///记一次分帧错误,并生成返回的错误
+fn frame_error(reason: FrameError, detail: String) -> io::Error {
+	reason.counter().fetch_add(1, Ordering::Relaxed);
+
+	io::Error::new(io::ErrorKind::InvalidData, format!("分帧出错.{:?}.{}", reason, detail))
+}
+
+///分帧错误的统计.用来记录日志或者上报
+pub fn get_frame_error_stats() -> JsonValue {
+	json::object! {
+		tooLong: FrameError::TooLong.count(),
+		tooShort: FrameError::TooShort.count(),
+		unknownCommand: FrameError::UnknownCommand.count(),
+	}
+}
+
+///检查消息剩余的长度是否足够.不够时返回消息结构错误.解码时先检查再读取,防止读越界
+pub fn check_len(buf: &BytesMut, len: usize) -> Result<(), io::Error> {
+	if buf.len() < len {
+		log::error!("消息结构出错.消息没有足够长度.可用长度:{}.需要长度{}", buf.len(), len);
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "消息结构出错.消息长度不足"));
+	}
+
+	Ok(())
+}
+
+///读4个字节的短信内容长度.短信内容不会超过255个字节,超过的认为是消息结构错误
+pub fn get_msg_content_len(buf: &mut BytesMut) -> Result<u8, io::Error> {
+	check_len(buf, 4)?;
+
+	let len = buf.get_u32();
+	if len > 0xFF {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "消息结构出错.短信内容长度超过255"));
 	}
+
+	Ok(len as u8)
 }
 
 ///读一个长度字串.做一个异常的保护.
@@ -965,6 +1326,37 @@ pub fn smgp_msg_id_str_to_buf(msg_id: &str) -> BytesMut {
 	buf
 }
 
+///根据跳过数量创建smgp格式的msgId。len:当前创建完成后，步进多少的值
+///必然返回10位长度。
+pub fn create_smgp_msg_id(len: u32) -> BytesMut {
+	let mut buf = BytesMut::with_capacity(10);
+	unsafe{
+		buf.set_len(buf.capacity());
+	}
+
+	let ismg = *ISMG_ID;
+	for i in 0..3 {
+		let d = ((ismg >> ((i * 2 + 1) * 4) & 0xF) << 4) | ismg >> (i * 2 * 4) & 0xF ;
+		buf[2 - i] = d as u8;
+	}
+
+	let mut time = get_time() / 100; //去掉秒
+	for i in 0..4 {
+		let d = ((time / 10) % 10) << 4 | time % 10;
+		time  /= 100;
+		buf[6 - i] = d as u8;
+	}
+
+	let mut seq = get_sequence_id(len) % 1000000;
+	for i in 0..3 {
+		let d = ((seq / 10) % 10) << 4 | seq % 10;
+		seq  /= 100;
+		buf[9 - i] = d as u8;
+	}
+
+	buf
+}
+
 //从buf转至String
 pub fn smgp_msg_id_buf_to_str(buf: &BytesMut) -> String {
 	format!("{:X}",buf)
@@ -1116,8 +1508,16 @@ pub fn create_cmpp_msg_id(len: u32) -> u64 {
 }
 
 
+///msg_fmt为0时内容使用的字符集.CMPP\SMGP\SGIP\CNGP为ASCII,SMPP的data_coding 0为GSM 7bit默认字母表
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alphabet {
+	Ascii,
+	///GSM 03.38的默认字母表和扩展表.每个septet占一个字节(不压缩).需要压缩的协议拆分以后自己压缩
+	Gsm7,
+}
+
 ///处理短信内容的通用方法.msg_content..
-pub fn encode_msg_content(msg_fmt: u8,msg_content: &str) -> Result<Vec<u8>, Error> {
+pub fn encode_msg_content(msg_fmt: u8, msg_content: &str, alphabet: Alphabet) -> Result<Vec<u8>, Error> {
 
 	//根据字符集转换.
 	match msg_fmt {
@@ -1129,7 +1529,12 @@ pub fn encode_msg_content(msg_fmt: u8,msg_content: &str) -> Result<Vec<u8>, Erro
 			Ok(v) => Ok(v),
 			Err(e) =>  Err(io::Error::new(io::ErrorKind::InvalidInput,e))
 		}
-		0 => Ok(msg_content.as_bytes().to_owned()),
+		0 if alphabet == Alphabet::Gsm7 => gsm7::encode(msg_content),
+		0 => if msg_content.is_ascii() {
+			Ok(msg_content.as_bytes().to_owned())
+		} else {
+			Err(io::Error::new(io::ErrorKind::InvalidInput, "ASCII字符集不支持的内容"))
+		}
 		_ => {
 			log::warn!("未处理的字符集类型.跳过.msg_fmt:{}", msg_fmt);
 			Err(io::Error::new(io::ErrorKind::NotFound,"错误的输入参数。"))
@@ -1138,47 +1543,287 @@ pub fn encode_msg_content(msg_fmt: u8,msg_content: &str) -> Result<Vec<u8>, Erro
 	
 }
 
+///二进制格式.4:二进制信息,246:(U)SIM相关消息.内容使用msg_hex
+pub fn is_binary_msg_fmt(msg_fmt: u8) -> bool {
+	msg_fmt == 4 || msg_fmt == 246
+}
+
+///取得需要发送的编码以后的短信内容.返回内容和内容是否以用户自带的UDH开头
+///二进制格式使用msg_hex的16进制内容,有udh_hex时放在内容的前面,这时只能发送一条.其他格式按字符集编码msg_content
+pub fn get_msg_content_code(msg_fmt: u8, json: &JsonValue, alphabet: Alphabet) -> Result<(Vec<u8>, bool), Error> {
+	if !is_binary_msg_fmt(msg_fmt) {
+		return match json[MSG_CONTENT].as_str() {
+			Some(msg_content) => Ok((encode_msg_content(msg_fmt, msg_content, alphabet)?, false)),
+			None => Err(io::Error::new(io::ErrorKind::NotFound, "没有内容字串"))
+		};
+	}
+
+	let msg_hex = match json[MSG_HEX].as_str().map(hex_to_bytes) {
+		Some(Some(v)) => v,
+		_ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "没有msg_hex或者格式错误"))
+	};
+
+	let udh = match json[UDH_HEX].as_str() {
+		None | Some("") => return Ok((msg_hex, false)),
+		Some(v) => match hex_to_bytes(v) {
+			//第一个字节是UDH的长度
+			Some(v) if !v.is_empty() && v[0] as usize + 1 == v.len() => v,
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "udh_hex格式错误"))
+		}
+	};
+
+	let (one_len, _) = get_msg_content_limit(msg_fmt, alphabet);
+	if udh.len() + msg_hex.len() > one_len {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "带有udh_hex的内容超过单条长度"));
+	}
+
+	let mut result = udh;
+	result.extend_from_slice(&msg_hex);
+
+	Ok((result, true))
+}
+
+///取得TP_pid.没有指定时(U)SIM相关消息使用0x7F(SIM Data download),其他为0
+pub fn get_tp_pid(msg_fmt: u8, json: &JsonValue) -> u8 {
+	json[TP_PID].as_u8().unwrap_or(if msg_fmt == 246 { 0x7F } else { 0 })
+}
+
+///取得第index条(从0开始)的Pk_total和Pk_number.自己拆分的长短信按拆分结果,否则使用json里面指定的值
+pub fn get_pk(json: &JsonValue, sms_len: usize, index: usize) -> (u8, u8) {
+	if sms_len > 1 {
+		(sms_len as u8, (index + 1) as u8)
+	} else {
+		(json[PK_TOTAL].as_u8().unwrap_or(1), json[PK_NUMBER].as_u8().unwrap_or(1))
+	}
+}
+
+///默认的字符集选择顺序.ASCII,GBK,UCS2
+pub static DEFAULT_MSG_FMTS: [u8; 3] = [0, 15, 8];
+
+///选择可以编码内容的字符集.指定的字符集可用时直接使用,否则按允许的字符集顺序选择第一个可用的.都不可用时返回None.
+///msg_fmt 0按ASCII判断.SMPP发送时不能用GSM 7bit编码的内容自己改为UCS2
+pub fn select_msg_fmt(msg_content: &str, msg_fmt: Option<u8>, allowed: &[u8]) -> Option<u8> {
+	if let Some(msg_fmt) = msg_fmt {
+		if allowed.contains(&msg_fmt) && encode_msg_content(msg_fmt, msg_content, Alphabet::Ascii).is_ok() {
+			return Some(msg_fmt);
+		}
+	}
+
+	allowed.iter().find(|v| encode_msg_content(**v, msg_content, Alphabet::Ascii).is_ok()).copied()
+}
+
+///单条短信和拆分后每一条短信可以放的编码以后的长度.
+///GSM 7bit为160/153个septet.其他为140/134个字节(ASCII为140/134个字符,UCS2为70/67个字符)
+pub fn get_msg_content_limit(msg_fmt: u8, alphabet: Alphabet) -> (usize, usize) {
+	match (msg_fmt, alphabet) {
+		(0, Alphabet::Gsm7) => (gsm7::ONE_LEN, gsm7::PART_LEN),
+		_ => (140, 134),
+	}
+}
+
+///拆分编码以后的短信内容.不超过单条长度的不拆分.拆分后的每一条需要另外加上UDH
+pub fn split_msg_content(msg_fmt: u8, msg_content_code: &[u8], alphabet: Alphabet) -> Vec<&[u8]> {
+	let (one_len, part_len) = get_msg_content_limit(msg_fmt, alphabet);
+
+	if msg_content_code.len() <= one_len {
+		vec![msg_content_code]
+	} else {
+		split_msg_content_by_len(msg_fmt, msg_content_code, part_len, alphabet)
+	}
+}
+
+///按指定长度拆分编码以后的短信内容.不会把一个字符拆到两条里面:
+///UCS2的代理对(如emoji),GBK的双字节字符,GSM 7bit的转义符和后面的扩展字符
+pub fn split_msg_content_by_len(msg_fmt: u8, msg_content_code: &[u8], part_len: usize, alphabet: Alphabet) -> Vec<&[u8]> {
+	if msg_fmt == 0 && alphabet == Alphabet::Gsm7 {
+		return gsm7::split(msg_content_code, part_len);
+	}
+
+	let mut result = Vec::with_capacity(msg_content_code.len() / part_len + 1);
+	let mut rest = msg_content_code;
+
+	while rest.len() > part_len {
+		let mut len = part_len;
+		match msg_fmt {
+			8 => {
+				len -= len % 2;
+				//最后一个是高位代理时.和后面的低位代理放到下一条
+				if len >= 2 && (0xD8..=0xDB).contains(&rest[len - 2]) {
+					len -= 2;
+				}
+			}
+			//GBK的首字节大于0x80时是双字节字符.从头计算字符的边界
+			15 => {
+				let mut end = 0;
+				while end < len {
+					let char_len = if rest[end] > 0x80 { 2 } else { 1 };
+					if end + char_len > len {
+						break;
+					}
+					end += char_len;
+				}
+				len = end;
+			}
+			_ => {}
+		}
+
+		let (this, other) = rest.split_at(len);
+		result.push(this);
+		rest = other;
+	}
+
+	if !rest.is_empty() || result.is_empty() {
+		result.push(rest);
+	}
+
+	result
+}
+
+///UDH里面的信息单元(IE)
+#[derive(Debug, Clone, PartialEq)]
+pub enum UdhIE {
+	///长短信.IEI 0x00为8位参考号,0x08为16位参考号
+	Concat { reference: u16, total: u8, number: u8 },
+	///应用端口寻址.IEI 0x04为8位端口,0x05为16位端口
+	Port { dest_port: u16, src_port: u16 },
+	///其他未处理的信息单元
+	Other(u8, Vec<u8>),
+}
+
+///解析UDH.udh包含开头的长度字节.长度不对时返回错误
+pub fn parse_udh(udh: &[u8]) -> Result<Vec<UdhIE>, io::Error> {
+	if udh.is_empty() || udh[0] as usize + 1 != udh.len() {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "UDH长度出错."));
+	}
+
+	let mut ies = Vec::new();
+	let mut rest = &udh[1..];
+
+	while !rest.is_empty() {
+		if rest.len() < 2 || rest[1] as usize + 2 > rest.len() {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "UDH信息单元长度出错."));
+		}
+
+		let iei = rest[0];
+		let data = &rest[2..2 + rest[1] as usize];
+		rest = &rest[2 + data.len()..];
+
+		let ie = match (iei, data.len()) {
+			(0x00, 3) => UdhIE::Concat { reference: data[0] as u16, total: data[1], number: data[2] },
+			(0x08, 4) => UdhIE::Concat { reference: u16::from_be_bytes([data[0], data[1]]), total: data[2], number: data[3] },
+			(0x04, 2) => UdhIE::Port { dest_port: data[0] as u16, src_port: data[1] as u16 },
+			(0x05, 4) => UdhIE::Port { dest_port: u16::from_be_bytes([data[0], data[1]]), src_port: u16::from_be_bytes([data[2], data[3]]) },
+			_ => UdhIE::Other(iei, data.to_vec()),
+		};
+
+		ies.push(ie);
+	}
+
+	Ok(ies)
+}
+
+///把UDH里面的长短信和端口信息放入json.有未处理的信息单元时把整个UDH以16进制放在udh_hex
+pub fn put_udh_to_json(udh: &[u8], json: &mut JsonValue) -> Result<(), io::Error> {
+	let mut has_other = false;
+
+	for ie in parse_udh(udh)? {
+		match ie {
+			UdhIE::Concat { reference, total, number } => {
+				json[LONG_SMS_REF] = reference.into();
+				json[LONG_SMS_TOTAL] = total.into();
+				json[LONG_SMS_NOW_NUMBER] = number.into();
+			}
+			UdhIE::Port { dest_port, src_port } => {
+				json[DEST_PORT] = dest_port.into();
+				json[SRC_PORT] = src_port.into();
+			}
+			UdhIE::Other(..) => has_other = true,
+		}
+	}
+
+	if has_other {
+		json[UDH_HEX] = format!("{:X}", Bytes::copy_from_slice(udh)).into();
+	}
+	json[TP_UDHI] = 1.into(); //TP_udhi 1
+
+	Ok(())
+}
+
 ///处理短信内容的通用方法.msg_content..
-pub fn decode_msg_content(buf: &mut BytesMut, msg_fmt: u8, mut msg_content_len: u8, json: &mut JsonValue, is_long_sms: bool) -> Result<(), io::Error> {
+///GSM 7bit的内容为每个septet一个字节(不压缩).压缩过的需要先解压
+pub fn decode_msg_content(buf: &mut BytesMut, msg_fmt: u8, mut msg_content_len: u8, json: &mut JsonValue, is_long_sms: bool, alphabet: Alphabet) -> Result<(), io::Error> {
+	check_len(buf, msg_content_len as usize)?;
+
+	//非文本的内容转成16进制.有UDH的时候UDH单独放在udh_hex
+	if !matches!(msg_fmt, 0 | 8 | 15) {
+		let mut msg_content = buf.split_to(msg_content_len as usize);
+		if is_long_sms && !msg_content.is_empty() && (msg_content[0] as usize) < msg_content.len() {
+			let udh = msg_content.split_to(msg_content[0] as usize + 1);
+			//二进制内容的UDH原样交给上游.能解析时同时给出长短信和端口信息
+			if let Err(e) = put_udh_to_json(&udh, json) {
+				log::warn!("解析二进制短信的UDH出错.{}", e);
+			}
+			json[UDH_HEX] = format!("{:X}", udh).into();
+			json[TP_UDHI] = 1.into();
+		}
+		json[MSG_HEX] = format!("{:X}", msg_content).into();
+
+		return Ok(());
+	}
+
 	if is_long_sms {
-		let head_len = buf.get_u8(); //接下来的长度
-		buf.advance((head_len - 2) as usize); //跳过对应的长度
-		json[LONG_SMS_TOTAL] = buf.get_u8().into();
-		json[LONG_SMS_NOW_NUMBER] = buf.get_u8().into();
-		json[TP_UDHI] = 1.into(); //TP_udhi 1
-
-		if msg_content_len > (head_len + 1) {
-			msg_content_len = msg_content_len - head_len - 1;
-		} else {
+		let head_len = match buf.first() {
+			Some(v) if msg_content_len > 0 => *v,
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidData, "消息结构出错.")),
+		};
+
+		if msg_content_len as usize <= head_len as usize + 1 {
 			log::warn!("消息结构出错.整体消息长度小于消息头长度.");
 			return Err(io::Error::new(io::ErrorKind::Other, "消息结构出错."));
 		}
+
+		let udh = buf.split_to(head_len as usize + 1);
+		put_udh_to_json(&udh, json)?;
+		msg_content_len = msg_content_len - head_len - 1;
 	}
 
 	let msg_content = copy_to_bytes(buf, msg_content_len as usize);
-	//根据字符集转换.
-	let msg_content = match msg_fmt {
-		8 => match UTF_16BE.decode(&msg_content, DecoderTrap::Strict) {
-			Ok(v) => v,
-			Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e))
+	json[MSG_CONTENT] = decode_msg_content_bytes(msg_fmt, &msg_content, alphabet)?.into();
+
+	Ok(())
+}
+
+///根据字符集把已经取出来的短信内容转成字串.
+pub fn decode_msg_content_bytes(msg_fmt: u8, msg_content: &[u8], alphabet: Alphabet) -> Result<String, io::Error> {
+	match msg_fmt {
+		8 => match UTF_16BE.decode(msg_content, DecoderTrap::Strict) {
+			Ok(v) => Ok(v),
+			Err(e) => Err(io::Error::new(io::ErrorKind::Other, e))
 		}
-		15 => match GBK.decode(&msg_content, DecoderTrap::Strict) {
-			Ok(v) => v,
-			Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e))
+		15 => match GBK.decode(msg_content, DecoderTrap::Strict) {
+			Ok(v) => Ok(v),
+			Err(e) => Err(io::Error::new(io::ErrorKind::Other, e))
+		}
+		0 if alphabet == Alphabet::Gsm7 => gsm7::decode(msg_content),
+		//ASCII.对方发来的不是ASCII时按UTF-8处理,也不是UTF-8时按Latin1处理.不返回错误
+		0 => match std::str::from_utf8(msg_content) {
+			Ok(v) => Ok(v.to_owned()),
+			Err(_) => Ok(msg_content.iter().map(|v| *v as char).collect()),
 		}
-		0 => match std::str::from_utf8(&msg_content) {
-			Ok(v) => v.to_owned(),
-			Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e))
-		},
 		_ => {
 			log::warn!("未处理的字符集类型.跳过.msg_fmt:{}", msg_fmt);
-			return Err(io::Error::new(io::ErrorKind::Other, "未处理的字符集类型.跳过"));
+			Err(io::Error::new(io::ErrorKind::Other, "未处理的字符集类型.跳过"))
 		}
-	};
+	}
+}
 
-	json[MSG_CONTENT] = msg_content.into();
+///把16进制的字串转成字节.格式不对的时候返回None
+pub fn hex_to_bytes(hex: &str) -> Option<Vec<u8>> {
+	if hex.len() % 2 != 0 {
+		return None;
+	}
 
-	Ok(())
+	(0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
 }
 
 ///得到一个mmddhhMMss格式的当前时间
@@ -1192,3 +1837,21 @@ pub fn get_time() -> u32 {
 
 	time
 }
+
+///CMPP3.0的submit\deliver回复result对照表
+pub static CMPP_RESULT_TABLE: &[ResultCode] = &[
+	(0, SmsStatus::Success, false, "正确"),
+	(1, SmsStatus::MessageError, false, "消息结构错"),
+	(2, SmsStatus::CommandError, false, "命令字错"),
+	(3, SmsStatus::SeqError, false, "消息序号重复"),
+	(4, SmsStatus::LengthError, false, "消息长度错"),
+	(5, SmsStatus::FeeError, false, "资费代码错"),
+	(6, SmsStatus::LengthError, false, "超过最大信息长"),
+	(7, SmsStatus::ServiceError, false, "业务代码错"),
+	(8, SmsStatus::TrafficRestrictions, true, "流量控制错"),
+	(9, SmsStatus::FeeTermError, false, "本网关不负责服务此计费号码"),
+	(10, SmsStatus::SrcIdError, false, "Src_Id错误"),
+	(11, SmsStatus::PermissionError, false, "Msg_src错误"),
+	(12, SmsStatus::FeeTermError, false, "Fee_terminal_Id错误"),
+	(13, SmsStatus::DestIdError, false, "Dest_terminal_Id错误"),
+];
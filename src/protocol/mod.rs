@@ -6,18 +6,20 @@ use json::JsonValue;
 use tokio::io;
 
 pub use crate::protocol::msg_type::MsgType;
-pub use crate::protocol::msg_type::SmsStatus;
+pub use crate::protocol::msg_type::{SmsStatus, ResultCode};
 
 pub use self::cmpp48::Cmpp48;
 pub use self::sgip::Sgip;
-use crate::protocol::names::{MSG_TYPE_U32, MSG_TYPE_STR};
+use crate::protocol::names::{MSG_TYPE_U32, MSG_TYPE_STR, RESULT, RESULT_STATUS, RETRYABLE};
 use tokio_util::codec::{Encoder, Decoder};
-use crate::protocol::cmpp32::Cmpp32;
+use crate::protocol::cmpp20::Cmpp20;
 use futures::io::Error;
 use crate::protocol::smgp::Smgp30;
 use crate::protocol::Protocol::SMGP;
 use crate::protocol::smpp::Smpp;
+use crate::protocol::cngp::Cngp;
 pub use crate::protocol::implements::ProtocolImpl;
+use crate::protocol::report_status::{denormalize_report, normalize_report};
 
 ///协议的对应部分。用来编码和解码
 mod cmpp48;
@@ -25,17 +27,21 @@ pub mod smgp;
 mod sgip;
 mod msg_type;
 pub mod names;
-pub mod cmpp32;
+pub mod cmpp20;
 pub mod implements;
 mod smpp;
+mod cngp;
+pub mod gsm7;
+pub mod report_status;
 
 #[derive(Debug, Clone)]
 pub enum Protocol {
 	CMPP48(Cmpp48),
-	CMPP32(Cmpp32),
+	CMPP20(Cmpp20),
 	SMGP(Smgp30),
 	SGIP(Sgip),
 	SMPP(Smpp),
+	CNGP(Cngp),
 	None,
 }
 
@@ -47,6 +53,7 @@ impl From<&str> for Protocol {
 			"SMGP" => Protocol::SMGP(Smgp30::new()),
 			"SGIP" => Protocol::SGIP(Sgip::new()),
 			"SMPP" => Protocol::SMPP(Smpp::new()),
+			"CNGP" => Protocol::CNGP(Cngp::new()),
 			_ => Protocol::None
 		}
 	}
@@ -57,10 +64,11 @@ impl Protocol {
 	fn parse(&self) -> &str {
 		match self {
 			Protocol::CMPP48(_) => "CMPP",
-			Protocol::CMPP32(_) => "CMPP",
+			Protocol::CMPP20(_) => "CMPP",
 			SMGP(_) => "SMGP",
 			Protocol::SGIP(_) => "SGIP",
 			Protocol::SMPP(_) => "SMPP",
+			Protocol::CNGP(_) => "CNGP",
 			Protocol::None => "None"
 		}
 	}
@@ -75,11 +83,13 @@ impl Protocol {
 	///给定一个版本号。返回与版本号相对应的操作类型
 	pub fn get_protocol(name: &str, version: u32) -> Protocol {
 		match (name.to_uppercase().as_str(), version) {
-			("CMPP", 48) => Protocol::CMPP48(Cmpp48::new()),
-			("CMPP", 32) => Protocol::CMPP32(Cmpp32::new()),
+			//cmpp的版本号高4位为主版本号.低4位为次版本号
+			("CMPP", 0x30..=0x3F) => Protocol::CMPP48(Cmpp48::new()),
+			("CMPP", 0x20..=0x2F) => Protocol::CMPP20(Cmpp20::new()),
 			("SGIP", _) => Protocol::SGIP(Sgip::new()),
 			("SMGP", _) => Protocol::SMGP(Smgp30::new()),
 			("SMPP", _) => Protocol::SMPP(Smpp::new()),
+			("CNGP", _) => Protocol::CNGP(Cngp::new()),
 			_ => {
 				log::error!("未了解的协议名称和版本号.name:{},version:{}", name, version);
 				Protocol::CMPP48(Cmpp48::new())
@@ -90,12 +100,14 @@ impl Protocol {
 	pub fn has(&self, version: u32) -> bool {
 		match self {
 			Protocol::CMPP48(_) |
-			Protocol::CMPP32(_) => {
-				version == 32 || version == 48
+			Protocol::CMPP20(_) => {
+				(0x20..=0x3F).contains(&version)
 			}
 			SMGP(_) => true,
 			Protocol::SGIP(_) => true,
-			Protocol::SMPP(_) => true,
+			//3.3及以前的版本号都小于0x34
+			Protocol::SMPP(_) => version <= 0x34,
+			Protocol::CNGP(_) => true,
 			Protocol::None => false
 		}
 	}
@@ -104,10 +116,11 @@ impl Protocol {
 	pub fn get_auth(&self, login_name: &str, password: &str, timestamp: u32) -> [u8; 16] {
 		match self {
 			Protocol::CMPP48(obj) => obj.get_auth(login_name, password, timestamp),
-			Protocol::CMPP32(obj) => obj.get_auth(login_name, password, timestamp),
+			Protocol::CMPP20(obj) => obj.get_auth(login_name, password, timestamp),
 			Protocol::SMGP(obj) => obj.get_auth(login_name, password, timestamp),
 			Protocol::SGIP(obj) => obj.get_auth(login_name, password, timestamp),
 			Protocol::SMPP(obj) => obj.get_auth(login_name, password, timestamp),
+			Protocol::CNGP(obj) => obj.get_auth(login_name, password, timestamp),
 			Protocol::None => {
 				log::error!("当前操作不可用。");
 				[0u8;16]
@@ -118,10 +131,11 @@ impl Protocol {
 	pub fn get_status_enum(&self, v: u32) -> SmsStatus {
 		match self {
 			Protocol::CMPP48(obj) => obj.get_status_enum(v),
-			Protocol::CMPP32(obj) => obj.get_status_enum(v),
+			Protocol::CMPP20(obj) => obj.get_status_enum(v),
 			Protocol::SMGP(obj) => obj.get_status_enum(v),
 			Protocol::SGIP(obj) => obj.get_status_enum(v),
 			Protocol::SMPP(obj) => obj.get_status_enum(v),
+			Protocol::CNGP(obj) => obj.get_status_enum(v),
 			Protocol::None => {
 				log::error!("当前操作不可用。");
 				SmsStatus::UNKNOWN
@@ -129,8 +143,29 @@ impl Protocol {
 		}
 	}
 
+	///按回复的result取得统一状态和是否可以重发
+	pub fn get_result_enum(&self, code: u32) -> (SmsStatus, bool) {
+		match self {
+			Protocol::CMPP48(obj) => obj.get_result_enum(code),
+			Protocol::CMPP20(obj) => obj.get_result_enum(code),
+			Protocol::SMGP(obj) => obj.get_result_enum(code),
+			Protocol::SGIP(obj) => obj.get_result_enum(code),
+			Protocol::SMPP(obj) => obj.get_result_enum(code),
+			Protocol::CNGP(obj) => obj.get_result_enum(code),
+			Protocol::None => {
+				log::error!("当前操作不可用。");
+				(SmsStatus::UNKNOWN, false)
+			}
+		}
+	}
+
 	///生成实体过来.这里应该是由实体发送来的消息.其他的不在这里。
 	pub fn encode_message(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		//其他协议收到的状态报告.按统一状态换成当前协议的state
+		if json[MSG_TYPE_STR] == "Report" {
+			denormalize_report(self.parse(), json);
+		}
+
 		match self {
 			Protocol::CMPP48(obj) => match json[MSG_TYPE_STR].as_str() {
 				None => {
@@ -145,6 +180,10 @@ impl Protocol {
 						MsgType::Report => obj.encode_report(json),
 						MsgType::ActiveTest => obj.encode_active_test(json),
 						MsgType::Terminate => obj.encode_terminate(json),
+						MsgType::Query => obj.encode_query(json),
+						MsgType::QueryResp => obj.encode_query_resp(json),
+						MsgType::Cancel => obj.encode_cancel(json),
+						MsgType::CancelResp => encode_cancel_result(obj, json),
 						_ => {
 							log::error!("编码出错..未知的.msg_type..msg:{}", json);
 							Err(io::Error::new(io::ErrorKind::Other, "还未实现"))
@@ -152,7 +191,7 @@ impl Protocol {
 					}
 				}
 			}
-			Protocol::CMPP32(obj) => match json[MSG_TYPE_STR].as_str() {
+			Protocol::CMPP20(obj) => match json[MSG_TYPE_STR].as_str() {
 				None => {
 					log::error!("编码出错..没有msg_type_str..msg:{}", json);
 					Err(Error::new(io::ErrorKind::NotFound, format!("没有找到指定的type.json:{}", json)))
@@ -165,6 +204,10 @@ impl Protocol {
 						MsgType::Report => obj.encode_report(json),
 						MsgType::ActiveTest => obj.encode_active_test(json),
 						MsgType::Terminate => obj.encode_terminate(json),
+						MsgType::Query => obj.encode_query(json),
+						MsgType::QueryResp => obj.encode_query_resp(json),
+						MsgType::Cancel => obj.encode_cancel(json),
+						MsgType::CancelResp => encode_cancel_result(obj, json),
 						_ => {
 							log::error!("编码出错..未知的.msg_type..msg:{}", json);
 							Err(io::Error::new(io::ErrorKind::Other, "还未实现"))
@@ -213,6 +256,28 @@ impl Protocol {
 				}
 			}
 			Protocol::SMPP(obj) => match json[MSG_TYPE_STR].as_str() {
+				None => {
+					log::error!("编码出错..没有msg_type_str..msg:{}", json);
+					Err(Error::new(io::ErrorKind::NotFound, format!("没有找到指定的type.json:{}", json)))
+				}
+				Some(msg_type) => {
+					match msg_type.into() {
+						MsgType::Connect => obj.encode_connect(json),
+						MsgType::Submit => obj.encode_submit(json),
+						MsgType::Deliver => obj.encode_deliver(json),
+						MsgType::Report => obj.encode_report(json),
+						MsgType::ActiveTest => obj.encode_active_test(json),
+						MsgType::Terminate => obj.encode_terminate(json),
+						MsgType::Cancel => obj.encode_cancel(json),
+						MsgType::CancelResp => encode_cancel_result(obj, json),
+						_ => {
+							log::error!("编码出错..未知的.msg_type..msg:{}", json);
+							Err(io::Error::new(io::ErrorKind::Other, "还未实现"))
+						}
+					}
+				}
+			}
+			Protocol::CNGP(obj) => match json[MSG_TYPE_STR].as_str() {
 				None => {
 					log::error!("编码出错..没有msg_type_str..msg:{}", json);
 					Err(Error::new(io::ErrorKind::NotFound, format!("没有找到指定的type.json:{}", json)))
@@ -240,6 +305,7 @@ impl Protocol {
 	}
 
 	///为收到的业务消息生成一个回执消息,不包括登录.当本身就是回执消息的时候返回None.
+	///删除请求由实体按定时短信的删除结果回复,这里也返回None
 	pub fn encode_receipt(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
 		match self {
 			Protocol::CMPP48(obj) => {
@@ -252,7 +318,7 @@ impl Protocol {
 					_ => None,
 				}
 			}
-			Protocol::CMPP32(obj) => {
+			Protocol::CMPP20(obj) => {
 				match obj.get_type_enum(json[MSG_TYPE_U32].as_u32().unwrap()) {
 					MsgType::Connect => obj.encode_connect_rep(status, json),
 					MsgType::Submit => obj.encode_submit_resp(status, json),
@@ -289,6 +355,19 @@ impl Protocol {
 					MsgType::Deliver => obj.encode_deliver_resp(status, json),
 					MsgType::Report => obj.encode_report_resp(status, json),
 					MsgType::ActiveTest => obj.encode_active_test_resp(status, json),
+					MsgType::Terminate => obj.encode_terminate_resp(status, json),
+					MsgType::UNKNOWN => obj.encode_generic_nack(json),
+					_ => None,
+				}
+			}
+			Protocol::CNGP(obj) => {
+				match obj.get_type_enum(json[MSG_TYPE_U32].as_u32().unwrap()) {
+					MsgType::Connect => obj.encode_connect_rep(status, json),
+					MsgType::Submit => obj.encode_submit_resp(status, json),
+					MsgType::Deliver => obj.encode_deliver_resp(status, json),
+					MsgType::Report => obj.encode_report_resp(status, json),
+					MsgType::ActiveTest => obj.encode_active_test_resp(status, json),
+					MsgType::Terminate => obj.encode_terminate_resp(status, json),
 					_ => None,
 				}
 			}
@@ -300,6 +379,16 @@ impl Protocol {
 	}
 }
 
+///实体回复的删除结果.result为0时成功,其他为失败
+fn encode_cancel_result<T: ProtocolImpl>(obj: &T, json: &mut JsonValue) -> Result<BytesMut, Error> {
+	let status = match json[RESULT].as_u32() {
+		Some(0) => SmsStatus::Success,
+		_ => SmsStatus::OtherError,
+	};
+
+	obj.encode_cancel_resp(status, json).ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "删除回复没有seq_id"))
+}
+
 impl Display for Protocol {
 	fn fmt(&self, f: &mut Formatter<'_>) -> result::Result<(), std::fmt::Error> {
 		write!(f, "{:?}", self)
@@ -322,16 +411,35 @@ impl Decoder for Protocol {
 	type Error = io::Error;
 
 	fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-		match self {
+		let mut result = match self {
 			Protocol::CMPP48(obj) => obj.decode_read_msg(src),
-			Protocol::CMPP32(obj) => obj.decode_read_msg(src),
+			Protocol::CMPP20(obj) => obj.decode_read_msg(src),
 			Protocol::SMGP(obj) => obj.decode_read_msg(src),
 			Protocol::SGIP(obj) => obj.decode_read_msg(src),
 			Protocol::SMPP(obj) => obj.decode_read_msg(src),
+			Protocol::CNGP(obj) => obj.decode_read_msg(src),
 			Protocol::None => {
 				log::error!("当前操作不可用。");
 				Ok(None)
 			}
+		};
+
+		//状态报告加上统一的状态.回复加上result对应的统一状态
+		if let Ok(Some(msg)) = &mut result {
+			match msg[MSG_TYPE_STR].as_str() {
+				Some("Report") => normalize_report(self.parse(), msg),
+				Some("SubmitResp") | Some("DeliverResp") | Some("ReportResp") => {
+					if let Some(code) = msg[RESULT].as_u32() {
+						let (status, retryable) = self.get_result_enum(code);
+						let status: &str = status.into();
+						msg[RESULT_STATUS] = status.into();
+						msg[RETRYABLE] = retryable.into();
+					}
+				}
+				_ => {}
+			}
 		}
+
+		result
 	}
 }
@@ -37,6 +37,8 @@ pub enum MsgType {
 	PushMoRouteUpdateResp,
 	GetMoRoute,
 	GetMoRouteResp,
+	///SMPP的通用否定应答
+	GenericNack,
 	UNKNOWN,
 }
 
@@ -77,6 +79,7 @@ impl From<&str> for MsgType {
 			"PushMoRouteUpdateResp" => MsgType::PushMoRouteUpdateResp,
 			"GetMoRoute" => MsgType::GetMoRoute,
 			"GetMoRouteResp" => MsgType::GetMoRouteResp,
+			"GenericNack" => MsgType::GenericNack,
 			_ => MsgType::UNKNOWN,
 		}
 	}
@@ -119,13 +122,14 @@ impl Into<&str> for MsgType {
 			MsgType::PushMoRouteUpdateResp => "PushMoRouteUpdateResp",
 			MsgType::GetMoRoute => "GetMoRoute",
 			MsgType::GetMoRouteResp => "GetMoRouteResp",
+			MsgType::GenericNack => "GenericNack",
 			MsgType::UNKNOWN => "UnKnow",
 		}
 	}
 }
 
 ///协议的错误回复
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SmsStatus {
 	///成功。
 	Success,
@@ -141,9 +145,32 @@ pub enum SmsStatus {
 	OtherError,
 	///流量限制
 	TrafficRestrictions,
+	///命令字错
+	CommandError,
+	///序列号重复
+	SeqError,
+	///消息长度错
+	LengthError,
+	///资费错
+	FeeError,
+	///业务代码错
+	ServiceError,
+	///发送号码错
+	SrcIdError,
+	///接收号码错
+	DestIdError,
+	///计费号码错
+	FeeTermError,
+	///没有权限.如SP帐号过期、超过日流量等
+	PermissionError,
+	///对端系统忙
+	SystemBusy,
 	UNKNOWN,
 }
 
+///协议回复的result对照表的一项.(result,统一状态,是否可以重发,说明)
+pub type ResultCode = (u32, SmsStatus, bool, &'static str);
+
 impl From<&'static str> for SmsStatus {
 	fn from(name: &'static str) -> Self {
 		match name {
@@ -154,6 +181,16 @@ impl From<&'static str> for SmsStatus {
 			"VersionError" => SmsStatus::VersionError,
 			"LoginOtherError" => SmsStatus::OtherError,
 			"TrafficRestrictions" => SmsStatus::TrafficRestrictions,
+			"CommandError" => SmsStatus::CommandError,
+			"SeqError" => SmsStatus::SeqError,
+			"LengthError" => SmsStatus::LengthError,
+			"FeeError" => SmsStatus::FeeError,
+			"ServiceError" => SmsStatus::ServiceError,
+			"SrcIdError" => SmsStatus::SrcIdError,
+			"DestIdError" => SmsStatus::DestIdError,
+			"FeeTermError" => SmsStatus::FeeTermError,
+			"PermissionError" => SmsStatus::PermissionError,
+			"SystemBusy" => SmsStatus::SystemBusy,
 			_ => SmsStatus::UNKNOWN
 		}
 	}
@@ -170,6 +207,16 @@ impl Into<&'static str> for SmsStatus {
 			SmsStatus::VersionError => "VersionError",
 			SmsStatus::OtherError => "LoginOtherError",
 			SmsStatus::TrafficRestrictions => "TrafficRestrictions",
+			SmsStatus::CommandError => "CommandError",
+			SmsStatus::SeqError => "SeqError",
+			SmsStatus::LengthError => "LengthError",
+			SmsStatus::FeeError => "FeeError",
+			SmsStatus::ServiceError => "ServiceError",
+			SmsStatus::SrcIdError => "SrcIdError",
+			SmsStatus::DestIdError => "DestIdError",
+			SmsStatus::FeeTermError => "FeeTermError",
+			SmsStatus::PermissionError => "PermissionError",
+			SmsStatus::SystemBusy => "SystemBusy",
 			SmsStatus::UNKNOWN => "UNKNOWN",
 		}
 	}
@@ -187,8 +234,18 @@ impl fmt::Display for SmsStatus {
 			SmsStatus::VersionError => write!(f, "登录,版本太高,"),
 			SmsStatus::OtherError => write!(f, "登录,其他错误"),
 			SmsStatus::TrafficRestrictions => write!(f, "发送.流量限制"),
+			SmsStatus::CommandError => write!(f, "命令字错"),
+			SmsStatus::SeqError => write!(f, "序列号重复"),
+			SmsStatus::LengthError => write!(f, "消息长度错"),
+			SmsStatus::FeeError => write!(f, "资费错"),
+			SmsStatus::ServiceError => write!(f, "业务代码错"),
+			SmsStatus::SrcIdError => write!(f, "发送号码错"),
+			SmsStatus::DestIdError => write!(f, "接收号码错"),
+			SmsStatus::FeeTermError => write!(f, "计费号码错"),
+			SmsStatus::PermissionError => write!(f, "没有权限"),
+			SmsStatus::SystemBusy => write!(f, "系统忙"),
 			SmsStatus::UNKNOWN => write!(f, "未知的错误."),
 			// _ => write!(f, "其他错误,这里没有更新。"),
 		}
 	}
-}
\ No newline at end of file
+}
@@ -35,6 +35,7 @@ pub static SUBMIT_TIME: &'static str = "submit_time";
 pub static SMSC_SEQUENCE: &'static str = "smsc_sequence";
 pub static DONE_TIME: &'static str = "done_time";
 pub static TP_UDHI: &'static str = "tp_udhi";
+pub static TP_PID: &'static str = "tp_pid";
 pub static SP_ID: &'static str = "spId";
 pub static CROP_ID: &'static str = "corpId";
 pub static VALID_TIME: &'static str = "valid_time";
@@ -53,17 +54,144 @@ pub static WRITE_LIMIT: &'static str = "writeLimit";
 pub static NAME: &'static str = "name";
 pub static OP_NAME: &'static str = "op_name";
 pub static MAX_BUFF_CAP: &'static str = "maxBuffCap";
+///通道允许使用的字符集.按优先顺序排列.如:"0,15,8"
+pub static MSG_FMTS: &'static str = "msgFmts";
 
 ///上游连接我方时的账号
 pub static GATEWAY_LOGIN_NAME: &'static str = "gatewayServerUsername";
 ///上游连接我方时的密码
 pub static GATEWAY_PASSWORD: &'static str = "gatewayServerPassword";
 
+///解码失败的原因.有这个值的消息只用来回复消息结构错误
+pub static DECODE_ERROR: &'static str = "decode_error";
+
 ///长短信总条数
 pub static LONG_SMS_TOTAL: &'static str = "l_s_total";
 ///长短信当前第几条
 pub static LONG_SMS_NOW_NUMBER: &'static str = "l_s_n_number";
+///长短信的参考号.来自UDH(IEI 0x00或者0x08)或者SMPP的sar_msg_ref_num
+pub static LONG_SMS_REF: &'static str = "l_s_ref";
+///长短信超时未收全,只合并了收到的部分
+pub static LONG_SMS_PARTIAL: &'static str = "l_s_partial";
+///长短信超时未收全时缺少的序号
+pub static LONG_SMS_MISSING: &'static str = "l_s_missing";
+///长短信等待其他部分的超时时间.单位秒
+pub static LONG_SMS_TIMEOUT: &'static str = "longSmsTimeout";
+///UDH端口寻址的源端口和目的端口
+pub static SRC_PORT: &'static str = "src_port";
+pub static DEST_PORT: &'static str = "dest_port";
 pub static MANAGER_TYPE: &'static str = "manager_type";
 ///消息优先级
 pub static IS_PRIORITY: &'static str = "is_priority";
 pub static ID: &'static str = "id";
+
+///SMPP的地址类型和编号方案.不给时根据号码自动判断
+pub static SRC_TON: &'static str = "src_ton";
+pub static SRC_NPI: &'static str = "src_npi";
+pub static DEST_TON: &'static str = "dest_ton";
+pub static DEST_NPI: &'static str = "dest_npi";
+///SMPP的service_type
+pub static SERVICE_TYPE: &'static str = "service_type";
+///SMPP的user_message_reference
+pub static USER_MESSAGE_REFERENCE: &'static str = "user_msg_ref";
+///未识别的tlv.以16进制字串保存.key为tag
+pub static TLVS: &'static str = "tlvs";
+///SMGP的tlv.交互的LinkID
+pub static LINK_ID: &'static str = "link_id";
+///SMGP的tlv.信息内容的来源
+pub static MSG_SRC: &'static str = "msg_src";
+///SMGP的tlv.业务代码
+pub static M_SERVICE_ID: &'static str = "m_service_id";
+///SMGP的tlv.主叫\被叫\计费用户的伪码
+pub static SRC_TERM_PSEUDO: &'static str = "src_pseudo";
+pub static DEST_TERM_PSEUDO: &'static str = "dest_pseudo";
+pub static CHARGE_TERM_PSEUDO: &'static str = "charge_pseudo";
+///长短信的发送方式.udh:使用UDH拆分(默认),sar:使用sar_*的tlv拆分,payload:使用message_payload一次发送
+pub static LONG_SMS_MODE: &'static str = "long_sms_mode";
+
+///查询的日期.格式YYYYMMDD
+pub static QUERY_TIME: &'static str = "query_time";
+///查询类别.0:总数查询,1:按业务类型查询
+pub static QUERY_TYPE: &'static str = "query_type";
+///查询码.按业务类型查询时为业务类型
+pub static QUERY_CODE: &'static str = "query_code";
+///从SP接收信息总数
+pub static MT_TL_MSG: &'static str = "mt_tl_msg";
+///从SP接收用户总数
+pub static MT_TL_USR: &'static str = "mt_tl_usr";
+///成功转发数量
+pub static MT_SCS: &'static str = "mt_scs";
+///待转发数量
+pub static MT_WT: &'static str = "mt_wt";
+///转发失败数量
+pub static MT_FL: &'static str = "mt_fl";
+///向SP成功送达数量
+pub static MO_SCS: &'static str = "mo_scs";
+///向SP待送达数量
+pub static MO_WT: &'static str = "mo_wt";
+///向SP送达失败数量
+pub static MO_FL: &'static str = "mo_fl";
+
+///二进制短信(msg_fmt为4或者246)的内容.16进制字串
+pub static MSG_HEX: &'static str = "msg_hex";
+///二进制短信自带的UDH.16进制字串.包含开头的长度字节
+pub static UDH_HEX: &'static str = "udh_hex";
+
+///资费类别.CMPP\SMGP为2位字串,SGIP为数字
+pub static FEE_TYPE: &'static str = "fee_type";
+///资费代码.以分为单位的6位字串
+pub static FEE_CODE: &'static str = "fee_code";
+///SMGP的包月费\封顶费.6位字串
+pub static FIXED_FEE: &'static str = "fixed_fee";
+///计费用户的号码.CMPP的Fee_terminal_Id,SMGP的ChargeTermID,SGIP的ChargeNumber
+pub static CHARGE_ID: &'static str = "charge_id";
+///计费用户类型.CMPP的Fee_UserType,SMGP的ChargeUserType
+pub static FEE_USER_TYPE: &'static str = "fee_user_type";
+///CMPP3.0的计费用户和接收用户的号码类型.0:真实号码,1:伪码
+pub static FEE_TERM_TYPE: &'static str = "fee_term_type";
+pub static DEST_TERM_TYPE: &'static str = "dest_term_type";
+///信息级别.SMGP\SGIP的Priority
+pub static MSG_LEVEL: &'static str = "msg_level";
+///相同Msg_Id的信息总条数和序号.长短信拆分时自动填写
+pub static PK_TOTAL: &'static str = "pk_total";
+pub static PK_NUMBER: &'static str = "pk_number";
+///SGIP的MorelatetoMTFlag.引起MT消息的原因
+pub static MO_RELATE_MT: &'static str = "mo_relate_mt";
+///通道配置里面计费字段的默认值
+pub static FEE_DEFAULTS: &'static str = "feeDefaults";
+
+///状态报告的统一状态.delivered\expired\deleted\undeliverable\accepted\rejected\enroute\unknown
+pub static REPORT_STATUS: &'static str = "report_status";
+///状态报告来源的协议.转发到其他协议时按统一状态转换state
+pub static REPORT_PROTOCOL: &'static str = "report_protocol";
+///回复的result对应的统一状态
+pub static RESULT_STATUS: &'static str = "result_status";
+///回复的result是否可以重发
+pub static RETRYABLE: &'static str = "retryable";
+///按valid_time计算出来的过期时间戳.第一次发送时计算
+pub static EXPIRE_TIME: &'static str = "expire_time";
+///发往sms.send.failure的失败原因
+pub static REASON: &'static str = "reason";
+///网关自己生成的状态报告
+pub static IS_SYNTHETIC: &'static str = "is_synthetic";
+///通道配置里面发送时选择连接的方式.roundRobin\leastQueued\leastInFlight
+pub static CHANNEL_SELECT: &'static str = "channelSelect";
+///通道配置里面每个连接发出未收到回复的最大请求数.0为不限制
+pub static WINDOW_SIZE: &'static str = "windowSize";
+///通道配置里面读\写的突发数量.不配置时为readLimit\writeLimit的十分之一
+pub static READ_BURST: &'static str = "readBurst";
+pub static WRITE_BURST: &'static str = "writeBurst";
+///passage.state.change里面当前实际使用的发送速度.收到超速回复后自动降低
+pub static EFFECTIVE_RATE: &'static str = "effective_rate";
+///已经重发的次数
+pub static RETRY_COUNT: &'static str = "retry_count";
+///第一次发送的时间戳.用来判断重发是否超过期限
+pub static FIRST_SEND_TIME: &'static str = "first_send_time";
+///通道配置里面的重发策略.最多重发次数,重发间隔的上限(秒),从第一次发送开始的重发期限(秒)
+pub static MAX_RETRIES: &'static str = "maxRetries";
+pub static MAX_RETRY_INTERVAL: &'static str = "maxRetryInterval";
+pub static RETRY_DEADLINE: &'static str = "retryDeadline";
+///通道配置里面at_time是否由网关保存.false时at_time直接发往通道,由服务商定时
+pub static GATEWAY_SCHEDULE: &'static str = "gatewaySchedule";
+///转给其他实体处理的请求.处理结果需要发回的实体id
+pub static REPLY_ENTITY_ID: &'static str = "reply_entity_id";
@@ -0,0 +1,170 @@
+use std::fs;
+
+use json::JsonValue;
+use lazy_static::lazy_static;
+
+use crate::protocol::names::{REPORT_PROTOCOL, REPORT_STATUS, STATE};
+
+///状态报告的统一状态
+pub static DELIVERED: &'static str = "delivered";
+pub static EXPIRED: &'static str = "expired";
+pub static DELETED: &'static str = "deleted";
+pub static UNDELIVERABLE: &'static str = "undeliverable";
+pub static ACCEPTED: &'static str = "accepted";
+pub static REJECTED: &'static str = "rejected";
+pub static ENROUTE: &'static str = "enroute";
+pub static UNKNOWN: &'static str = "unknown";
+
+///状态对照表的配置文件.没有时使用默认的对照表
+static REPORT_STATUS_FILE: &'static str = "config/report_status.json";
+
+lazy_static! {
+	static ref REPORT_STATUS_TABLE: JsonValue = load_report_status_table(REPORT_STATUS_FILE);
+}
+
+///默认的对照表.key为协议,value为各协议的state对应的统一状态.以*结尾的按前缀匹配.
+///反向转换时使用第一个对应的state
+fn default_report_status_table() -> JsonValue {
+	json::object! {
+		CMPP: {
+			DELIVRD: "delivered",
+			EXPIRED: "expired",
+			DELETED: "deleted",
+			UNDELIV: "undeliverable",
+			ACCEPTD: "accepted",
+			UNKNOWN: "unknown",
+			REJECTD: "rejected",
+			"MA:*": "undeliverable",
+			"MB:*": "undeliverable",
+			"MK:*": "undeliverable",
+			"MI:*": "undeliverable",
+			"CA:*": "rejected",
+			"CB:*": "rejected",
+		},
+		SMGP: {
+			DELIVRD: "delivered",
+			EXPIRED: "expired",
+			DELETED: "deleted",
+			UNDELIV: "undeliverable",
+			ACCEPTD: "accepted",
+			UNKNOWN: "unknown",
+			REJECTD: "rejected",
+			ENROUTE: "enroute",
+		},
+		CNGP: {
+			DELIVRD: "delivered",
+			EXPIRED: "expired",
+			DELETED: "deleted",
+			UNDELIV: "undeliverable",
+			ACCEPTD: "accepted",
+			UNKNOWN: "unknown",
+			REJECTD: "rejected",
+			ENROUTE: "enroute",
+		},
+		SMPP: {
+			DELIVRD: "delivered",
+			EXPIRED: "expired",
+			DELETED: "deleted",
+			UNDELIV: "undeliverable",
+			ACCEPTD: "accepted",
+			UNKNOWN: "unknown",
+			REJECTD: "rejected",
+			ENROUTE: "enroute",
+		},
+		SGIP: {
+			DELIVRD: "delivered",
+			ACCEPTD: "accepted",
+			SGIPERR: "undeliverable",
+		},
+	}
+}
+
+///读取状态对照表.文件里面有的协议替换默认的对照表
+pub fn load_report_status_table(file_name: &str) -> JsonValue {
+	let mut table = default_report_status_table();
+
+	let text = match fs::read_to_string(file_name) {
+		Ok(text) => text,
+		Err(_) => return table,
+	};
+
+	match json::parse(text.as_str()) {
+		Ok(config) => {
+			for (protocol, states) in config.entries() {
+				if states.is_object() {
+					table[protocol] = states.clone();
+				}
+			}
+		}
+		Err(e) => log::error!("状态报告对照表格式错误.使用默认的对照表.file:{}.e:{}", file_name, e),
+	}
+
+	table
+}
+
+///按协议的state取得统一状态.找不到时为unknown
+pub fn get_report_status(table: &JsonValue, protocol: &str, state: &str) -> String {
+	for (key, status) in table[protocol].entries() {
+		let matched = match key.strip_suffix('*') {
+			Some(prefix) => state.starts_with(prefix),
+			None => key == state,
+		};
+
+		if matched {
+			return status.as_str().unwrap_or(UNKNOWN).to_owned();
+		}
+	}
+
+	UNKNOWN.to_owned()
+}
+
+///按统一状态取得协议的state.没有对应的失败状态使用undeliverable对应的state
+pub fn get_report_state(table: &JsonValue, protocol: &str, status: &str) -> Option<String> {
+	let find = |status: &str| {
+		table[protocol].entries()
+			.find(|(key, value)| !key.ends_with('*') && value.as_str() == Some(status))
+			.map(|(key, _)| key.to_owned())
+	};
+
+	match find(status) {
+		Some(state) => Some(state),
+		None if status != DELIVERED => find(UNDELIVERABLE),
+		None => None,
+	}
+}
+
+///收到的状态报告加上统一状态和来源协议.原来的state和error_code不变
+pub fn normalize_report(protocol: &str, json: &mut JsonValue) {
+	let state = match &json[STATE] {
+		JsonValue::Number(_) => json[STATE].to_string(),
+		v => v.as_str().unwrap_or("").to_owned(),
+	};
+
+	json[REPORT_STATUS] = get_report_status(&REPORT_STATUS_TABLE, protocol, &state).into();
+	json[REPORT_PROTOCOL] = protocol.into();
+}
+
+///转发其他协议收到的状态报告时,按统一状态换成当前协议的state
+pub fn denormalize_report(protocol: &str, json: &mut JsonValue) {
+	let status = match json[REPORT_STATUS].as_str() {
+		Some(v) => v.to_owned(),
+		None => return,
+	};
+
+	if json[REPORT_PROTOCOL].as_str() == Some(protocol) {
+		return;
+	}
+
+	match get_report_state(&REPORT_STATUS_TABLE, protocol, &status) {
+		Some(state) => json[STATE] = state.into(),
+		None => log::warn!("没有找到统一状态对应的state.使用原来的值.protocol:{}.status:{}", protocol, status),
+	}
+}
+
+///状态报告是否为成功.有统一状态时以统一状态为准
+pub fn is_delivered(json: &JsonValue) -> bool {
+	match json[REPORT_STATUS].as_str() {
+		Some(status) => status == DELIVERED,
+		None => json[STATE].as_str() == Some("DELIVRD"),
+	}
+}
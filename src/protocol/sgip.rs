@@ -6,16 +6,21 @@ use tokio::io;
 use tokio::io::Error;
 use tokio_util::codec::{Decoder, LengthDelimitedCodec};
 
-use crate::protocol::{SmsStatus};
+use crate::protocol::{SmsStatus, ResultCode};
 use crate::protocol::msg_type::MsgType;
-use crate::protocol::implements::{ProtocolImpl, fill_bytes_zero, get_time, load_utf8_string, decode_msg_content};
-use crate::protocol::names::{LOGIN_NAME, PASSWORD, MSG_TYPE_U32, SEQ_ID, RESULT, MSG_CONTENT, SERVICE_ID, SP_ID, SRC_ID, DEST_IDS, SEQ_IDS, MSG_FMT, DEST_ID, STATE, ERROR_CODE};
+use crate::protocol::implements::{ProtocolImpl, check_len, decode_error_json, fill_bytes_zero, get_msg_content_len, get_time, load_utf8_string, decode_msg_content};
+use crate::protocol::names::{LOGIN_NAME, PASSWORD, MSG_TYPE_U32, SEQ_ID, RESULT, SERVICE_ID, SP_ID, SRC_ID, DEST_IDS, SEQ_IDS, MSG_FMT, DEST_ID, STATE, ERROR_CODE};
 use crate::global::get_sequence_id;
 use crate::protocol::msg_type::MsgType::{Connect, SubmitResp};
 use crate::global::FILL_ZERO;
 
-use super::implements::{encode_msg_content, sgip_msg_id_str_to_u64, sgip_msg_id_u64_to_str};
-use super::names::{CAN_WRITE, MSG_ID, NODE_ID, PASSAGE_MSG_ID, SPEED_LIMIT, STATUS, VERSION};
+use super::implements::{Alphabet, get_msg_content_code, get_tp_pid, split_msg_content, sgip_msg_id_str_to_u64, sgip_msg_id_u64_to_str};
+use super::names::{CAN_WRITE, CHARGE_ID, FEE_CODE, FEE_TYPE, MO_RELATE_MT, MSG_ID, MSG_LEVEL, NODE_ID, PASSAGE_MSG_ID, SPEED_LIMIT, STATUS, VERSION};
+
+///SGIP的消息头长度(Message_Length + Command_ID + 12个字节的Sequence_Number)
+const SGIP_HEAD_LEN: usize = 20;
+///SGIP消息的最大长度.100个接收号码的Submit也不会超过这个长度
+const SGIP_MAX_LEN: usize = 4096;
 
 ///Sgip协议的处理
 #[derive(Debug)]
@@ -27,13 +32,25 @@ pub struct Sgip {
 
 impl ProtocolImpl for Sgip {
 	fn get_framed(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
-		self.length_codec.decode(buf)
-	}
-	
-	fn is_speed_limit(&self, code: u32) -> bool{
-		return code == 88
+		let frame = self.length_codec.decode(buf);
+		self.check_frame(frame, SGIP_HEAD_LEN)
 	}
 
+	///sgip的序列号有3个字段.后面2个在消息体的开头.回复需要使用完整的序列号
+	fn decode_error(&self, head: &[u8], node_id: u32, tp: u32, e: io::Error) -> JsonValue {
+		let mut json = decode_error_json(node_id, tp, e);
+		json[SP_ID] = node_id.into();
+		if head.len() >= 8 {
+			let mut seq_id = [0u8; 8];
+			seq_id.copy_from_slice(&head[..8]);
+			let seq_id = u64::from_be_bytes(seq_id);
+			json[SEQ_ID] = seq_id.into();
+			json[MSG_ID] = sgip_msg_id_u64_to_str(node_id, seq_id).into();
+		}
+
+		json
+	}
+	
 	fn get_type_id(&self, t: MsgType) -> u32 {
 		match t {
 			MsgType::Submit => 0x00000003,
@@ -89,6 +106,7 @@ impl ProtocolImpl for Sgip {
 			SmsStatus::TrafficRestrictions => 101,
 			SmsStatus::OtherError => 5,
 			SmsStatus::UNKNOWN => 999,
+			_ => self.get_result_id(status).unwrap_or(5),
 		}
 	}
 
@@ -105,6 +123,10 @@ impl ProtocolImpl for Sgip {
 		}
 	}
 
+	fn get_result_table(&self) -> &'static [ResultCode] {
+		SGIP_RESULT_TABLE
+	}
+
 	///通过给定的账号密码。计算实际向客户发送的消息，也是用来进行校验密码是否正确。
 	fn get_auth(&self, _sp_id: &str, password: &str, _timestamp: u32) -> [u8; 16] {
 		let mut input = [0u8; 16];
@@ -288,21 +310,20 @@ impl ProtocolImpl for Sgip {
 			Some(v) => sgip_msg_id_str_to_u64(v)
 		};
 
-		let stat = match json[STATE].as_u8() {
-			Some(v) => v,
-			None => {
+		//state可以是数字,也可以是解码时转成的字串
+		let stat = match (json[STATE].as_u8(), json[STATE].as_str()) {
+			(Some(v), _) => v,
+			(None, Some("DELIVRD")) => 0,
+			(None, Some("ACCEPTD")) => 1,
+			(None, Some(_)) => 2,
+			(None, None) => {
 				log::error!("没有state.退出..json:{}", json);
 				return Err(io::Error::new(io::ErrorKind::NotFound, "没有state"));
 			}
 		};
 
-		let err_code = match json[ERROR_CODE].as_u8() {
-			Some(v) => v,
-			None => {
-				log::error!("没有error_code.退出..json:{}", json);
-				return Err(io::Error::new(io::ErrorKind::NotFound, "没有error_code"));
-			}
-		};
+		//其他协议转过来的状态报告可能没有error_code或者是字串
+		let err_code = json[ERROR_CODE].as_u8().or_else(|| json[ERROR_CODE].as_str().and_then(|v| v.parse().ok())).unwrap_or(0);
 
 		let src_id = match json[SRC_ID].as_str() {
 			Some(v) => v,
@@ -396,23 +417,16 @@ impl ProtocolImpl for Sgip {
 			}
 		};
 
-		let msg_content = match json[MSG_CONTENT].as_str() {
-			None => {
-				log::error!("没有内容字串.退出..json:{}", json);
-				return Err(io::Error::new(io::ErrorKind::NotFound, "没有内容字串"));
-			}
-			Some(v) => v
-		};
-
 		let msg_fmt = json[MSG_FMT].as_u8().unwrap_or(8);
 		//编码以后的消息内容
-		let msg_content_code = match encode_msg_content(msg_fmt,msg_content) {
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_fmt, json, Alphabet::Ascii) {
 			Ok(v) => v,
 			Err(e) => {
 				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
 				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
 			}
 		};
+		let tp_pid = get_tp_pid(msg_fmt, json);
 		
 		let src_id = match json[SRC_ID].as_str() {
 			Some(v) => v,
@@ -433,17 +447,17 @@ impl ProtocolImpl for Sgip {
 		let msg_content_len = msg_content_code.len();
 
 		let mut msg_content_head_len: usize = 0;
-		let mut one_content_len: usize = 140;
+		//按字符集拆分以后的每一条内容
+		let contents = split_msg_content(msg_fmt, &msg_content_code, Alphabet::Ascii);
 		let mut msg_content_seq_id: u8 = 0;
 		//整个消息的长度
-		let sms_len = if msg_content_len <= 140 {
+		let sms_len = if contents.len() == 1 {
 			1 as usize
 		} else {
 			msg_content_head_len = 6;
-			one_content_len = one_content_len - 6;
 			msg_content_seq_id = get_sequence_id(1) as u8;
 
-			((msg_content_len as f32) / one_content_len as f32).ceil() as usize
+			contents.len()
 		};
 
 		//长短信的话,一次性生成多条记录
@@ -453,13 +467,7 @@ impl ProtocolImpl for Sgip {
 		let mut dst = BytesMut::with_capacity(total_len);
 		let mut seq_ids = Vec::with_capacity(sms_len);
 
-		for i in 0..sms_len {
-			let this_msg_content = if i == sms_len - 1 {
-				&msg_content_code[(i * one_content_len)..msg_content_code.len()]
-			} else {
-				&msg_content_code[(i * one_content_len)..((i + 1) * one_content_len)]
-			};
-
+		for (i, this_msg_content) in contents.iter().enumerate() {
 			dst.put_u32((77 + msg_content_head_len + this_msg_content.len()) as u32);
 			dst.put_u32(self.get_type_id(MsgType::Deliver));
 			dst.put_u32(node_id);
@@ -469,8 +477,8 @@ impl ProtocolImpl for Sgip {
 
 			fill_bytes_zero(&mut dst, src_id, 21);//src_id 21
 			fill_bytes_zero(&mut dst, dest_id, 21);//src_id 21
-			dst.put_u8(0); //TP_pid 1
-			dst.put_u8(if sms_len == 1 { 0 } else { 1 }); //tp_udhi 1
+			dst.put_u8(tp_pid); //TP_pid 1
+			dst.put_u8(if sms_len == 1 && !has_udh { 0 } else { 1 }); //tp_udhi 1
 			dst.put_u8(msg_fmt); //Msg_Fmt 1
 			dst.put_u32((this_msg_content.len() + msg_content_head_len) as u32); //Msg_Length
 			if msg_content_head_len > 0 {
@@ -490,23 +498,16 @@ impl ProtocolImpl for Sgip {
 	}
 
 	fn encode_submit(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
-		let msg_content = match json[MSG_CONTENT].as_str() {
-			None => {
-				log::error!("没有内容字串.退出..json:{}", json);
-				return Err(io::Error::new(io::ErrorKind::NotFound, "没有内容字串"));
-			}
-			Some(v) => v
-		};
-
 		let msg_format = json[MSG_FMT].as_u8().unwrap_or(8);
 		//编码以后的消息内容
-		let msg_content_code = match encode_msg_content(msg_format,msg_content) {
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_format, json, Alphabet::Ascii) {
 			Ok(v) => v,
 			Err(e) => {
 				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
 				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
 			}
-		};		
+		};
+		let tp_pid = get_tp_pid(msg_format, json);
 
 		let service_id = match json[SERVICE_ID].as_str() {
 			Some(v) => v,
@@ -554,16 +555,16 @@ impl ProtocolImpl for Sgip {
 		let msg_content_len = msg_content_code.len();
 
 		let mut msg_content_head_len: usize = 0;
-		let mut one_content_len: usize = 140;
+		//按字符集拆分以后的每一条内容
+		let contents = split_msg_content(msg_format, &msg_content_code, Alphabet::Ascii);
 		let mut msg_content_seq_id: u8 = 0;
 		//整个消息的长度
-		let sms_len = if msg_content_len <= 140 {
+		let sms_len = if contents.len() == 1 {
 			1 as usize
 		} else {
 			msg_content_head_len = 6;
-			one_content_len = one_content_len - 6;
 			msg_content_seq_id = get_sequence_id(1) as u8;
-			((msg_content_len as f32) / one_content_len as f32).ceil() as usize
+			contents.len()
 		};
 
 		//长短信的话,一次性生成多条记录
@@ -574,13 +575,14 @@ impl ProtocolImpl for Sgip {
 		let mut dst = BytesMut::with_capacity(total_len);
 		let mut seq_ids = Vec::with_capacity(sms_len);
 
-		for i in 0..sms_len {
-			let this_msg_content = if i == sms_len - 1 {
-				&msg_content_code[(i * one_content_len)..msg_content_code.len()]
-			} else {
-				&msg_content_code[(i * one_content_len)..((i + 1) * one_content_len)]
-			};
+		//计费相关.没有指定时使用原来的默认值.FeeType在SGIP里面是数字
+		let charge_id = json[CHARGE_ID].as_str().unwrap_or("");
+		let fee_type = json[FEE_TYPE].as_u8().or_else(|| json[FEE_TYPE].as_str().and_then(|v| v.parse().ok())).unwrap_or(1);
+		let fee_code = json[FEE_CODE].as_str().unwrap_or("000001");
+		let mo_relate_mt = json[MO_RELATE_MT].as_u8().unwrap_or(0);
+		let msg_level = json[MSG_LEVEL].as_u8().unwrap_or(0);
 
+		for (i, this_msg_content) in contents.iter().enumerate() {
 			dst.put_u32((143 + dest_ids.len() * 21 + msg_content_head_len + this_msg_content.len()) as u32);
 			dst.put_u32(self.get_type_id(MsgType::Submit));
 			dst.put_u32(node_id);
@@ -590,7 +592,7 @@ impl ProtocolImpl for Sgip {
 			seq_ids.push(seq_id);
 
 			fill_bytes_zero(&mut dst, src_id, 21);  //src_id:SPNumber
-			dst.extend_from_slice(&FILL_ZERO[0..21]);//ChargeNumber
+			fill_bytes_zero(&mut dst, charge_id, 21);//ChargeNumber
 			dst.put_u8(dest_ids.len() as u8); //UserCount
 			dest_ids.iter().for_each(|dest_id| {
 				dst.extend_from_slice("86".as_bytes());
@@ -598,17 +600,17 @@ impl ProtocolImpl for Sgip {
 			});  //dest_id 19位.因为+86
 			dst.extend_from_slice(corp_id[0..5].as_bytes()); //corp_id
 			fill_bytes_zero(&mut dst, service_id, 10);//Service_Id
-			dst.put_u8(1); //FeeType
-			dst.extend_from_slice("000001".as_ref()); //FeeCode
+			dst.put_u8(fee_type); //FeeType
+			fill_bytes_zero(&mut dst, fee_code, 6); //FeeCode
 			dst.extend_from_slice("000000".as_ref()); //GivenValue
 			dst.put_u8(0); //AgentFlag
-			dst.put_u8(0); //MorelatetoMTFlag
-			dst.put_u8(0); //Priority
+			dst.put_u8(mo_relate_mt); //MorelatetoMTFlag
+			dst.put_u8(msg_level); //Priority
 			dst.extend_from_slice(&FILL_ZERO[0..16]); //ExpireTime
 			dst.extend_from_slice(&FILL_ZERO[0..16]); //ScheduleTime
 			dst.put_u8(1); //ReportFlag
-			dst.put_u8(0); //TP_pId
-			dst.put_u8(if sms_len == 1 { 0 } else { 1 }); //tp_udhi
+			dst.put_u8(tp_pid); //TP_pId
+			dst.put_u8(if sms_len == 1 && !has_udh { 0 } else { 1 }); //tp_udhi
 			dst.put_u8(msg_format); //Msg_Fmt
 			dst.put_u8(0); //MessageType
 			dst.put_u32((this_msg_content.len() + msg_content_head_len) as u32); //Msg_Length
@@ -630,6 +632,8 @@ impl ProtocolImpl for Sgip {
 	}
 
 	fn decode_connect_resp(&self, buf: &mut BytesMut, node_id: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//Sequence 8 Result 1 Reserve 8
+		check_len(buf, 9)?;
 		let mut json = JsonValue::new_object();
 
 		json[MSG_TYPE_U32] = tp.into();
@@ -644,6 +648,8 @@ impl ProtocolImpl for Sgip {
 	}
 
 	fn decode_submit_or_deliver_resp(&self, buf: &mut BytesMut, node_id: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//Sequence 8 Result 1 Reserve 8
+		check_len(buf, 9)?;
 		let mut json = JsonValue::new_object();
 
 		json[MSG_TYPE_U32] = tp.into();
@@ -661,6 +667,7 @@ impl ProtocolImpl for Sgip {
 	}
 
 	fn decode_nobody(&self, buf: &mut BytesMut, node_id: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		check_len(buf, 8)?;
 		let mut json = JsonValue::new_object();
 
 		json[MSG_TYPE_U32] = tp.into();
@@ -673,6 +680,8 @@ impl ProtocolImpl for Sgip {
 	}
 
 	fn decode_report(&self, buf: &mut BytesMut, node_id: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//Sequence 8 SubmitSequenceNumber 12 ReportType 1 UserNumber 21 State 1 ErrorCode 1
+		check_len(buf, 44)?;
 		let mut json = JsonValue::new_object();
 
 		json[MSG_TYPE_U32] = tp.into();
@@ -699,6 +708,8 @@ impl ProtocolImpl for Sgip {
 	}
 
 	fn decode_deliver(&self, buf: &mut BytesMut, node_id: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//到MessageLength为止的固定长度
+		check_len(buf, 57)?;
 		let mut json = JsonValue::new_object();
 
 		json[MSG_TYPE_U32] = tp.into();
@@ -713,13 +724,14 @@ impl ProtocolImpl for Sgip {
 		let tp_udhi = buf.get_u8(); //是否长短信
 		let msg_fmt = buf.get_u8();
 		json[MSG_FMT] = msg_fmt.into(); //MessageCoding 1
-		let msg_content_len = buf.get_u32(); //Msg_Length	4
-		decode_msg_content(buf, msg_fmt, msg_content_len as u8, &mut json, tp_udhi != 0)?;
+		let msg_content_len = get_msg_content_len(buf)?; //Msg_Length	4
+		decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0, Alphabet::Ascii)?;
 
 		Ok(json)
 	}
 
 	fn decode_report_resp(&self, buf: &mut BytesMut, node_id: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		check_len(buf, 9)?;
 		let mut json = JsonValue::new_object();
 
 		json[MSG_TYPE_U32] = tp.into();
@@ -734,6 +746,8 @@ impl ProtocolImpl for Sgip {
 	}
 
 	fn decode_submit(&self, buf: &mut BytesMut, node_id: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//到UserCount为止的固定长度
+		check_len(buf, 51)?;
 		let mut json = JsonValue::new_object();
 
 		json[MSG_TYPE_U32] = tp.into();
@@ -743,29 +757,39 @@ impl ProtocolImpl for Sgip {
 		json[MSG_ID] = sgip_msg_id_u64_to_str(node_id, seq_id).into();
 
 		json[SRC_ID] = load_utf8_string(buf, 21).into(); //src_id 21
-		buf.advance(21); //ChargeNumber
+		json[CHARGE_ID] = load_utf8_string(buf, 21).into(); //ChargeNumber 21
 		let dest_len = buf.get_u8();//UserCount 1
+		//UserNumber 21 加上到MessageLength为止的 72
+		check_len(buf, dest_len as usize * 21 + 72)?;
 		//dest_ids 21
 		let mut dest_ids: Vec<String> = Vec::new();
 		for _i in 0..dest_len {
 			dest_ids.push(load_utf8_string(buf, 21));
 		}
 		json[DEST_IDS] = dest_ids.into();
-		buf.advance(65); //CorpId 5  ServiceType 10  FeeType 1 FeeValue 6 GivenValue 6 AgentFlag 1  MorelatetoMTFlag 1  Priority 1 ExpireTime 16 ScheduleTime 16 ReportFlag 1 TP_pid 1
+		buf.advance(15); //CorpId 5  ServiceType 10
+		json[FEE_TYPE] = buf.get_u8().into(); //FeeType 1
+		json[FEE_CODE] = load_utf8_string(buf, 6).into(); //FeeValue 6
+		buf.advance(7); //GivenValue 6 AgentFlag 1
+		json[MO_RELATE_MT] = buf.get_u8().into(); //MorelatetoMTFlag 1
+		json[MSG_LEVEL] = buf.get_u8().into(); //Priority 1
+		buf.advance(34); //ExpireTime 16 ScheduleTime 16 ReportFlag 1 TP_pid 1
 		let tp_udhi = buf.get_u8(); //是否长短信
 		let msg_fmt = buf.get_u8();
 		json[MSG_FMT] = msg_fmt.into(); //Msg_Fmt 1
 
 		buf.advance(1); //MessageType 1
 		//长短信的处理 tp_udhi != 0 说明是长短信
-		let msg_content_len = buf.get_u32(); //Msg_Length	4
-		decode_msg_content(buf, msg_fmt, msg_content_len as u8, &mut json, tp_udhi != 0)?;
+		let msg_content_len = get_msg_content_len(buf)?; //Msg_Length	4
+		decode_msg_content(buf, msg_fmt, msg_content_len, &mut json, tp_udhi != 0, Alphabet::Ascii)?;
 
 		Ok(json)
 	}
 
 	///实际的解码连接消息
 	fn decode_connect(&self, buf: &mut BytesMut, node_id: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		//Sequence 8 LoginType 1 LoginName 16 LoginPassword 16
+		check_len(buf, 41)?;
 		let mut json = JsonValue::new_object();
 		json[MSG_TYPE_U32] = tp.into();
 
@@ -803,7 +827,38 @@ impl Sgip {
 				.length_field_offset(0)
 				.length_field_length(4)
 				.length_adjustment(-4)
+				.max_frame_length(SGIP_MAX_LEN)
 				.new_codec(),
 		}
 	}
 }	
+
+///SGIP1.2的回复Result对照表.88为运营商使用的流量控制错
+static SGIP_RESULT_TABLE: &[ResultCode] = &[
+	(0, SmsStatus::Success, false, "成功"),
+	(1, SmsStatus::AuthError, false, "非法登录"),
+	(2, SmsStatus::OtherError, false, "重复登录"),
+	(3, SmsStatus::SystemBusy, true, "连接过多"),
+	(4, SmsStatus::AuthError, false, "登录类型错"),
+	(5, SmsStatus::MessageError, false, "参数格式错"),
+	(6, SmsStatus::DestIdError, false, "非法手机号码"),
+	(7, SmsStatus::MessageError, false, "消息ID错"),
+	(8, SmsStatus::LengthError, false, "信息长度错"),
+	(9, SmsStatus::SeqError, false, "非法序列号"),
+	(10, SmsStatus::CommandError, false, "非法操作GNS"),
+	(11, SmsStatus::SystemBusy, true, "节点忙"),
+	(21, SmsStatus::DestIdError, false, "目的地址不可达"),
+	(22, SmsStatus::OtherError, false, "路由错"),
+	(23, SmsStatus::OtherError, false, "路由不存在"),
+	(24, SmsStatus::FeeTermError, false, "计费号码无效"),
+	(25, SmsStatus::OtherError, true, "用户不能通信"),
+	(26, SmsStatus::OtherError, true, "手机内存不足"),
+	(27, SmsStatus::DestIdError, false, "手机不支持短消息"),
+	(28, SmsStatus::OtherError, true, "手机接收短消息出现错误"),
+	(29, SmsStatus::DestIdError, false, "不知道的用户"),
+	(30, SmsStatus::CommandError, false, "不提供此功能"),
+	(31, SmsStatus::PermissionError, false, "非法设备"),
+	(32, SmsStatus::SystemBusy, true, "系统失败"),
+	(33, SmsStatus::SystemBusy, true, "短信中心队列满"),
+	(88, SmsStatus::TrafficRestrictions, true, "流量控制错"),
+];
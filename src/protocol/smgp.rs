@@ -1,17 +1,21 @@
 use tokio_util::codec::{LengthDelimitedCodec, Decoder};
-use crate::protocol::implements::{ProtocolImpl, get_time, fill_bytes_zero, load_utf8_string, decode_msg_content, smgp_msg_id_buf_to_str, smgp_msg_id_str_to_buf};
+use crate::protocol::implements::{ProtocolImpl, check_len, decode_error_json, get_time, fill_bytes_zero, load_utf8_string, decode_msg_content, smgp_msg_id_buf_to_str, smgp_msg_id_str_to_buf, create_smgp_msg_id};
 use bytes::{BytesMut, BufMut, Buf};
 use tokio::io;
-use crate::protocol::{SmsStatus, MsgType};
+use crate::protocol::{SmsStatus, MsgType, ResultCode};
 use json::JsonValue;
-use crate::protocol::names::{LOGIN_NAME,IS_REPORT,PASSAGE_MSG_ID, PASSWORD, VERSION, MSG_TYPE_U32, SEQ_ID, AUTHENTICATOR, MSG_CONTENT, SERVICE_ID, VALID_TIME, AT_TIME, SRC_ID, DEST_IDS, SEQ_IDS, MSG_FMT, MSG_ID, RESULT, DEST_ID, SMGP_RECEIVE_TIME, SUBMIT_TIME, DONE_TIME, STATE, ERROR_CODE, TIMESTAMP, MSG_IDS};
+use crate::protocol::names::{LOGIN_NAME,IS_REPORT,PASSAGE_MSG_ID, PASSWORD, VERSION, MSG_TYPE_U32, SEQ_ID, AUTHENTICATOR, SERVICE_ID, VALID_TIME, AT_TIME, SRC_ID, DEST_IDS, SEQ_IDS, MSG_FMT, MSG_ID, RESULT, DEST_ID, SMGP_RECEIVE_TIME, SUBMIT_TIME, DONE_TIME, STATE, ERROR_CODE, TIMESTAMP, MSG_IDS};
 use crate::protocol::msg_type::MsgType::{Connect, SubmitResp};
 use std::io::Error;
 use crate::global::{get_sequence_id, FILL_ZERO};
-use crate::global::ISMG_ID;
 
-use super::implements::encode_msg_content;
-use super::names::SPEED_LIMIT;
+use super::implements::{Alphabet, get_msg_content_code, get_tp_pid, hex_to_bytes, split_msg_content};
+use super::names::{CHARGE_ID, CHARGE_TERM_PSEUDO, FEE_CODE, FEE_TYPE, FEE_USER_TYPE, FIXED_FEE, MSG_LEVEL, PK_NUMBER, PK_TOTAL, DEST_TERM_PSEUDO, LINK_ID, MSG_SRC, M_SERVICE_ID, SPEED_LIMIT, SRC_TERM_PSEUDO, TLVS, TP_PID};
+
+///SMGP的消息头长度(Packet_Length + Request_ID + Sequence_ID)
+const SMGP_HEAD_LEN: usize = 12;
+///SMGP消息的最大长度.100个接收号码加上TLV也不会超过这个长度
+const SMGP_MAX_LEN: usize = 4096;
 
 ///Sgip协议的处理
 #[derive(Debug, Default)]
@@ -23,7 +27,8 @@ pub struct Smgp30 {
 
 impl ProtocolImpl for Smgp30 {
 	fn get_framed(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
-		self.length_codec.decode(buf)
+		let frame = self.length_codec.decode(buf);
+		self.check_frame(frame, SMGP_HEAD_LEN)
 	}
 
 	fn get_type_id(&self, t: MsgType) -> u32 {
@@ -73,6 +78,7 @@ impl ProtocolImpl for Smgp30 {
 			SmsStatus::TrafficRestrictions => 134,
 			SmsStatus::OtherError => 5,
 			SmsStatus::UNKNOWN => 99,
+			_ => self.get_result_id(status).unwrap_or(5),
 		}
 	}
 
@@ -89,6 +95,10 @@ impl ProtocolImpl for Smgp30 {
 		}
 	}
 
+	fn get_result_table(&self) -> &'static [ResultCode] {
+		SMGP_RESULT_TABLE
+	}
+
 	///通过给定的账号密码。计算实际向客户发送的消息，也是用来进行校验密码是否正确。
 	fn get_auth(&self, sp_id: &str, password: &str, timestamp: u32) -> [u8; 16] {
 		let time_str = format!("{:010}", timestamp);
@@ -297,17 +307,9 @@ impl ProtocolImpl for Smgp30 {
 			Some(v) => v
 		};
 
-		let msg_content = match json[MSG_CONTENT].as_str() {
-			None => {
-				log::error!("没有内容字串.退出..json:{}", json);
-				return Err(io::Error::new(io::ErrorKind::NotFound, "没有内容字串"));
-			}
-			Some(v) => v
-		};
-
 		let msg_fmt = json[MSG_FMT].as_u8().unwrap_or(8);
 		//编码以后的消息内容
-		let msg_content_code = match encode_msg_content(msg_fmt,msg_content) {
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_fmt, json, Alphabet::Ascii) {
 			Ok(v) => v,
 			Err(e) => {
 				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
@@ -334,25 +336,35 @@ impl ProtocolImpl for Smgp30 {
 		let msg_content_len = msg_content_code.len();
 
 		let mut msg_content_head_len: usize = 0;
-		let mut one_content_len: usize = 140;
+		//按字符集拆分以后的每一条内容
+		let contents = split_msg_content(msg_fmt, &msg_content_code, Alphabet::Ascii);
 		let mut msg_content_seq_id: u8 = 0;
-		let mut tlv_len: usize = 0;
-		let mut tlvs = Vec::new();
+		let mut tlvs = get_tlvs_from_json(json);
+
+		//二进制短信需要的TP_pid和用户自带UDH时的TP_udhi
+		let tp_pid = get_tp_pid(msg_fmt, json);
+		if tp_pid != 0 {
+			tlvs.push(SmgpTLV::TPPid(tp_pid));
+		}
 
 		//整个消息的长度
-		let sms_len = if msg_content_len <= 140 {
+		let sms_len = if contents.len() == 1 {
 			1 as usize
 		} else {
 			msg_content_head_len = 6;
-			one_content_len = one_content_len - 6;
 			msg_content_seq_id = get_sequence_id(1) as u8;
 
-			//增加一个可选项.
-			tlv_len += 5;
+			contents.len()
+		};
+
+		if has_udh || sms_len > 1 {
 			tlvs.push(SmgpTLV::TPUdhi(1));
+		}
 
-			((msg_content_len as f32) / one_content_len as f32).ceil() as usize
-		};
+		//每一条都一样的tlv.先编码好
+		let mut tlv_buf = BytesMut::new();
+		tlvs.iter().for_each(|tlv| tlv.encode(&mut tlv_buf));
+		let tlv_len = tlv_buf.len();
 
 		//长短信的话,一次性生成多条记录
 		//77是除开内容之后所有长度加在一起
@@ -361,13 +373,7 @@ impl ProtocolImpl for Smgp30 {
 		let mut dst = BytesMut::with_capacity(total_len);
 		let mut seq_ids = Vec::with_capacity(sms_len);
 
-		for i in 0..sms_len {
-			let this_msg_content = if i == sms_len - 1 {
-				&msg_content_code[(i * one_content_len)..msg_content_code.len()]
-			} else {
-				&msg_content_code[(i * one_content_len)..((i + 1) * one_content_len)]
-			};
-
+		for (i, this_msg_content) in contents.iter().enumerate() {
 			dst.put_u32((89 + msg_content_head_len + this_msg_content.len() + tlv_len) as u32);
 			dst.put_u32(self.get_type_id(MsgType::Deliver));
 			let seq_id = get_sequence_id(1);
@@ -393,8 +399,7 @@ impl ProtocolImpl for Smgp30 {
 			}
 			dst.extend_from_slice(&this_msg_content[..]); //Msg_Content
 			dst.extend_from_slice(&FILL_ZERO[0..8]); //Reserve
-
-			if !tlvs.is_empty() { self.coder_tlv(&mut dst, &tlvs,i + 1); }
+			dst.extend_from_slice(&tlv_buf);
 		}
 
 		json[SEQ_IDS] = seq_ids.into();
@@ -402,14 +407,6 @@ impl ProtocolImpl for Smgp30 {
 	}
 
 	fn encode_submit(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
-		let msg_content = match json[MSG_CONTENT].as_str() {
-			None => {
-				log::error!("没有内容字串.退出..json:{}", json);
-				return Err(io::Error::new(io::ErrorKind::NotFound, "没有内容字串"));
-			}
-			Some(v) => v
-		};
-
 		//进行一下检测
 		if !json[MSG_IDS].is_array() {
 			log::error!("没有msg_ids.退出..json:{}", json);
@@ -419,7 +416,7 @@ impl ProtocolImpl for Smgp30 {
 		let msg_fmt = json[MSG_FMT].as_u8().unwrap_or(8);
 
 		//编码以后的消息内容
-		let msg_content_code = match encode_msg_content(msg_fmt,msg_content) {
+		let (msg_content_code, has_udh) = match get_msg_content_code(msg_fmt, json, Alphabet::Ascii) {
 			Ok(v) => v,
 			Err(e) => {
 				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
@@ -467,44 +464,62 @@ impl ProtocolImpl for Smgp30 {
 		let msg_content_len = msg_content_code.len();
 
 		let mut msg_content_head_len: usize = 0;
-		let mut one_content_len: usize = 140;
+		//按字符集拆分以后的每一条内容
+		let contents = split_msg_content(msg_fmt, &msg_content_code, Alphabet::Ascii);
 		let mut msg_content_seq_id: u8 = 0;
-		let mut tlv_len: usize = 0;
-		let mut tlvs = Vec::new();
+		let mut tlvs = get_tlvs_from_json(json);
+
+		//二进制短信需要的TP_pid和用户自带UDH时的TP_udhi
+		let tp_pid = get_tp_pid(msg_fmt, json);
+		if tp_pid != 0 {
+			tlvs.push(SmgpTLV::TPPid(tp_pid));
+		}
 
 		//整个消息的长度
-		let sms_len = if msg_content_len <= 140 {
+		let sms_len = if contents.len() == 1 {
 			1 as usize
 		} else {
 			msg_content_head_len = 6;
-			one_content_len = one_content_len - 6;
 			msg_content_seq_id = get_sequence_id(1) as u8;
 
-			//增加可选项.
-			tlv_len += 15;
-			tlvs.push(SmgpTLV::TPUdhi(1));
-			tlvs.push(SmgpTLV::PkTotal(((msg_content_len as f32) / one_content_len as f32).ceil() as u8));
-			tlvs.push(SmgpTLV::PkNumber);
+			tlvs.push(SmgpTLV::PkTotal(contents.len() as u8));
 
-			((msg_content_len as f32) / one_content_len as f32).ceil() as usize
+			contents.len()
 		};
 
+		//计费相关.没有指定时使用原来的默认值
+		let msg_level = json[MSG_LEVEL].as_u8().unwrap_or(2);
+		let fee_type = json[FEE_TYPE].as_str().unwrap_or("00");
+		let fee_code = json[FEE_CODE].as_str().unwrap_or("000000");
+		let fixed_fee = json[FIXED_FEE].as_str().unwrap_or("000000");
+		let charge_id = json[CHARGE_ID].as_str().unwrap_or("");
+		if let Some(v) = json[FEE_USER_TYPE].as_u8() {
+			tlvs.push(SmgpTLV::ChargeUserType(v));
+		}
+		//不是自己拆分的.使用指定的Pk_total和Pk_number
+		if let (1, Some(pk_total), Some(pk_number)) = (sms_len, json[PK_TOTAL].as_u8(), json[PK_NUMBER].as_u8()) {
+			tlvs.push(SmgpTLV::PkTotal(pk_total));
+			tlvs.push(SmgpTLV::PkNumber(pk_number));
+		}
+
+		if has_udh || sms_len > 1 {
+			tlvs.push(SmgpTLV::TPUdhi(1));
+		}
+
+		//每一条都一样的tlv.先编码好.长短信每一条另外加上5个字节的PkNumber
+		let mut tlv_buf = BytesMut::new();
+		tlvs.iter().for_each(|tlv| tlv.encode(&mut tlv_buf));
+		let tlv_len = tlv_buf.len() + if sms_len > 1 { 5 } else { 0 };
+
 		//长短信的话,一次性生成多条记录
 		//126是除开内容\发送号码之后所有长度加在一起 6是长短信消息头长度
-		//还需要增加相应的tlv的长度.这里只有一个.
 		let total_len = sms_len * (126 + dest_ids.len() * 21 + msg_content_head_len + tlv_len) + msg_content_len;
 
 		// 126 + msg_content_len + dest_ids.len() * 21;
 		let mut dst = BytesMut::with_capacity(total_len);
 		let mut seq_ids = Vec::with_capacity(sms_len);
 
-		for i in 0..sms_len {
-			let this_msg_content = if i == sms_len - 1 {
-				&msg_content_code[(i * one_content_len)..msg_content_code.len()]
-			} else {
-				&msg_content_code[(i * one_content_len)..((i + 1) * one_content_len)]
-			};
-
+		for (i, this_msg_content) in contents.iter().enumerate() {
 			dst.put_u32((126 + dest_ids.len() * 21 + msg_content_head_len + this_msg_content.len() + tlv_len) as u32);
 			dst.put_u32(self.get_type_id(MsgType::Submit));
 			let seq_id = get_sequence_id(dest_ids.len() as u32);
@@ -513,16 +528,16 @@ impl ProtocolImpl for Smgp30 {
 
 			dst.put_u8(6); //MsgType
 			dst.put_u8(1); //NeedReport
-			dst.put_u8(2); //Priority
+			dst.put_u8(msg_level); //Priority
 			fill_bytes_zero(&mut dst, service_id, 10);//Service_Id
-			dst.extend_from_slice("00".as_bytes());//FeeType
-			dst.extend_from_slice("000000".as_bytes());//FeeCode
-			dst.extend_from_slice("000000".as_bytes());//FixedFee
+			fill_bytes_zero(&mut dst, fee_type, 2);//FeeType
+			fill_bytes_zero(&mut dst, fee_code, 6);//FeeCode
+			fill_bytes_zero(&mut dst, fixed_fee, 6);//FixedFee
 			dst.put_u8(msg_fmt); //MsgFormat
 			fill_bytes_zero(&mut dst, valid_time, 17);  //valid_time
 			fill_bytes_zero(&mut dst, at_time, 17);  //at_time
 			fill_bytes_zero(&mut dst, src_id, 21);  //src_id
-			dst.extend_from_slice(&FILL_ZERO[0..21]);//ChargeTermID
+			fill_bytes_zero(&mut dst, charge_id, 21);//ChargeTermID
 			dst.put_u8(dest_ids.len() as u8); //DestTermIDCount
 			dest_ids.iter().for_each(|dest_id| fill_bytes_zero(&mut dst, dest_id, 21));  //dest_id
 			dst.put_u8(this_msg_content.len() as u8 + msg_content_head_len as u8); //Msg_Length
@@ -535,9 +550,11 @@ impl ProtocolImpl for Smgp30 {
 				dst.put_u8((i + 1) as u8);
 			}
 			dst.extend_from_slice(&this_msg_content[..]); //Msg_Content
-			dst.extend_from_slice(&FILL_ZERO[0..8]); //LinkID
-
-			if !tlvs.is_empty() { self.coder_tlv(&mut dst, &tlvs,i + 1); }
+			dst.extend_from_slice(&FILL_ZERO[0..8]); //Reserve
+			dst.extend_from_slice(&tlv_buf);
+			if sms_len > 1 {
+				SmgpTLV::PkNumber((i + 1) as u8).encode(&mut dst);
+			}
 		}
 
 		json[SEQ_IDS] = seq_ids.into();
@@ -550,6 +567,8 @@ impl ProtocolImpl for Smgp30 {
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
 
+		//MsgID 10 Status 4
+		check_len(buf, 14)?;
 		let msg_id = smgp_msg_id_buf_to_str(&buf.split_to(10));
 		json[MSG_ID] = msg_id.into();
 
@@ -563,8 +582,14 @@ impl ProtocolImpl for Smgp30 {
 		Ok(json)
 	}
 
-	fn is_speed_limit(&self, code: u32) -> bool{
-		return code == 75
+	///解码失败时MsgID在消息体的开头.deliver_resp需要带回
+	fn decode_error(&self, head: &[u8], seq: u32, tp: u32, e: io::Error) -> JsonValue {
+		let mut json = decode_error_json(seq, tp, e);
+		if head.len() >= 10 {
+			json[MSG_ID] = smgp_msg_id_buf_to_str(&BytesMut::from(&head[..10])).into();
+		}
+
+		json
 	}
 
 	fn decode_deliver(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
@@ -572,6 +597,8 @@ impl ProtocolImpl for Smgp30 {
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
 
+		//到MsgLength为止的固定长度
+		check_len(buf, 69)?;
 		let msg_id = smgp_msg_id_buf_to_str(&buf.split_to(10));
 		json[MSG_ID] = msg_id.into();
 		let is_report = buf.get_u8(); //Registered_Delivery	1
@@ -582,23 +609,21 @@ impl ProtocolImpl for Smgp30 {
 		json[DEST_ID] = load_utf8_string(buf, 21).into(); //dest_id 21
 
 		let msg_content_len = buf.get_u8(); //Msg_Length	1
+		//MsgContent 加上 Reserve 8
+		check_len(buf, msg_content_len as usize + 8)?;
 		let mut content_buf = buf.split_to(msg_content_len as usize);// MsgContent
 		buf.advance(8); //Reserve	8
 
-		let mut need_tlvs = vec![SmgpTLV::TPUdhi(0)];
-		self.decode_tlvs(buf, &mut need_tlvs);
-		let is_long_sms = if let SmgpTLV::TPUdhi(v) = need_tlvs[0] {
-			v == 1
-		} else {
-			false
-		};
+		let is_long_sms = put_tlvs_to_json(decode_tlvs(buf), &mut json);
 
 		if is_report == 0 {
 			json[MSG_FMT] = msg_fmt.into(); //Msg_Fmt 1
-			decode_msg_content(&mut content_buf, msg_fmt, msg_content_len, &mut json, is_long_sms)?;
+			decode_msg_content(&mut content_buf, msg_fmt, msg_content_len, &mut json, is_long_sms, Alphabet::Ascii)?;
 		} else {
 				// id:XXXXXXXXXX sub:000 dlvrd:000 Submit_Date:0901151559 Done_Date:0901151559 Stat:DELIVRD err:000 text:
 			json[IS_REPORT] = true.into(); //状态报告增加.
+			//状态报告的内容到err为止的固定长度
+			check_len(&content_buf, 96)?;
 
 			content_buf.advance(3);//名字："id:"
 
@@ -632,38 +657,38 @@ impl ProtocolImpl for Smgp30 {
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
 
-		buf.advance(27); //MsgType 1 NeedReport 1 Priority 1 ServiceID	10 FeeType	2 FeeCode	6 FixedFee	6
+		//到DestTermIDCount为止的固定长度
+		check_len(buf, 105)?;
+		buf.advance(2); //MsgType 1 NeedReport 1
+		json[MSG_LEVEL] = buf.get_u8().into(); //Priority 1
+		buf.advance(10); //ServiceID	10
+		json[FEE_TYPE] = load_utf8_string(buf, 2).into(); //FeeType	2
+		json[FEE_CODE] = load_utf8_string(buf, 6).into(); //FeeCode	6
+		json[FIXED_FEE] = load_utf8_string(buf, 6).into(); //FixedFee	6
 		let msg_fmt = buf.get_u8();
 		json[MSG_FMT] = msg_fmt.into(); //Msg_Fmt 1
 		json[VALID_TIME] = load_utf8_string(buf, 17).into(); //valid_time 17
 		json[AT_TIME] = load_utf8_string(buf, 17).into(); //at_time 17
 		json[SRC_ID] = load_utf8_string(buf, 21).into(); //src_id 21
-		buf.advance(21);//ChargeTermID	21
+		json[CHARGE_ID] = load_utf8_string(buf, 21).into();//ChargeTermID	21
 		let dest_len = buf.get_u8(); //DestUsr_tl 1
-		//dest_id 32
+		//DestTermID 21 MsgLength 1
+		check_len(buf, dest_len as usize * 21 + 1)?;
+		//dest_id 21
 		let mut dest_ids: Vec<String> = Vec::new();
 		for _i in 0..dest_len {
 			dest_ids.push(load_utf8_string(buf, 21));
 		}
 
 		let msg_content_len = buf.get_u8() as usize; //Msg_Length	1
-		let mut content_buf = if msg_content_len < buf.len() {
-			buf.split_to(msg_content_len)
-		} else {
-			log::warn!("消息结构出错.没有足够的长度读消息.");
-			return Err(io::Error::new(io::ErrorKind::Other, "没有足够的长度读消息."));
-		};
+		//MsgContent 加上 Reserve 8
+		check_len(buf, msg_content_len + 8)?;
+		let mut content_buf = buf.split_to(msg_content_len);
 		buf.advance(8); //Reserve 8
 
-		let mut need_tlvs = vec![SmgpTLV::TPUdhi(0)];
-		self.decode_tlvs(buf, &mut need_tlvs);
-		let is_long_sms = if let SmgpTLV::TPUdhi(v) = need_tlvs[0] {
-			v == 1
-		} else {
-			false
-		};
+		let is_long_sms = put_tlvs_to_json(decode_tlvs(buf), &mut json);
 
-		decode_msg_content(&mut content_buf, msg_fmt, msg_content_len as u8, &mut json, is_long_sms)?;
+		decode_msg_content(&mut content_buf, msg_fmt, msg_content_len as u8, &mut json, is_long_sms, Alphabet::Ascii)?;
 		Ok(json)
 	}
 
@@ -673,6 +698,8 @@ impl ProtocolImpl for Smgp30 {
 		json[MSG_TYPE_U32] = tp.into();
 		json[SEQ_ID] = seq.into();
 
+		//ClientID 8 AuthenticatorClient 16 LoginMode 1 TimeStamp 4 Version 1
+		check_len(buf, 30)?;
 		json[LOGIN_NAME] = load_utf8_string(buf, 8).into();
 		// json[AUTHENTICATOR] = copy_to_bytes(buf, 16).as_ref().into();
 		//现在取2次,相当于只保存8位.
@@ -701,6 +728,7 @@ impl Smgp30 {
 				.length_field_offset(0)
 				.length_field_length(4)
 				.length_adjustment(-4)
+				.max_frame_length(SMGP_MAX_LEN)
 				.new_codec(),
 		}
 	}
@@ -708,128 +736,118 @@ impl Smgp30 {
 	//根据跳过数量创建msgId。len:当前创建完成后，步进多少的值
 	//必然返回10位长度。
 	fn create_msg_id(&self, len: u32) -> BytesMut {
-		let mut buf = BytesMut::with_capacity(10);
-		unsafe{
-			buf.set_len(buf.capacity());
-		}
+		create_smgp_msg_id(len)
+	}
+}
 
-		let ismg = *ISMG_ID;
-		for i in 0..3 {
-			let d = ((ismg >> ((i * 2 + 1) * 4) & 0xF) << 4) | ismg >> (i * 2 * 4) & 0xF ;
-			buf[2 - i] = d as u8;
+///读取剩下的所有tlv.
+fn decode_tlvs(buf: &mut BytesMut) -> Vec<SmgpTLV> {
+	let mut tlvs = Vec::new();
+
+	//tag和length共4位.值可以为空
+	while buf.len() >= 4 {
+		let tag = buf.get_u16();
+		let length = buf.get_u16() as usize;
+		if buf.len() < length {
+			log::error!("解码tlv失败..没有足够的长度.tag:{:X}", tag);
+			break;
 		}
 
-		let mut time = get_time() / 100; //去掉秒
-		for i in 0..4 {
-			let d = ((time / 10) % 10) << 4 | time % 10;
-			time  /= 100;
-			buf[6 - i] = d as u8;
-		}
+		tlvs.push(SmgpTLV::decode(tag, buf.split_to(length)));
+	}
 
-		let mut seq = get_sequence_id(len) % 1000000;
-		for i in 0..3 {
-			let d = ((seq / 10) % 10) << 4 | seq % 10;
-			seq  /= 100;
-			buf[9 - i] = d as u8;
-		}
+	tlvs
+}
 
-		buf
-	}
-
-	fn coder_tlv(&self, buf: &mut BytesMut, tlvs: &Vec<SmgpTLV>,sms_len: usize) {
-		for tlv in tlvs.iter() {
-			buf.put_u16(tlv.get_u16());
-			match tlv {
-				SmgpTLV::TPPid(v) |
-				SmgpTLV::TPUdhi(v) |
-				SmgpTLV::ChargeUserType(v) |
-				SmgpTLV::ChargeTermType(v) |
-				SmgpTLV::DestTermType(v) |
-				SmgpTLV::PkTotal(v) |
-				SmgpTLV::SubmitMsgType(v) |
-				SmgpTLV::SPDealReslt(v) |
-				SmgpTLV::SrcTermType(v) |
-				SmgpTLV::NodesCount(v) |
-				SmgpTLV::SrcType(v) => {
-					buf.put_u16(1);
-					buf.put_u8(*v);
-				}
-				SmgpTLV::PkNumber => {
-					buf.put_u16(1);
-					buf.put_u8(sms_len as u8);
-				}
-				SmgpTLV::DestTermPseudo(b) |
-				SmgpTLV::MsgSrc(b) |
-				SmgpTLV::MServiceID(b) |
-				SmgpTLV::SrcTermPseudo(b) |
-				SmgpTLV::LinkID(b) |
-				SmgpTLV::ChargeTermPseudo(b) => {
-					buf.put_u16(b.len() as u16);
-					buf.extend_from_slice(b.as_ref());
+///把收到的tlv放入json.返回是否带有UDH.没有单独字段的tlv以16进制放入tlvs里面
+fn put_tlvs_to_json(tlvs: Vec<SmgpTLV>, json: &mut JsonValue) -> bool {
+	let mut has_udh = false;
+	for tlv in tlvs.into_iter() {
+		match tlv {
+			SmgpTLV::TPUdhi(v) => has_udh = v == 1,
+			SmgpTLV::TPPid(v) => {
+				if v != 0 {
+					json[TP_PID] = v.into();
 				}
 			}
+			SmgpTLV::LinkID(v) => json[LINK_ID] = v.into(),
+			SmgpTLV::MsgSrc(v) => json[MSG_SRC] = v.into(),
+			SmgpTLV::MServiceID(v) => json[M_SERVICE_ID] = v.into(),
+			SmgpTLV::SrcTermPseudo(v) => json[SRC_TERM_PSEUDO] = v.into(),
+			SmgpTLV::DestTermPseudo(v) => json[DEST_TERM_PSEUDO] = v.into(),
+			SmgpTLV::ChargeTermPseudo(v) => json[CHARGE_TERM_PSEUDO] = v.into(),
+			SmgpTLV::ChargeUserType(v) => json[FEE_USER_TYPE] = v.into(),
+			SmgpTLV::PkTotal(v) => json[PK_TOTAL] = v.into(),
+			SmgpTLV::PkNumber(v) => json[PK_NUMBER] = v.into(),
+			//号码类型跟着伪码走
+			SmgpTLV::SrcTermType(_) |
+			SmgpTLV::DestTermType(_) |
+			SmgpTLV::ChargeTermType(_) => {}
+			_ => json[TLVS][format!("{:04X}", tlv.get_u16())] = format!("{:X}", tlv.value()).into(),
 		}
 	}
 
-	fn decode_tlvs(&self, buf: &mut BytesMut, tlvs: &mut Vec<SmgpTLV>) {
-		//最少读到5位才够
-		while buf.len() >= 5 {
-			let key = buf.get_u16();
-			let length = buf.get_u16();
-			if buf.len() < length as usize {
-				log::error!("解码tlv失败..没有足够的长度.");
-				return;
-			}
+	has_udh
+}
 
-			if let Some(tlv) = tlvs.iter_mut().find(|item| item.get_u16() == key) {
-				match tlv {
-					SmgpTLV::TPPid(_) |
-					SmgpTLV::TPUdhi(_) |
-					SmgpTLV::ChargeUserType(_) |
-					SmgpTLV::ChargeTermType(_) |
-					SmgpTLV::DestTermType(_) |
-					SmgpTLV::PkTotal(_) |
-					SmgpTLV::PkNumber |
-					SmgpTLV::SubmitMsgType(_) |
-					SmgpTLV::SPDealReslt(_) |
-					SmgpTLV::SrcTermType(_) |
-					SmgpTLV::NodesCount(_) |
-					SmgpTLV::SrcType(_) => {
-						*tlv = SmgpTLV::from_u8(key, buf.get_u8());
-					}
-					SmgpTLV::DestTermPseudo(_) |
-					SmgpTLV::MsgSrc(_) |
-					SmgpTLV::MServiceID(_) |
-					SmgpTLV::SrcTermPseudo(_) |
-					SmgpTLV::LinkID(_) |
-					SmgpTLV::ChargeTermPseudo(_) => {
-						*tlv = SmgpTLV::from_slice(key, buf.split_to(length as usize));
-					}
-				}
-			}
+///取出json里面需要发送的tlv.有伪码的同时发送号码类型为伪码
+fn get_tlvs_from_json(json: &JsonValue) -> Vec<SmgpTLV> {
+	let mut result = Vec::new();
+
+	if let Some(v) = json[LINK_ID].as_str() {
+		result.push(SmgpTLV::LinkID(v.to_owned()));
+	}
+	if let Some(v) = json[MSG_SRC].as_str() {
+		result.push(SmgpTLV::MsgSrc(v.to_owned()));
+	}
+	if let Some(v) = json[M_SERVICE_ID].as_str() {
+		result.push(SmgpTLV::MServiceID(v.to_owned()));
+	}
+	if let Some(v) = json[SRC_TERM_PSEUDO].as_str() {
+		result.push(SmgpTLV::SrcTermType(1));
+		result.push(SmgpTLV::SrcTermPseudo(v.to_owned()));
+	}
+	if let Some(v) = json[DEST_TERM_PSEUDO].as_str() {
+		result.push(SmgpTLV::DestTermType(1));
+		result.push(SmgpTLV::DestTermPseudo(v.to_owned()));
+	}
+	if let Some(v) = json[CHARGE_TERM_PSEUDO].as_str() {
+		result.push(SmgpTLV::ChargeTermType(1));
+		result.push(SmgpTLV::ChargeTermPseudo(v.to_owned()));
+	}
+
+	for (tag, value) in json[TLVS].entries() {
+		match (u16::from_str_radix(tag, 16), value.as_str().and_then(hex_to_bytes)) {
+			(Ok(tag), Some(value)) => result.push(SmgpTLV::Unknown(tag, BytesMut::from(&value[..]))),
+			_ => log::warn!("tlv格式错误.跳过.tag:{}.value:{}", tag, value),
 		}
 	}
+
+	result
 }
 
+///smgp 3.0的可选参数
 enum SmgpTLV {
 	TPPid(u8),
 	TPUdhi(u8),
+	LinkID(String),
 	ChargeUserType(u8),
-	LinkID(BytesMut),
 	ChargeTermType(u8),
-	ChargeTermPseudo(BytesMut),
+	ChargeTermPseudo(String),
 	DestTermType(u8),
-	DestTermPseudo(BytesMut),
+	DestTermPseudo(String),
 	PkTotal(u8),
-	PkNumber,
+	PkNumber(u8),
 	SubmitMsgType(u8),
 	SPDealReslt(u8),
 	SrcTermType(u8),
-	SrcTermPseudo(BytesMut),
+	SrcTermPseudo(String),
 	NodesCount(u8),
-	MsgSrc(BytesMut),
+	MsgSrc(String),
 	SrcType(u8),
-	MServiceID(BytesMut),
+	MServiceID(String),
+	///未识别的tlv.原样保存
+	Unknown(u16, BytesMut),
 }
 
 impl SmgpTLV {
@@ -837,14 +855,14 @@ impl SmgpTLV {
 		match self {
 			SmgpTLV::TPPid(_) => 0x0001,
 			SmgpTLV::TPUdhi(_) => 0x0002,
-			SmgpTLV::ChargeUserType(_) => 0x0004,
 			SmgpTLV::LinkID(_) => 0x0003,
+			SmgpTLV::ChargeUserType(_) => 0x0004,
 			SmgpTLV::ChargeTermType(_) => 0x0005,
 			SmgpTLV::ChargeTermPseudo(_) => 0x0006,
 			SmgpTLV::DestTermType(_) => 0x0007,
 			SmgpTLV::DestTermPseudo(_) => 0x0008,
 			SmgpTLV::PkTotal(_) => 0x0009,
-			SmgpTLV::PkNumber => 0x000A,
+			SmgpTLV::PkNumber(_) => 0x000A,
 			SmgpTLV::SubmitMsgType(_) => 0x000B,
 			SmgpTLV::SPDealReslt(_) => 0x000C,
 			SmgpTLV::SrcTermType(_) => 0x000D,
@@ -853,36 +871,123 @@ impl SmgpTLV {
 			SmgpTLV::MsgSrc(_) => 0x0010,
 			SmgpTLV::SrcType(_) => 0x0011,
 			SmgpTLV::MServiceID(_) => 0x0012,
+			SmgpTLV::Unknown(tag, _) => *tag,
 		}
 	}
 
-	fn from_u8(id: u16, v: u8) -> Self {
-		match id {
-			0x0001 => SmgpTLV::TPPid(v),
-			0x0002 => SmgpTLV::TPUdhi(v),
-			0x0004 => SmgpTLV::ChargeUserType(v),
-			0x0005 => SmgpTLV::ChargeTermType(v),
-			0x0007 => SmgpTLV::DestTermType(v),
-			0x0009 => SmgpTLV::PkTotal(v),
-			0x000A => SmgpTLV::PkNumber,
-			0x000B => SmgpTLV::SubmitMsgType(v),
-			0x000C => SmgpTLV::SPDealReslt(v),
-			0x000D => SmgpTLV::SrcTermType(v),
-			0x000F => SmgpTLV::NodesCount(v),
-			0x0011 => SmgpTLV::SrcType(v),
-			_ => { SmgpTLV::SrcType(v) }
+	///tlv的值.定长的字符串不够时补0
+	fn value(&self) -> BytesMut {
+		let mut value = BytesMut::new();
+		match self {
+			SmgpTLV::TPPid(v) |
+			SmgpTLV::TPUdhi(v) |
+			SmgpTLV::ChargeUserType(v) |
+			SmgpTLV::ChargeTermType(v) |
+			SmgpTLV::DestTermType(v) |
+			SmgpTLV::PkTotal(v) |
+			SmgpTLV::PkNumber(v) |
+			SmgpTLV::SubmitMsgType(v) |
+			SmgpTLV::SPDealReslt(v) |
+			SmgpTLV::SrcTermType(v) |
+			SmgpTLV::NodesCount(v) |
+			SmgpTLV::SrcType(v) => value.put_u8(*v),
+			SmgpTLV::LinkID(v) => fill_bytes_zero(&mut value, v, 20),
+			SmgpTLV::MsgSrc(v) => fill_bytes_zero(&mut value, v, 8),
+			SmgpTLV::MServiceID(v) => fill_bytes_zero(&mut value, v, 21),
+			SmgpTLV::ChargeTermPseudo(v) |
+			SmgpTLV::DestTermPseudo(v) |
+			SmgpTLV::SrcTermPseudo(v) => value.extend_from_slice(v.as_bytes()),
+			SmgpTLV::Unknown(_, v) => value.extend_from_slice(v),
 		}
+
+		value
+	}
+
+	fn encode(&self, buf: &mut BytesMut) {
+		let value = self.value();
+		buf.put_u16(self.get_u16());
+		buf.put_u16(value.len() as u16);
+		buf.extend_from_slice(&value);
 	}
 
-	fn from_slice(id: u16, v: BytesMut) -> Self {
-		match id {
-			0x0003 => SmgpTLV::LinkID(v),
-			0x0006 => SmgpTLV::ChargeTermPseudo(v),
-			0x0008 => SmgpTLV::DestTermPseudo(v),
-			0x000E => SmgpTLV::SrcTermPseudo(v),
-			0x0010 => SmgpTLV::MsgSrc(v),
-			0x0012 => SmgpTLV::MServiceID(v),
-			_ => { SmgpTLV::MServiceID(v) }
+	///长度不对的按未识别的处理
+	fn decode(tag: u16, value: BytesMut) -> Self {
+		let text = || String::from_utf8_lossy(&value).trim_end_matches(char::from(0)).to_owned();
+		match (tag, value.len()) {
+			(0x0001, 1) => SmgpTLV::TPPid(value[0]),
+			(0x0002, 1) => SmgpTLV::TPUdhi(value[0]),
+			(0x0003, _) => SmgpTLV::LinkID(text()),
+			(0x0004, 1) => SmgpTLV::ChargeUserType(value[0]),
+			(0x0005, 1) => SmgpTLV::ChargeTermType(value[0]),
+			(0x0006, _) => SmgpTLV::ChargeTermPseudo(text()),
+			(0x0007, 1) => SmgpTLV::DestTermType(value[0]),
+			(0x0008, _) => SmgpTLV::DestTermPseudo(text()),
+			(0x0009, 1) => SmgpTLV::PkTotal(value[0]),
+			(0x000A, 1) => SmgpTLV::PkNumber(value[0]),
+			(0x000B, 1) => SmgpTLV::SubmitMsgType(value[0]),
+			(0x000C, 1) => SmgpTLV::SPDealReslt(value[0]),
+			(0x000D, 1) => SmgpTLV::SrcTermType(value[0]),
+			(0x000E, _) => SmgpTLV::SrcTermPseudo(text()),
+			(0x000F, 1) => SmgpTLV::NodesCount(value[0]),
+			(0x0010, _) => SmgpTLV::MsgSrc(text()),
+			(0x0011, 1) => SmgpTLV::SrcType(value[0]),
+			(0x0012, _) => SmgpTLV::MServiceID(text()),
+			_ => SmgpTLV::Unknown(tag, value),
 		}
 	}
 }
+
+///SMGP3.0的回复Status对照表
+static SMGP_RESULT_TABLE: &[ResultCode] = &[
+	(0, SmsStatus::Success, false, "成功"),
+	(1, SmsStatus::SystemBusy, true, "系统忙"),
+	(2, SmsStatus::SystemBusy, true, "超过最大连接数"),
+	(10, SmsStatus::MessageError, false, "消息结构错"),
+	(11, SmsStatus::CommandError, false, "命令字错"),
+	(12, SmsStatus::SeqError, false, "序列号重复"),
+	(20, SmsStatus::AddError, false, "IP地址错"),
+	(21, SmsStatus::AuthError, false, "认证错"),
+	(22, SmsStatus::VersionError, false, "版本太高"),
+	(30, SmsStatus::MessageError, false, "非法消息类型(MsgType)"),
+	(31, SmsStatus::MessageError, false, "非法优先级(Priority)"),
+	(32, SmsStatus::FeeError, false, "非法资费类型(FeeType)"),
+	(33, SmsStatus::FeeError, false, "非法资费代码(FeeCode)"),
+	(34, SmsStatus::MessageError, false, "非法短消息格式(MsgFormat)"),
+	(35, SmsStatus::MessageError, false, "非法时间格式"),
+	(36, SmsStatus::LengthError, false, "非法短消息长度(MsgLength)"),
+	(37, SmsStatus::OtherError, false, "有效期已过"),
+	(38, SmsStatus::MessageError, false, "非法查询类别(QueryType)"),
+	(39, SmsStatus::OtherError, false, "路由错误"),
+	(40, SmsStatus::FeeError, false, "非法包月费/封顶费(FixedFee)"),
+	(41, SmsStatus::MessageError, false, "非法更新类型(UpdateType)"),
+	(42, SmsStatus::MessageError, false, "非法路由编号(RouteId)"),
+	(43, SmsStatus::ServiceError, false, "非法服务代码(ServiceId)"),
+	(44, SmsStatus::MessageError, false, "非法有效期(ValidTime)"),
+	(45, SmsStatus::MessageError, false, "非法定时发送时间(AtTime)"),
+	(46, SmsStatus::SrcIdError, false, "非法发送用户号码(SrcTermId)"),
+	(47, SmsStatus::DestIdError, false, "非法接收用户号码(DestTermId)"),
+	(48, SmsStatus::FeeTermError, false, "非法计费用户号码(ChargeTermId)"),
+	(49, SmsStatus::ServiceError, false, "非法SP服务代码(SPCode)"),
+	(56, SmsStatus::AddError, false, "非法源网关代码(SrcGatewayID)"),
+	(57, SmsStatus::MessageError, false, "非法查询号码(QueryTermID)"),
+	(58, SmsStatus::OtherError, false, "没有匹配路由"),
+	(59, SmsStatus::MessageError, false, "非法SP类型(SPType)"),
+	(60, SmsStatus::MessageError, false, "非法上一条路由编号(LastRouteID)"),
+	(61, SmsStatus::MessageError, false, "非法路由类型(RouteType)"),
+	(62, SmsStatus::MessageError, false, "非法目标网关代码(DestGatewayID)"),
+	(63, SmsStatus::MessageError, false, "非法目标网关IP(DestGatewayIP)"),
+	(64, SmsStatus::MessageError, false, "非法目标网关端口(DestGatewayPort)"),
+	(65, SmsStatus::MessageError, false, "非法路由号码段(TerminalIDStart)"),
+	(66, SmsStatus::MessageError, false, "非法路由号码段(TerminalIDEnd)"),
+	(67, SmsStatus::MessageError, false, "非法用户所属省代码(ProvinceCode)"),
+	(68, SmsStatus::MessageError, false, "非法用户类型(UserType)"),
+	(69, SmsStatus::CommandError, false, "本节点不支持路由更新"),
+	(70, SmsStatus::PermissionError, false, "非法SP企业代码(SPID)"),
+	(71, SmsStatus::PermissionError, false, "非法SP接入类型(SPAccessType)"),
+	(72, SmsStatus::OtherError, false, "路由信息更新失败"),
+	(73, SmsStatus::AuthError, false, "非法时间戳(Time)"),
+	(74, SmsStatus::ServiceError, false, "非法业务代码(MServiceID)"),
+	(75, SmsStatus::TrafficRestrictions, true, "SP禁止下发时段"),
+	(76, SmsStatus::PermissionError, false, "SP发送超过日流量"),
+	(77, SmsStatus::PermissionError, false, "SP帐号过有效期"),
+];
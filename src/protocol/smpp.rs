@@ -1,19 +1,758 @@
-use tokio_util::codec::{LengthDelimitedCodec, Decoder};
-use crate::protocol::implements::ProtocolImpl;
-use bytes::BytesMut;
+use bytes::{Buf, BufMut, BytesMut};
+use encoding::{DecoderTrap, Encoding};
+use encoding::all::ISO_8859_1;
+use json::JsonValue;
 use tokio::io;
+use tokio::io::Error;
+use tokio_util::codec::{Decoder, LengthDelimitedCodec};
 
-///Sgip协议的处理
+use crate::global::get_sequence_id;
+use crate::protocol::{MsgType, SmsStatus, ResultCode};
+use crate::protocol::gsm7;
+use crate::protocol::implements::{Alphabet, check_frame_len, check_len, cmpp_msg_id_u64_to_str, create_cmpp_msg_id, decode_msg_content, decode_msg_content_bytes, fill_c_string, get_msg_content_code, get_msg_content_limit, get_tp_pid, hex_to_bytes, load_c_string, put_udh_to_json, split_msg_content_by_len, ProtocolImpl};
+use crate::protocol::names::{AT_TIME, MSG_HEX, AUTHENTICATOR, CAN_WRITE, DEST_ID, DEST_IDS, DEST_NPI, DEST_TON, DONE_TIME, ERROR_CODE, IS_REPORT, LOGIN_NAME, LONG_SMS_MODE, LONG_SMS_NOW_NUMBER, LONG_SMS_REF, LONG_SMS_TOTAL, MSG_CONTENT, MSG_FMT, MSG_ID, MSG_IDS, MSG_TYPE_STR, MSG_TYPE_U32, PASSAGE_MSG_ID, PASSWORD, RESULT, SEQ_ID, SEQ_IDS, SERVICE_TYPE, SPEED_LIMIT, SRC_ID, SRC_NPI, SRC_TON, STATE, STATUS, SUBMIT_TIME, TIMESTAMP, TLVS, USER_MESSAGE_REFERENCE, VALID_TIME, VERSION};
+
+///smpp 3.4的版本号
+const SMPP_VERSION: u8 = 0x34;
+///smpp的消息头长度(command_length + command_id + command_status + sequence_number)
+const SMPP_HEAD_LEN: usize = 16;
+///smpp消息的最大长度.message_payload最大64K,加上submit_multi的254个接收号码
+const SMPP_MAX_LEN: usize = 72 * 1024;
+
+///三种绑定方式.都对应Connect
+const BIND_RECEIVER: u32 = 0x00000001;
+const BIND_TRANSMITTER: u32 = 0x00000002;
+const BIND_TRANSCEIVER: u32 = 0x00000009;
+///多个接收号码时使用
+const SUBMIT_MULTI: u32 = 0x00000021;
+///回执的标识位
+const RESP_FLAG: u32 = 0x80000000;
+
+///ESME_RINVCMDID 无效的命令
+const ESME_RINVCMDID: u32 = 0x00000003;
+///删除短信失败
+const ESME_RCANCELFAIL: u32 = 0x00000011;
+
+///Smpp协议3.4的处理
 #[derive(Debug, Default)]
 pub struct Smpp {
-	version:u32,
+	version: u32,
 	length_codec: LengthDelimitedCodec,
 }
 
 
 impl ProtocolImpl for Smpp {
-	fn get_framed(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>>  {
-		self.length_codec.decode(buf)
+	fn get_framed(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+		let frame = self.length_codec.decode(buf);
+		self.check_frame(frame, SMPP_HEAD_LEN)
+	}
+
+	///smpp收到不认识的命令时要回复generic_nack(ESME_RINVCMDID),不能断开连接.所以这里只检查长度
+	fn check_frame(&self, frame: io::Result<Option<BytesMut>>, head_len: usize) -> io::Result<Option<BytesMut>> {
+		check_frame_len(frame, head_len)
+	}
+
+	///smpp的消息头多一个command_status.所以这里单独处理.
+	fn decode_read_msg(&mut self, buf: &mut BytesMut) -> io::Result<Option<JsonValue>> {
+		match self.get_framed(buf) {
+			Ok(Some(mut buf)) => {
+				log::trace!("收到的消息. src:{:X}", buf);
+				if buf.len() < 12 {
+					log::error!("消息长度不够消息头长度.跳过.src:{:X}", buf);
+					return Ok(None);
+				}
+
+				let tp = buf.get_u32();
+				let status = buf.get_u32();
+				let seq = buf.get_u32();
+
+				let head = buf[..buf.len().min(10)].to_vec();
+				let (msg_type, mut msg) = match self.decode_pdu(&mut buf, status, seq, tp) {
+					Ok(v) => v,
+					//解码失败的消息交给上层回复消息结构错误.不断开连接
+					Err(e) => (self.get_type_enum(tp), self.decode_error(&head, seq, tp, e)),
+				};
+
+				let msg_type_str: &str = msg_type.into();
+				msg[MSG_TYPE_STR] = msg_type_str.into();
+				buf.truncate(0);
+
+				Ok(Some(msg))
+			}
+			Ok(None) => Ok(None),
+			//分帧出错时后面的数据已经无法对齐.返回错误让上层断开连接
+			Err(e) => Err(e)
+		}
+	}
+
+	fn get_result_table(&self) -> &'static [ResultCode] {
+		SMPP_RESULT_TABLE
+	}
+
+	fn get_type_id(&self, t: MsgType) -> u32 {
+		match t {
+			MsgType::Submit => 0x00000004,
+			MsgType::SubmitResp => 0x80000004,
+			MsgType::Deliver => 0x00000005,
+			MsgType::DeliverResp => 0x80000005,
+			//smpp的状态报告也是使用deliver_sm
+			MsgType::Report => 0x00000005,
+			MsgType::ReportResp => 0x80000005,
+			MsgType::Connect => BIND_TRANSCEIVER,
+			MsgType::ConnectResp => BIND_TRANSCEIVER | RESP_FLAG,
+			MsgType::Terminate => 0x00000006,
+			MsgType::TerminateResp => 0x80000006,
+			MsgType::Query => 0x00000003,
+			MsgType::QueryResp => 0x80000003,
+			MsgType::Cancel => 0x00000008,
+			MsgType::CancelResp => 0x80000008,
+			MsgType::ActiveTest => 0x00000015,
+			MsgType::ActiveTestResp => 0x80000015,
+			MsgType::GenericNack => 0x80000000,
+			_ => 0
+		}
+	}
+
+	fn get_type_enum(&self, v: u32) -> MsgType {
+		match v {
+			BIND_RECEIVER | BIND_TRANSMITTER | BIND_TRANSCEIVER => MsgType::Connect,
+			0x80000001 | 0x80000002 | 0x80000009 => MsgType::ConnectResp,
+			0x00000004 | SUBMIT_MULTI => MsgType::Submit,
+			0x80000004 | 0x80000021 => MsgType::SubmitResp,
+			0x00000005 => MsgType::Deliver,
+			0x80000005 => MsgType::DeliverResp,
+			0x00000006 => MsgType::Terminate,
+			0x80000006 => MsgType::TerminateResp,
+			0x00000003 => MsgType::Query,
+			0x80000003 => MsgType::QueryResp,
+			0x00000008 => MsgType::Cancel,
+			0x80000008 => MsgType::CancelResp,
+			0x00000015 => MsgType::ActiveTest,
+			0x80000015 => MsgType::ActiveTestResp,
+			0x80000000 => MsgType::GenericNack,
+			_ => MsgType::UNKNOWN,
+		}
+	}
+
+	fn get_status_id(&self, status: &SmsStatus) -> u32 {
+		match status {
+			SmsStatus::Success => 0,
+			SmsStatus::MessageError => 0x02, //ESME_RINVCMDLEN
+			SmsStatus::AddError => 0x0D, //ESME_RBINDFAIL
+			SmsStatus::AuthError => 0x0E, //ESME_RINVPASWD
+			SmsStatus::VersionError => 0x0D, //ESME_RBINDFAIL
+			SmsStatus::TrafficRestrictions => 0x58, //ESME_RTHROTTLED
+			SmsStatus::OtherError => 0x08, //ESME_RSYSERR
+			SmsStatus::UNKNOWN => 0xFF, //ESME_RUNKNOWNERR
+			_ => self.get_result_id(status).unwrap_or(0x08),
+		}
+	}
+
+	fn get_status_enum(&self, v: u32) -> SmsStatus {
+		match v {
+			0 => SmsStatus::Success,
+			0x01 | 0x02 => SmsStatus::MessageError,
+			0x0A | 0x0B => SmsStatus::AddError,
+			0x0E | 0x0F => SmsStatus::AuthError,
+			0x58 | 0x14 => SmsStatus::TrafficRestrictions,
+			_ => SmsStatus::OtherError
+		}
+	}
+
+	///smpp是明文的密码.这里使用system_id + password做md5.用来和收到的登录消息进行比较.
+	fn get_auth(&self, system_id: &str, password: &str, _timestamp: u32) -> [u8; 16] {
+		let mut src = BytesMut::with_capacity(system_id.len() + password.len());
+		src.put_slice(system_id.as_bytes());
+		src.put_slice(password.as_bytes());
+
+		md5::compute(&src[..]).0
+	}
+
+	///生成登录操作的消息.统一使用bind_transceiver
+	fn encode_connect(&self, json: &mut JsonValue) -> Result<BytesMut, io::Error> {
+		let system_id = match json[LOGIN_NAME].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有login_name.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有login_name"));
+			}
+		};
+
+		let password = match json[PASSWORD].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有password.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有password"));
+			}
+		};
+
+		let system_type = json[SERVICE_TYPE].as_str().unwrap_or("");
+
+		let mut body = BytesMut::with_capacity(64);
+		fill_c_string(&mut body, system_id, 16); //system_id
+		fill_c_string(&mut body, password, 9); //password
+		fill_c_string(&mut body, system_type, 13); //system_type
+		body.put_u8(SMPP_VERSION); //interface_version
+		body.put_u8(0); //addr_ton
+		body.put_u8(0); //addr_npi
+		body.put_u8(0); //address_range
+
+		let seq_id = get_smpp_sequence_id();
+		json[SEQ_ID] = seq_id.into();
+
+		Ok(put_pdu(BIND_TRANSCEIVER, 0, seq_id, &body))
+	}
+
+	///根据对方给的请求,处理以后的编码消息.回执的命令和对方绑定的命令对应.
+	fn encode_connect_rep(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		let tp = json[MSG_TYPE_U32].as_u32().unwrap_or(BIND_TRANSCEIVER) | RESP_FLAG;
+		let status = self.get_status_id(&status);
+
+		let mut body = BytesMut::with_capacity(32);
+		fill_c_string(&mut body, json[LOGIN_NAME].as_str().unwrap_or(""), 16); //system_id
+		//sc_interface_version
+		if status == 0 {
+			SmppTLV::ScInterfaceVersion(SMPP_VERSION).encode(&mut body);
+		}
+
+		Some(put_pdu(tp, status, seq_id, &body))
+	}
+
+	fn encode_submit_resp(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		let tp = json[MSG_TYPE_U32].as_u32().unwrap_or(0x00000004);
+		let status = self.get_status_id(&status);
+		let msg_id = cmpp_msg_id_u64_to_str(create_cmpp_msg_id(1));
+
+		let mut body = BytesMut::with_capacity(32);
+		fill_c_string(&mut body, msg_id.as_str(), 65); //message_id
+		if tp == SUBMIT_MULTI {
+			body.put_u8(0); //no_unsuccess
+		}
+
+		json[MSG_ID] = msg_id.into();
+		Some(put_pdu(tp | RESP_FLAG, status, seq_id, &body))
+	}
+
+	fn encode_report_resp(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		self.encode_deliver_resp(status, json)
+	}
+
+	fn encode_deliver_resp(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		//deliver_sm_resp 的message_id 不使用.固定为空
+		Some(put_pdu(self.get_type_id(MsgType::DeliverResp), self.get_status_id(&status), seq_id, &[0u8]))
+	}
+
+	fn encode_active_test_resp(&self, _status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		Some(put_pdu(self.get_type_id(MsgType::ActiveTestResp), 0, seq_id, &[]))
+	}
+
+	fn encode_active_test(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let seq_id = get_smpp_sequence_id();
+		json[SEQ_ID] = seq_id.into();
+
+		Ok(put_pdu(self.get_type_id(MsgType::ActiveTest), 0, seq_id, &[]))
+	}
+
+	fn encode_terminate_resp(&self, _status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		Some(put_pdu(self.get_type_id(MsgType::TerminateResp), 0, seq_id, &[]))
+	}
+
+	fn encode_terminate(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let seq_id = get_smpp_sequence_id();
+		json[SEQ_ID] = seq_id.into();
+
+		Ok(put_pdu(self.get_type_id(MsgType::Terminate), 0, seq_id, &[]))
+	}
+
+	///使用cancel_sm删除还未下发的短信.source_addr需要和提交时一致
+	fn encode_cancel(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let msg_id = match json[PASSAGE_MSG_ID].as_str() {
+			None => {
+				log::error!("没有passage_msg_id字串.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有passage_msg_id字串"));
+			}
+			Some(v) => v
+		};
+
+		let mut body = BytesMut::with_capacity(128);
+		fill_c_string(&mut body, json[SERVICE_TYPE].as_str().unwrap_or(""), 6);
+		fill_c_string(&mut body, msg_id, 65);
+		put_address(&mut body, json[SRC_ID].as_str().unwrap_or(""), json[SRC_TON].as_u8(), json[SRC_NPI].as_u8());
+		put_address(&mut body, json[DEST_ID].as_str().unwrap_or(""), json[DEST_TON].as_u8(), json[DEST_NPI].as_u8());
+
+		let seq_id = get_smpp_sequence_id();
+		json[SEQ_ID] = seq_id.into();
+
+		Ok(put_pdu(self.get_type_id(MsgType::Cancel), 0, seq_id, &body))
+	}
+
+	///cancel_sm_resp的状态放在消息头里.失败使用ESME_RCANCELFAIL
+	fn encode_cancel_resp(&self, status: SmsStatus, json: &mut JsonValue) -> Option<BytesMut> {
+		let seq_id = match json[SEQ_ID].as_u32() {
+			Some(v) => v,
+			None => {
+				log::error!("没有seq_id.退出..json:{}", json);
+				return None;
+			}
+		};
+
+		let status = match status {
+			SmsStatus::Success => 0,
+			_ => ESME_RCANCELFAIL
+		};
+
+		Some(put_pdu(self.get_type_id(MsgType::CancelResp), status, seq_id, &[]))
+	}
+
+	fn decode_cancel(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		json[SERVICE_TYPE] = load_c_string(buf, 6)?.into();
+		json[MSG_ID] = load_c_string(buf, 65)?.into();
+		if buf.len() >= 2 {
+			json[SRC_TON] = buf.get_u8().into();
+			json[SRC_NPI] = buf.get_u8().into();
+			json[SRC_ID] = load_c_string(buf, 21)?.into();
+		}
+		if buf.len() >= 2 {
+			json[DEST_TON] = buf.get_u8().into();
+			json[DEST_NPI] = buf.get_u8().into();
+			json[DEST_ID] = load_c_string(buf, 21)?.into();
+		}
+
+		Ok(json)
+	}
+
+	///状态报告使用deliver_sm发送.esm_class为0x04.内容为标准的回执文本格式
+	fn encode_report(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let msg_id = match json[MSG_ID].as_str() {
+			None => {
+				log::error!("没有msg_id字串.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有msg_id字串"));
+			}
+			Some(v) => v
+		};
+
+		let stat = match json[STATE].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有state.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有state"));
+			}
+		};
+
+		let src_id = match json[SRC_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有src_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有src_id"));
+			}
+		};
+
+		let dest_id = match json[DEST_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有dest_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有dest_id"));
+			}
+		};
+
+		let submit_time = json[SUBMIT_TIME].as_str().unwrap_or("");
+		let done_time = json[DONE_TIME].as_str().unwrap_or("");
+		//error_code 有可能是数字,也有可能是字串
+		let err = json[ERROR_CODE].as_u32().unwrap_or_else(|| json[ERROR_CODE].as_str().unwrap_or("0").parse().unwrap_or(0));
+		let dlvrd = if stat == "DELIVRD" { "001" } else { "000" };
+
+		let text = format!("id:{} sub:001 dlvrd:{} submit date:{} done date:{} stat:{} err:{:03} text:", msg_id, dlvrd, submit_time, done_time, stat, err);
+
+		let mut body = BytesMut::with_capacity(128 + text.len());
+		fill_c_string(&mut body, "", 6); //service_type
+		put_address(&mut body, src_id, json[SRC_TON].as_u8(), json[SRC_NPI].as_u8()); //source_addr 状态报告的源地址为手机号
+		put_address(&mut body, dest_id, json[DEST_TON].as_u8(), json[DEST_NPI].as_u8()); //destination_addr
+		body.put_u8(0x04); //esm_class 0x04:状态报告
+		body.put_u8(0); //protocol_id
+		body.put_u8(0); //priority_flag
+		body.put_u8(0); //schedule_delivery_time
+		body.put_u8(0); //validity_period
+		body.put_u8(0); //registered_delivery
+		body.put_u8(0); //replace_if_present_flag
+		body.put_u8(0); //data_coding
+		body.put_u8(0); //sm_default_msg_id
+		body.put_u8(text.len() as u8); //sm_length
+		body.extend_from_slice(text.as_bytes()); //short_message
+
+		SmppTLV::ReceiptedMessageId(msg_id.to_owned()).encode(&mut body);
+		SmppTLV::MessageState(stat_to_message_state(stat)).encode(&mut body);
+
+		let seq_id = get_smpp_sequence_id();
+		json[SEQ_IDS] = vec![seq_id].into();
+		Ok(put_pdu(self.get_type_id(MsgType::Deliver), 0, seq_id, &body))
+	}
+
+	fn encode_deliver(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let msg_fmt = get_smpp_msg_fmt(json[MSG_FMT].as_u8().unwrap_or(8), json);
+		//编码以后的消息内容
+		let (msg_content_code, has_udh) = match get_smpp_content_code(msg_fmt, json) {
+			Ok(v) => v,
+			Err(e) => {
+				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
+				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
+			}
+		};
+		let tp_pid = get_tp_pid(msg_fmt, json);
+
+		let src_id = match json[SRC_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有src_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有src_id"));
+			}
+		};
+
+		let dest_id = match json[DEST_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有dest_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有dest_id"));
+			}
+		};
+
+		let segments = split_smpp_content(msg_fmt, &msg_content_code, json[LONG_SMS_MODE].as_str().unwrap_or(""));
+		let extra_tlvs = get_tlvs_from_json(json);
+
+		let mut dst = BytesMut::with_capacity(segments.len() * 80 + msg_content_code.len());
+		let mut seq_ids = Vec::with_capacity(segments.len());
+		let mut msg_ids = Vec::with_capacity(segments.len());
+
+		for (esm_class, short_message, tlvs) in segments.iter() {
+			let mut body = BytesMut::with_capacity(80 + short_message.len());
+			fill_c_string(&mut body, json[SERVICE_TYPE].as_str().unwrap_or(""), 6); //service_type
+			put_address(&mut body, src_id, json[SRC_TON].as_u8(), json[SRC_NPI].as_u8()); //source_addr
+			put_address(&mut body, dest_id, json[DEST_TON].as_u8(), json[DEST_NPI].as_u8()); //destination_addr
+			body.put_u8(if has_udh { esm_class | 0x40 } else { *esm_class }); //esm_class 0x40:有UDH
+			body.put_u8(tp_pid); //protocol_id
+			body.put_u8(0); //priority_flag
+			body.put_u8(0); //schedule_delivery_time
+			body.put_u8(0); //validity_period
+			body.put_u8(0); //registered_delivery
+			body.put_u8(0); //replace_if_present_flag
+			body.put_u8(msg_fmt); //data_coding
+			body.put_u8(0); //sm_default_msg_id
+			body.put_u8(short_message.len() as u8); //sm_length
+			body.extend_from_slice(short_message); //short_message
+			tlvs.iter().chain(extra_tlvs.iter()).for_each(|tlv| tlv.encode(&mut body));
+
+			let seq_id = get_smpp_sequence_id();
+			seq_ids.push(seq_id);
+			msg_ids.push(cmpp_msg_id_u64_to_str(create_cmpp_msg_id(1)));
+
+			dst.extend_from_slice(&put_pdu(self.get_type_id(MsgType::Deliver), 0, seq_id, &body));
+		}
+
+		json[MSG_IDS] = msg_ids.into();
+		json[SEQ_IDS] = seq_ids.into();
+		Ok(dst)
+	}
+
+	///多个号码的时候使用submit_multi.单个号码使用submit_sm
+	fn encode_submit(&self, json: &mut JsonValue) -> Result<BytesMut, Error> {
+		let msg_fmt = get_smpp_msg_fmt(json[MSG_FMT].as_u8().unwrap_or(8), json);
+		//编码以后的消息内容
+		let (msg_content_code, has_udh) = match get_smpp_content_code(msg_fmt, json) {
+			Ok(v) => v,
+			Err(e) => {
+				log::error!("字符串内容解码出现错误..json:{}.e:{}", json, e);
+				return Err(io::Error::new(io::ErrorKind::Other, "字符串内容解码出现错误"));
+			}
+		};
+		let tp_pid = get_tp_pid(msg_fmt, json);
+
+		let src_id = match json[SRC_ID].as_str() {
+			Some(v) => v,
+			None => {
+				log::error!("没有src_id.退出..json:{}", json);
+				return Err(io::Error::new(io::ErrorKind::NotFound, "没有src_id"));
+			}
+		};
+
+		let dest_ids = if json[DEST_IDS].is_array() && !json[DEST_IDS].is_empty() {
+			let mut dest_ids = Vec::with_capacity(json[DEST_IDS].len());
+			json[DEST_IDS].members().for_each(|item| dest_ids.push(item.as_str().unwrap_or("")));
+
+			dest_ids
+		} else {
+			log::error!("没有dest_ids.退出..json:{}", json);
+			return Err(io::Error::new(io::ErrorKind::NotFound, "没有dest_ids"));
+		};
+
+		let service_type = json[SERVICE_TYPE].as_str().unwrap_or("");
+		let valid_time = json[VALID_TIME].as_str().unwrap_or("");
+		let at_time = json[AT_TIME].as_str().unwrap_or("");
+		let tp = if dest_ids.len() == 1 { self.get_type_id(MsgType::Submit) } else { SUBMIT_MULTI };
+
+		let segments = split_smpp_content(msg_fmt, &msg_content_code, json[LONG_SMS_MODE].as_str().unwrap_or(""));
+		let mut extra_tlvs = get_tlvs_from_json(json);
+		if let Some(v) = json[USER_MESSAGE_REFERENCE].as_u16() {
+			extra_tlvs.push(SmppTLV::UserMessageReference(v));
+		}
+
+		let mut dst = BytesMut::with_capacity(segments.len() * (120 + dest_ids.len() * 24) + msg_content_code.len());
+		let mut seq_ids = Vec::with_capacity(segments.len());
+
+		for (esm_class, short_message, tlvs) in segments.iter() {
+			let mut body = BytesMut::with_capacity(120 + dest_ids.len() * 24 + short_message.len());
+			fill_c_string(&mut body, service_type, 6); //service_type
+			put_address(&mut body, src_id, json[SRC_TON].as_u8(), json[SRC_NPI].as_u8()); //source_addr
+			if tp == SUBMIT_MULTI {
+				body.put_u8(dest_ids.len() as u8); //number_of_dests
+				dest_ids.iter().for_each(|dest_id| {
+					body.put_u8(1); //dest_flag 1:SME Address
+					put_address(&mut body, dest_id, json[DEST_TON].as_u8(), json[DEST_NPI].as_u8());
+				});
+			} else {
+				put_address(&mut body, dest_ids[0], json[DEST_TON].as_u8(), json[DEST_NPI].as_u8()); //destination_addr
+			}
+			body.put_u8(if has_udh { esm_class | 0x40 } else { *esm_class }); //esm_class 0x40:有UDH
+			body.put_u8(tp_pid); //protocol_id
+			body.put_u8(0); //priority_flag
+			fill_c_string(&mut body, at_time, 17); //schedule_delivery_time
+			fill_c_string(&mut body, valid_time, 17); //validity_period
+			body.put_u8(1); //registered_delivery 需要状态报告
+			body.put_u8(0); //replace_if_present_flag
+			body.put_u8(msg_fmt); //data_coding
+			body.put_u8(0); //sm_default_msg_id
+			body.put_u8(short_message.len() as u8); //sm_length
+			body.extend_from_slice(short_message); //short_message
+			tlvs.iter().chain(extra_tlvs.iter()).for_each(|tlv| tlv.encode(&mut body));
+
+			let seq_id = get_smpp_sequence_id();
+			seq_ids.push(seq_id);
+
+			dst.extend_from_slice(&put_pdu(tp, 0, seq_id, &body));
+		}
+
+		json[SEQ_IDS] = seq_ids.into();
+
+		Ok(dst)
+	}
+
+	///status在消息头里.由decode_read_msg放入
+	fn decode_connect_resp(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+		json[VERSION] = (SMPP_VERSION as u32).into();
+
+		//失败的时候可能没有消息体
+		if buf.has_remaining() {
+			json[LOGIN_NAME] = load_c_string(buf, 16)?.into(); //system_id
+			put_tlvs_to_json(decode_tlvs(buf), &mut json);
+		}
+
+		Ok(json)
+	}
+
+	///result在消息头里.由decode_read_msg放入
+	fn decode_submit_or_deliver_resp(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+		//失败的时候可能没有消息体
+		json[MSG_ID] = if buf.has_remaining() {
+			load_c_string(buf, 65)?.into()
+		} else {
+			"".into()
+		};
+
+		Ok(json)
+	}
+
+	fn decode_nobody(&self, _buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		Ok(json)
+	}
+
+	///esm_class里面的消息类型为0x04的时候是状态报告
+	fn decode_deliver(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		load_c_string(buf, 6)?; //service_type
+		check_len(buf, 2)?;
+		buf.advance(2); //source_addr_ton source_addr_npi
+		json[SRC_ID] = load_c_string(buf, 21)?.into(); //source_addr
+		check_len(buf, 2)?;
+		buf.advance(2); //dest_addr_ton dest_addr_npi
+		json[DEST_ID] = load_c_string(buf, 21)?.into(); //destination_addr
+		check_len(buf, 3)?;
+		let esm_class = buf.get_u8();
+		buf.advance(2); //protocol_id priority_flag
+		load_c_string(buf, 17)?; //schedule_delivery_time
+		load_c_string(buf, 17)?; //validity_period
+		//registered_delivery replace_if_present_flag data_coding sm_default_msg_id sm_length
+		check_len(buf, 5)?;
+		buf.advance(2); //registered_delivery replace_if_present_flag
+		let data_coding = buf.get_u8();
+		buf.advance(1); //sm_default_msg_id
+		let sm_length = buf.get_u8() as usize;
+		check_len(buf, sm_length)?;
+		let short_message = buf.split_to(sm_length);
+		let tlvs = decode_tlvs(buf);
+
+		json[MSG_ID] = cmpp_msg_id_u64_to_str(create_cmpp_msg_id(1)).into();
+		if esm_class & 0x3C == 0x04 {
+			json[IS_REPORT] = true.into(); //状态报告增加.
+			let text = String::from_utf8_lossy(&short_message).into_owned();
+
+			if let Some(v) = get_receipt_field(&text, "id:") {
+				json[PASSAGE_MSG_ID] = v.into();
+			}
+			json[STATE] = get_receipt_field(&text, "stat:").unwrap_or("UNKNOWN").into();
+			json[SUBMIT_TIME] = get_receipt_field(&text, "submit date:").unwrap_or("").into();
+			json[DONE_TIME] = get_receipt_field(&text, "done date:").unwrap_or("").into();
+			json[ERROR_CODE] = get_receipt_field(&text, "err:").unwrap_or("0").parse::<u32>().unwrap_or(0).into();
+
+			//有receipted_message_id等tlv的时候以tlv为准
+			put_tlvs_to_json(tlvs, &mut json);
+		} else {
+			json[MSG_FMT] = get_msg_fmt_from_smpp(data_coding).into();
+			let payload = put_tlvs_to_json(tlvs, &mut json);
+			decode_smpp_content(payload.unwrap_or(short_message), data_coding, &mut json, esm_class & 0x40 != 0)?;
+		}
+
+		Ok(json)
+	}
+
+	fn decode_report_resp(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		self.decode_submit_or_deliver_resp(buf, seq, tp)
+	}
+
+	fn decode_submit(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		json[SERVICE_TYPE] = load_c_string(buf, 6)?.into(); //service_type
+		check_len(buf, 2)?;
+		buf.advance(2); //source_addr_ton source_addr_npi
+		json[SRC_ID] = load_c_string(buf, 21)?.into(); //source_addr
+
+		let mut dest_ids: Vec<String> = Vec::new();
+		if tp == SUBMIT_MULTI {
+			check_len(buf, 1)?;
+			let number_of_dests = buf.get_u8();
+			for _i in 0..number_of_dests {
+				check_len(buf, 1)?;
+				match buf.get_u8() {
+					//SME Address
+					1 => {
+						check_len(buf, 2)?;
+						buf.advance(2); //dest_addr_ton dest_addr_npi
+						dest_ids.push(load_c_string(buf, 21)?);
+					}
+					//Distribution List 不支持.跳过
+					_ => {
+						load_c_string(buf, 21)?;
+					}
+				}
+			}
+		} else {
+			check_len(buf, 2)?;
+			buf.advance(2); //dest_addr_ton dest_addr_npi
+			dest_ids.push(load_c_string(buf, 21)?); //destination_addr
+		}
+		json[DEST_IDS] = dest_ids.into();
+
+		check_len(buf, 3)?;
+		let esm_class = buf.get_u8();
+		buf.advance(2); //protocol_id priority_flag
+		json[AT_TIME] = load_c_string(buf, 17)?.into(); //schedule_delivery_time
+		json[VALID_TIME] = load_c_string(buf, 17)?.into(); //validity_period
+		//registered_delivery replace_if_present_flag data_coding sm_default_msg_id sm_length
+		check_len(buf, 5)?;
+		buf.advance(2); //registered_delivery replace_if_present_flag
+		let data_coding = buf.get_u8();
+		json[MSG_FMT] = get_msg_fmt_from_smpp(data_coding).into();
+		buf.advance(1); //sm_default_msg_id
+		let sm_length = buf.get_u8() as usize;
+		check_len(buf, sm_length)?;
+		let short_message = buf.split_to(sm_length);
+
+		let payload = put_tlvs_to_json(decode_tlvs(buf), &mut json);
+		decode_smpp_content(payload.unwrap_or(short_message), data_coding, &mut json, esm_class & 0x40 != 0)?;
+
+		Ok(json)
+	}
+
+	///实际的解码连接消息.这里把收到的system_id和password按get_auth同样的方式算出来,用来给entity进行比较
+	fn decode_connect(&self, buf: &mut BytesMut, seq: u32, tp: u32) -> Result<JsonValue, io::Error> {
+		let mut json = JsonValue::new_object();
+		json[MSG_TYPE_U32] = tp.into();
+		json[SEQ_ID] = seq.into();
+
+		let system_id = load_c_string(buf, 16)?;
+		let password = load_c_string(buf, 9)?;
+		let auth = self.get_auth(system_id.as_str(), password.as_str(), 0);
+		let mut auth = &auth[8..16];
+
+		json[LOGIN_NAME] = system_id.into();
+		json[AUTHENTICATOR] = auth.get_u64().into();
+		json[SERVICE_TYPE] = load_c_string(buf, 13)?.into(); //system_type
+		check_len(buf, 1)?;
+		json[VERSION] = (buf.get_u8() as u32).into(); //interface_version
+		json[TIMESTAMP] = 0.into();
+		//只能发送的连接。不能往这个连接上写消息
+		json[CAN_WRITE] = (tp != BIND_TRANSMITTER).into();
+
+		Ok(json)
 	}
 }
 
@@ -26,12 +765,459 @@ impl Clone for Smpp {
 impl Smpp {
 	pub fn new() -> Self {
 		Smpp {
-			version: 0,
+			version: SMPP_VERSION as u32,
 			length_codec: LengthDelimitedCodec::builder()
 				.length_field_offset(0)
 				.length_field_length(4)
 				.length_adjustment(-4)
+				.max_frame_length(SMPP_MAX_LEN)
 				.new_codec(),
 		}
 	}
+
+	///按消息类型解码消息体.status为消息头里的command_status.返回实际的消息类型(状态报告和上行使用同一个command)
+	fn decode_pdu(&self, buf: &mut BytesMut, status: u32, seq: u32, tp: u32) -> io::Result<(MsgType, JsonValue)> {
+		let mut msg_type = self.get_type_enum(tp);
+		let msg = match msg_type {
+			MsgType::Connect => self.decode_connect(buf, seq, tp)?,
+			MsgType::ConnectResp => {
+				let mut json = self.decode_connect_resp(buf, seq, tp)?;
+				json[STATUS] = status.into();
+
+				json
+			}
+			MsgType::Submit => self.decode_submit(buf, seq, tp)?,
+			MsgType::SubmitResp | MsgType::DeliverResp => {
+				let mut json = self.decode_submit_or_deliver_resp(buf, seq, tp)?;
+				//smpp的状态是放在消息头里的
+				json[RESULT] = status.into();
+				if self.is_speed_limit(status) {
+					json[SPEED_LIMIT] = true.into();
+				};
+
+				json
+			}
+			MsgType::Deliver => {
+				let json = self.decode_deliver(buf, seq, tp)?;
+				if let Some(true) = json[IS_REPORT].as_bool() {
+					msg_type = MsgType::Report;
+				}
+
+				json
+			}
+			MsgType::GenericNack => {
+				let mut json = self.decode_nobody(buf, seq, tp)?;
+				log::warn!("收到generic_nack.seq:{}.status:{:X}", seq, status);
+				json[RESULT] = status.into();
+
+				json
+			}
+			MsgType::Cancel => self.decode_cancel(buf, seq, tp)?,
+			MsgType::CancelResp => {
+				let mut json = self.decode_nobody(buf, seq, tp)?;
+				json[RESULT] = status.into();
+
+				json
+			}
+			MsgType::ActiveTest | MsgType::ActiveTestResp => self.decode_nobody(buf, seq, tp)?,
+			MsgType::Terminate | MsgType::TerminateResp => self.decode_nobody(buf, seq, tp)?,
+			_ => {
+				//未支持的命令.交给上层回复generic_nack
+				log::warn!("收到未支持的命令.command_id:{:X}.seq:{}", tp, seq);
+				self.decode_nobody(buf, seq, tp)?
+			}
+		};
+
+		Ok((msg_type, msg))
+	}
+
+	///收到不支持的命令时的回复.对方发来的是回执的时候不回复.
+	pub fn encode_generic_nack(&self, json: &mut JsonValue) -> Option<BytesMut> {
+		let tp = json[MSG_TYPE_U32].as_u32().unwrap_or(0);
+		if tp & RESP_FLAG != 0 {
+			return None;
+		}
+
+		let seq_id = json[SEQ_ID].as_u32().unwrap_or(0);
+
+		Some(put_pdu(self.get_type_id(MsgType::GenericNack), ESME_RINVCMDID, seq_id, &[]))
+	}
+}
+
+///生成一个完整的pdu.
+fn put_pdu(tp: u32, status: u32, seq_id: u32, body: &[u8]) -> BytesMut {
+	let mut dst = BytesMut::with_capacity(16 + body.len());
+	dst.put_u32((16 + body.len()) as u32);
+	dst.put_u32(tp);
+	dst.put_u32(status);
+	dst.put_u32(seq_id);
+	dst.extend_from_slice(body);
+
+	dst
+}
+
+///smpp的sequence_number 只能使用 0x00000001 - 0x7FFFFFFF
+fn get_smpp_sequence_id() -> u32 {
+	let seq_id = get_sequence_id(1) & 0x7FFFFFFF;
+	if seq_id == 0 { 1 } else { seq_id }
 }
+
+///写入地址.ton和npi没有给出的时候根据号码判断
+fn put_address(dst: &mut BytesMut, addr: &str, ton: Option<u8>, npi: Option<u8>) {
+	let addr = addr.trim_start_matches('+');
+	let (def_ton, def_npi) = if addr.is_empty() {
+		(0, 0)
+	} else if addr.bytes().all(|b| b.is_ascii_digit()) {
+		(1, 1) //International ISDN
+	} else {
+		(5, 0) //Alphanumeric
+	};
+
+	dst.put_u8(ton.unwrap_or(def_ton));
+	dst.put_u8(npi.unwrap_or(def_npi));
+	fill_c_string(dst, addr, 21);
+}
+
+///读取剩下的所有tlv.
+fn decode_tlvs(buf: &mut BytesMut) -> Vec<SmppTLV> {
+	let mut result = Vec::new();
+	while buf.len() >= 4 {
+		let tag = buf.get_u16();
+		let len = buf.get_u16() as usize;
+		if buf.len() < len {
+			log::warn!("tlv长度出错.tag:{:X}.len:{}.剩余:{}", tag, len, buf.len());
+			break;
+		}
+
+		result.push(SmppTLV::decode(tag, buf.split_to(len)));
+	}
+
+	result
+}
+
+///把收到的tlv放入json.message_payload单独返回.未识别的tlv以16进制放入tlvs里面
+fn put_tlvs_to_json(tlvs: Vec<SmppTLV>, json: &mut JsonValue) -> Option<BytesMut> {
+	let mut payload = None;
+	for tlv in tlvs.into_iter() {
+		match tlv {
+			SmppTLV::UserMessageReference(v) => json[USER_MESSAGE_REFERENCE] = v.into(),
+			SmppTLV::ReceiptedMessageId(v) => json[PASSAGE_MSG_ID] = v.into(),
+			SmppTLV::ScInterfaceVersion(v) => json[VERSION] = (v as u32).into(),
+			//和UDH的参考号一样
+			SmppTLV::SarMsgRefNum(v) => json[LONG_SMS_REF] = v.into(),
+			SmppTLV::SarTotalSegments(v) => json[LONG_SMS_TOTAL] = v.into(),
+			SmppTLV::SarSegmentSeqnum(v) => json[LONG_SMS_NOW_NUMBER] = v.into(),
+			SmppTLV::NetworkErrorCode(_, v) => json[ERROR_CODE] = v.into(),
+			SmppTLV::MessagePayload(v) => payload = Some(v),
+			SmppTLV::MessageState(v) => {
+				if json[STATE].is_null() || json[STATE] == "UNKNOWN" {
+					json[STATE] = message_state_to_stat(v).into();
+				}
+			}
+			SmppTLV::Unknown(tag, v) => json[TLVS][format!("{:04X}", tag)] = format!("{:X}", v).into(),
+		}
+	}
+
+	payload
+}
+
+///取出json里面需要原样发送的tlv
+fn get_tlvs_from_json(json: &JsonValue) -> Vec<SmppTLV> {
+	let mut result = Vec::new();
+	for (tag, value) in json[TLVS].entries() {
+		match (u16::from_str_radix(tag, 16), value.as_str().and_then(hex_to_bytes)) {
+			(Ok(tag), Some(value)) => result.push(SmppTLV::Unknown(tag, BytesMut::from(&value[..]))),
+			_ => log::warn!("tlv格式错误.跳过.tag:{}.value:{}", tag, value),
+		}
+	}
+
+	result
+}
+
+///取得编码以后的内容.data_coding 0为GSM 7bit默认字母表,每个septet占一个字节,拆分以后再压缩
+fn get_smpp_content_code(msg_fmt: u8, json: &JsonValue) -> Result<(Vec<u8>, bool), Error> {
+	get_msg_content_code(msg_fmt, json, Alphabet::Gsm7)
+}
+
+///按发送方式拆分短信内容.返回每一条的esm_class,short_message(包含UDH),和需要附加的tlv.
+///GSM 7bit按septet个数拆分(160/153),每一条压缩成7bit.有UDH时按septet边界填充
+fn split_smpp_content(msg_fmt: u8, msg_content_code: &[u8], long_sms_mode: &str) -> Vec<(u8, Vec<u8>, Vec<SmppTLV>)> {
+	let is_gsm7 = msg_fmt == 0;
+	let (one_len, part_len) = get_msg_content_limit(msg_fmt, Alphabet::Gsm7);
+	let split = |len: usize| split_msg_content_by_len(msg_fmt, msg_content_code, len, Alphabet::Gsm7);
+	let pack = |content: &[u8], fill_bits: usize| if is_gsm7 { gsm7::pack(content, fill_bits) } else { content.to_vec() };
+
+	if msg_content_code.len() <= one_len {
+		return vec![(0, pack(msg_content_code, 0), Vec::new())];
+	}
+
+	let msg_content_seq_id = get_sequence_id(1);
+	match long_sms_mode {
+		//整条放在message_payload里面.short_message为空
+		"payload" => vec![(0, Vec::new(), vec![SmppTLV::MessagePayload(BytesMut::from(&pack(msg_content_code, 0)[..]))])],
+		//使用sar_*的tlv标识长短信.内容不需要UDH
+		"sar" => {
+			let contents = split(one_len);
+			let sms_len = contents.len();
+			contents.iter().enumerate().map(|(i, content)| {
+				(0, pack(content, 0), vec![
+					SmppTLV::SarMsgRefNum(msg_content_seq_id as u16),
+					SmppTLV::SarTotalSegments(sms_len as u8),
+					SmppTLV::SarSegmentSeqnum((i + 1) as u8),
+				])
+			}).collect()
+		}
+		_ => {
+			let contents = split(part_len);
+			let sms_len = contents.len();
+			contents.iter().enumerate().map(|(i, content)| {
+				let mut short_message = Vec::with_capacity(6 + content.len());
+				short_message.extend_from_slice(&[5, 0, 3, msg_content_seq_id as u8, sms_len as u8, (i + 1) as u8]);
+				short_message.extend_from_slice(&pack(content, gsm7::fill_bits(6)));
+
+				//esm_class 0x40:有UDH
+				(0x40, short_message, Vec::new())
+			}).collect()
+		}
+	}
+}
+
+///smpp里面GBK没有对应的data_coding.转成UCS2发送.
+///data_coding 0为GSM 7bit默认字母表.内容不能用GSM 7bit编码时(如ASCII里面的`)也使用UCS2
+fn get_smpp_msg_fmt(msg_fmt: u8, json: &JsonValue) -> u8 {
+	match msg_fmt {
+		15 => 8,
+		0 if !gsm7::can_encode(json[MSG_CONTENT].as_str().unwrap_or("")) => 8,
+		_ => msg_fmt
+	}
+}
+
+///把smpp的data_coding转成内部使用的msg_fmt
+fn get_msg_fmt_from_smpp(data_coding: u8) -> u8 {
+	match data_coding {
+		0 | 1 | 3 => 0,
+		_ => data_coding
+	}
+}
+
+///处理smpp的短信内容.GSM 7bit\IA5\Latin1和超过255长度的message_payload需要单独转换.其他的使用通用方法
+fn decode_smpp_content(mut content: BytesMut, data_coding: u8, json: &mut JsonValue, is_long_sms: bool) -> Result<(), io::Error> {
+	if data_coding == 0 {
+		return decode_gsm7_content(content, json, is_long_sms);
+	}
+
+	//IA5(ASCII)是Latin1的子集.不能按GSM 7bit解码
+	if (data_coding == 1 || data_coding == 3) && !is_long_sms {
+		match ISO_8859_1.decode(&content, DecoderTrap::Replace) {
+			Ok(v) => json[MSG_CONTENT] = v.into(),
+			Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e))
+		}
+
+		return Ok(());
+	}
+
+	if content.len() > 0xFF {
+		let msg_fmt = get_msg_fmt_from_smpp(data_coding);
+		if matches!(msg_fmt, 0 | 8 | 15) {
+			json[MSG_CONTENT] = decode_msg_content_bytes(msg_fmt, &content, Alphabet::Ascii)?.into();
+		} else {
+			json[MSG_HEX] = format!("{:X}", content).into();
+		}
+
+		return Ok(());
+	}
+
+	let len = content.len() as u8;
+	decode_msg_content(&mut content, get_msg_fmt_from_smpp(data_coding), len, json, is_long_sms, Alphabet::Ascii)
+}
+
+///GSM 7bit压缩的内容.有UDH时UDH后面按septet边界填充
+fn decode_gsm7_content(mut content: BytesMut, json: &mut JsonValue, is_long_sms: bool) -> Result<(), io::Error> {
+	let mut fill_bits = 0;
+	if is_long_sms {
+		let udh_len = match content.first() {
+			Some(v) if (*v as usize) < content.len() => *v as usize + 1,
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidData, "消息结构出错.")),
+		};
+
+		let udh = content.split_to(udh_len);
+		put_udh_to_json(&udh, json)?;
+		fill_bits = gsm7::fill_bits(udh_len);
+	}
+
+	let septets = gsm7::unpack(&content, gsm7::septet_count(&content, fill_bits), fill_bits);
+	json[MSG_CONTENT] = decode_msg_content_bytes(0, &septets, Alphabet::Gsm7)?.into();
+
+	Ok(())
+}
+
+///从状态报告的文本里面取出对应的值.如: id:xxxxx sub:001
+fn get_receipt_field<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+	let lower = text.to_ascii_lowercase();
+	let start = lower.find(key)? + key.len();
+	let value = &text[start..];
+	let end = value.find(' ').unwrap_or(value.len());
+
+	Some(&value[0..end])
+}
+
+///把状态转成message_state
+fn stat_to_message_state(stat: &str) -> u8 {
+	match stat {
+		"ENROUTE" => 1,
+		"DELIVRD" => 2,
+		"EXPIRED" => 3,
+		"DELETED" => 4,
+		"UNDELIV" => 5,
+		"ACCEPTD" => 6,
+		"REJECTD" => 8,
+		_ => 7
+	}
+}
+
+///把message_state转成状态
+fn message_state_to_stat(state: u8) -> &'static str {
+	match state {
+		1 => "ENROUTE",
+		2 => "DELIVRD",
+		3 => "EXPIRED",
+		4 => "DELETED",
+		5 => "UNDELIV",
+		6 => "ACCEPTD",
+		8 => "REJECTD",
+		_ => "UNKNOWN"
+	}
+}
+
+///smpp的可选参数
+enum SmppTLV {
+	UserMessageReference(u16),
+	ReceiptedMessageId(String),
+	ScInterfaceVersion(u8),
+	SarMsgRefNum(u16),
+	SarTotalSegments(u8),
+	SarSegmentSeqnum(u8),
+	///network_type,error_code
+	NetworkErrorCode(u8, u16),
+	MessagePayload(BytesMut),
+	MessageState(u8),
+	///未识别的tlv.原样保存
+	Unknown(u16, BytesMut),
+}
+
+impl SmppTLV {
+	fn get_u16(&self) -> u16 {
+		match self {
+			SmppTLV::UserMessageReference(_) => 0x0204,
+			SmppTLV::ReceiptedMessageId(_) => 0x001E,
+			SmppTLV::ScInterfaceVersion(_) => 0x0210,
+			SmppTLV::SarMsgRefNum(_) => 0x020C,
+			SmppTLV::SarTotalSegments(_) => 0x020E,
+			SmppTLV::SarSegmentSeqnum(_) => 0x020F,
+			SmppTLV::NetworkErrorCode(_, _) => 0x0423,
+			SmppTLV::MessagePayload(_) => 0x0424,
+			SmppTLV::MessageState(_) => 0x0427,
+			SmppTLV::Unknown(tag, _) => *tag,
+		}
+	}
+
+	fn encode(&self, buf: &mut BytesMut) {
+		buf.put_u16(self.get_u16());
+		match self {
+			SmppTLV::UserMessageReference(v) |
+			SmppTLV::SarMsgRefNum(v) => {
+				buf.put_u16(2);
+				buf.put_u16(*v);
+			}
+			SmppTLV::ScInterfaceVersion(v) |
+			SmppTLV::SarTotalSegments(v) |
+			SmppTLV::SarSegmentSeqnum(v) |
+			SmppTLV::MessageState(v) => {
+				buf.put_u16(1);
+				buf.put_u8(*v);
+			}
+			SmppTLV::ReceiptedMessageId(v) => {
+				buf.put_u16((v.len() + 1) as u16);
+				fill_c_string(buf, v, v.len() + 1);
+			}
+			SmppTLV::NetworkErrorCode(network_type, error_code) => {
+				buf.put_u16(3);
+				buf.put_u8(*network_type);
+				buf.put_u16(*error_code);
+			}
+			SmppTLV::MessagePayload(v) |
+			SmppTLV::Unknown(_, v) => {
+				buf.put_u16(v.len() as u16);
+				buf.extend_from_slice(v);
+			}
+		}
+	}
+
+	///长度不对的按未识别的处理
+	fn decode(tag: u16, mut value: BytesMut) -> Self {
+		match (tag, value.len()) {
+			(0x0204, 2) => SmppTLV::UserMessageReference(value.get_u16()),
+			(0x001E, _) => SmppTLV::ReceiptedMessageId(String::from_utf8_lossy(&value).trim_end_matches(char::from(0)).to_owned()),
+			(0x0210, 1) => SmppTLV::ScInterfaceVersion(value.get_u8()),
+			(0x020C, 2) => SmppTLV::SarMsgRefNum(value.get_u16()),
+			(0x020E, 1) => SmppTLV::SarTotalSegments(value.get_u8()),
+			(0x020F, 1) => SmppTLV::SarSegmentSeqnum(value.get_u8()),
+			(0x0423, 3) => SmppTLV::NetworkErrorCode(value.get_u8(), value.get_u16()),
+			(0x0424, _) => SmppTLV::MessagePayload(value),
+			(0x0427, 1) => SmppTLV::MessageState(value.get_u8()),
+			_ => SmppTLV::Unknown(tag, value),
+		}
+	}
+}
+
+///SMPP3.4的command_status对照表.ESME_RTHROTTLED和ESME_RMSGQFUL为流量控制
+static SMPP_RESULT_TABLE: &[ResultCode] = &[
+	(0x00, SmsStatus::Success, false, "ESME_ROK"),
+	(0x01, SmsStatus::LengthError, false, "ESME_RINVMSGLEN"),
+	(0x02, SmsStatus::MessageError, false, "ESME_RINVCMDLEN"),
+	(0x03, SmsStatus::CommandError, false, "ESME_RINVCMDID"),
+	(0x04, SmsStatus::OtherError, false, "ESME_RINVBNDSTS"),
+	(0x05, SmsStatus::OtherError, false, "ESME_RALYBND"),
+	(0x06, SmsStatus::MessageError, false, "ESME_RINVPRTFLG"),
+	(0x07, SmsStatus::MessageError, false, "ESME_RINVREGDLVFLG"),
+	(0x08, SmsStatus::SystemBusy, true, "ESME_RSYSERR"),
+	(0x0A, SmsStatus::SrcIdError, false, "ESME_RINVSRCADR"),
+	(0x0B, SmsStatus::DestIdError, false, "ESME_RINVDSTADR"),
+	(0x0C, SmsStatus::MessageError, false, "ESME_RINVMSGID"),
+	(0x0D, SmsStatus::AuthError, false, "ESME_RBINDFAIL"),
+	(0x0E, SmsStatus::AuthError, false, "ESME_RINVPASWD"),
+	(0x0F, SmsStatus::AuthError, false, "ESME_RINVSYSID"),
+	(0x11, SmsStatus::OtherError, false, "ESME_RCANCELFAIL"),
+	(0x13, SmsStatus::OtherError, false, "ESME_RREPLACEFAIL"),
+	(0x14, SmsStatus::TrafficRestrictions, true, "ESME_RMSGQFUL"),
+	(0x15, SmsStatus::ServiceError, false, "ESME_RINVSERTYP"),
+	(0x33, SmsStatus::DestIdError, false, "ESME_RINVNUMDESTS"),
+	(0x34, SmsStatus::DestIdError, false, "ESME_RINVDLNAME"),
+	(0x40, SmsStatus::MessageError, false, "ESME_RINVDESTFLAG"),
+	(0x42, SmsStatus::MessageError, false, "ESME_RINVSUBREP"),
+	(0x43, SmsStatus::MessageError, false, "ESME_RINVESMCLASS"),
+	(0x44, SmsStatus::OtherError, false, "ESME_RCNTSUBDL"),
+	(0x45, SmsStatus::OtherError, false, "ESME_RSUBMITFAIL"),
+	(0x48, SmsStatus::SrcIdError, false, "ESME_RINVSRCTON"),
+	(0x49, SmsStatus::SrcIdError, false, "ESME_RINVSRCNPI"),
+	(0x50, SmsStatus::DestIdError, false, "ESME_RINVDSTTON"),
+	(0x51, SmsStatus::DestIdError, false, "ESME_RINVDSTNPI"),
+	(0x53, SmsStatus::AuthError, false, "ESME_RINVSYSTYP"),
+	(0x54, SmsStatus::MessageError, false, "ESME_RINVREPFLAG"),
+	(0x55, SmsStatus::MessageError, false, "ESME_RINVNUMMSGS"),
+	(0x58, SmsStatus::TrafficRestrictions, true, "ESME_RTHROTTLED"),
+	(0x61, SmsStatus::MessageError, false, "ESME_RINVSCHED"),
+	(0x62, SmsStatus::MessageError, false, "ESME_RINVEXPIRY"),
+	(0x63, SmsStatus::MessageError, false, "ESME_RINVDFTMSGID"),
+	(0x64, SmsStatus::SystemBusy, true, "ESME_RX_T_APPN"),
+	(0x65, SmsStatus::PermissionError, false, "ESME_RX_P_APPN"),
+	(0x66, SmsStatus::PermissionError, false, "ESME_RX_R_APPN"),
+	(0x67, SmsStatus::OtherError, false, "ESME_RQUERYFAIL"),
+	(0xC0, SmsStatus::MessageError, false, "ESME_RINVOPTPARSTREAM"),
+	(0xC1, SmsStatus::MessageError, false, "ESME_ROPTPARNOTALLWD"),
+	(0xC2, SmsStatus::MessageError, false, "ESME_RINVPARLEN"),
+	(0xC3, SmsStatus::MessageError, false, "ESME_RMISSINGOPTPARAM"),
+	(0xC4, SmsStatus::MessageError, false, "ESME_RINVOPTPARAMVAL"),
+	(0xFE, SmsStatus::OtherError, true, "ESME_RDELIVERYFAILURE"),
+	(0xFF, SmsStatus::UNKNOWN, false, "ESME_RUNKNOWNERR"),
+];
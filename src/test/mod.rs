@@ -8,7 +8,7 @@ use chrono::{Datelike, Local, Timelike};
 use json::JsonValue;
 use log4rs::encode::pattern::PatternEncoderDeserializer;
 use tokio::time::Instant;
-use crate::{get_runtime, global::get_sequence_id, protocol::{Sgip, SmsStatus, cmpp32::Cmpp32, implements::{cmpp_msg_id_str_to_u64, cmpp_msg_id_u64_to_str, create_cmpp_msg_id, sgip_msg_id_str_to_u64, sgip_msg_id_u64_to_str}, names::{DEST_ID, DEST_IDS, LONG_SMS_NOW_NUMBER, LONG_SMS_TOTAL, MSG_CONTENT, MSG_ID, MSG_IDS, SRC_ID}}};
+use crate::{get_runtime, global::get_sequence_id, protocol::{Sgip, SmsStatus, cmpp20::Cmpp20, implements::{cmpp_msg_id_str_to_u64, cmpp_msg_id_u64_to_str, create_cmpp_msg_id, sgip_msg_id_str_to_u64, sgip_msg_id_u64_to_str}, names::{DEST_ID, DEST_IDS, LONG_SMS_NOW_NUMBER, LONG_SMS_TOTAL, MSG_CONTENT, MSG_ID, MSG_IDS, SRC_ID}}};
 use tokio::time;
 use crate::protocol::Protocol::SMGP;
 use crate::protocol::smgp::Smgp30;
@@ -119,4 +119,993 @@ fn test_smgp_long123(){
 	};
 	
 println!("{}",d);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_smpp_submit() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{MSG_TYPE_STR, SEQ_IDS};
+
+	let mut smpp: Protocol = "SMPP".into();
+	let mut json = json::object! {
+		msg_type: "Submit",
+		src_id: "Test",
+		dest_ids: ["447700900123"],
+		msg_content: "hello smpp",
+		msg_fmt: 8,
+	};
+
+	let mut buf = smpp.encode_message(&mut json).unwrap();
+	let msg = smpp.decode(&mut buf).unwrap().unwrap();
+
+	assert_eq!(msg[MSG_TYPE_STR], "Submit");
+	assert_eq!(msg[SRC_ID], "Test");
+	assert_eq!(msg[DEST_IDS][0], "447700900123");
+	assert_eq!(msg[MSG_CONTENT], "hello smpp");
+	assert_eq!(msg["seq_id"], json[SEQ_IDS][0]);
+}
+
+
+#[test]
+fn test_smpp_payload_tlvs() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{LONG_SMS_MODE, TLVS};
+
+	let mut smpp: Protocol = "SMPP".into();
+	let content = "长短信测试".repeat(20);
+	let mut json = json::object! {
+		msg_type: "Submit",
+		src_id: "10690000",
+		dest_ids: ["8613800138000"],
+		msg_content: content.as_str(),
+		msg_fmt: 8,
+	};
+	json[LONG_SMS_MODE] = "payload".into();
+	json[TLVS]["1501"] = "0A0B".into();
+
+	let mut buf = smpp.encode_message(&mut json).unwrap();
+	let msg = smpp.decode(&mut buf).unwrap().unwrap();
+
+	assert!(buf.is_empty());
+	assert_eq!(msg[MSG_CONTENT], content.as_str());
+	assert_eq!(msg[TLVS]["1501"], "0A0B");
+}
+
+
+#[test]
+fn test_cmpp_query_resp() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{MSG_TYPE_STR, QUERY_CODE, QUERY_TIME, QUERY_TYPE, MT_TL_MSG, MT_WT, MO_FL};
+
+	let mut cmpp: Protocol = "CMPP".into();
+	let mut query = json::object! {
+		msg_type: "Query",
+		query_time: "20210721",
+		query_type: 1,
+		query_code: "HELP",
+	};
+
+	let mut buf = cmpp.encode_message(&mut query).unwrap();
+	let mut msg = cmpp.decode(&mut buf).unwrap().unwrap();
+
+	assert_eq!(msg[MSG_TYPE_STR], "Query");
+	assert_eq!(msg[QUERY_TIME], "20210721");
+	assert_eq!(msg[QUERY_TYPE], 1);
+	assert_eq!(msg[QUERY_CODE], "HELP");
+
+	msg[MSG_TYPE_STR] = "QueryResp".into();
+	msg[MT_TL_MSG] = 10.into();
+	msg[MT_WT] = 3.into();
+	msg[MO_FL] = 1.into();
+
+	let mut buf = cmpp.encode_message(&mut msg).unwrap();
+	let resp = cmpp.decode(&mut buf).unwrap().unwrap();
+
+	assert_eq!(resp[MSG_TYPE_STR], "QueryResp");
+	assert_eq!(resp["seq_id"], query["seq_id"]);
+	assert_eq!(resp[QUERY_CODE], "HELP");
+	assert_eq!(resp[MT_TL_MSG], 10);
+	assert_eq!(resp[MT_WT], 3);
+	assert_eq!(resp[MO_FL], 1);
+}
+
+
+#[test]
+fn test_cmpp_cancel() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::{Protocol, SmsStatus};
+	use crate::protocol::names::{MSG_TYPE_STR, PASSAGE_MSG_ID, RESULT};
+
+	let msg_id = cmpp_msg_id_u64_to_str(create_cmpp_msg_id(1));
+
+	for version in [0x30u32, 0x20].iter() {
+		let mut cmpp = Protocol::get_protocol("CMPP", *version);
+		let mut json = json::object! {
+			msg_type: "Cancel",
+		};
+		json[PASSAGE_MSG_ID] = msg_id.as_str().into();
+
+		let mut buf = cmpp.encode_message(&mut json).unwrap();
+		let mut msg = cmpp.decode(&mut buf).unwrap().unwrap();
+
+		assert_eq!(msg[MSG_TYPE_STR], "Cancel");
+		assert_eq!(msg[MSG_ID], msg_id.as_str());
+
+		//删除请求由实体按删除结果回复.不自动回复
+		assert!(cmpp.encode_receipt(SmsStatus::Success, &mut msg).is_none());
+
+		for result in [0u32, 1].iter() {
+			let mut reply = json::object! {
+				msg_type: "CancelResp",
+				seq_id: msg["seq_id"].clone(),
+				result: *result,
+			};
+			let mut buf = cmpp.encode_message(&mut reply).unwrap();
+			let resp = cmpp.decode(&mut buf).unwrap().unwrap();
+
+			assert_eq!(resp[MSG_TYPE_STR], "CancelResp");
+			assert_eq!(resp["seq_id"], json["seq_id"]);
+			assert_eq!(resp[RESULT], *result);
+		}
+	}
+}
+
+
+#[test]
+fn test_cmpp20_report() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{MSG_TYPE_STR, PASSAGE_MSG_ID, STATE};
+
+	let cmpp: Protocol = "CMPP".into();
+	assert!(cmpp.has(0x20) && cmpp.has(0x21) && cmpp.has(0x30));
+	assert!(!cmpp.has(0x13) && !cmpp.has(0x40));
+
+	let mut cmpp = cmpp.match_version(0x21);
+	assert!(matches!(cmpp, Protocol::CMPP20(_)));
+
+	let msg_id = cmpp_msg_id_u64_to_str(create_cmpp_msg_id(1));
+	let mut json = json::object! {
+		msg_type: "Report",
+		serviceId: "HELP",
+		state: "DELIVRD",
+		submit_time: "2107211906",
+		done_time: "2107211906",
+		src_id: "13800138000",
+		dest_id: "10690000",
+	};
+	json[MSG_ID] = msg_id.as_str().into();
+
+	let mut buf = cmpp.encode_message(&mut json).unwrap();
+	let msg = cmpp.decode(&mut buf).unwrap().unwrap();
+
+	assert_eq!(msg[MSG_TYPE_STR], "Report");
+	assert_eq!(msg[PASSAGE_MSG_ID], msg_id.as_str());
+	assert_eq!(msg[STATE], "DELIVRD");
+	assert_eq!(msg[SRC_ID], "13800138000");
+}
+
+
+#[test]
+fn test_cngp_submit() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{MSG_TYPE_STR, SEQ_IDS, SERVICE_ID, SP_ID};
+
+	let mut cngp: Protocol = "CNGP".into();
+	let content = "网通长短信测试".repeat(12);
+	let mut json = json::object! {
+		msg_type: "Submit",
+		spId: "101094",
+		serviceId: "HELP",
+		src_id: "10690000",
+		dest_ids: ["13800138000", "13900139000"],
+		msg_content: content.as_str(),
+		msg_ids: ["0720102545000693402291"],
+		msg_fmt: 8,
+	};
+
+	let mut buf = cngp.encode_message(&mut json).unwrap();
+	assert_eq!(json[SEQ_IDS].len(), 2);
+
+	let mut received = String::new();
+	while let Some(msg) = cngp.decode(&mut buf).unwrap() {
+		assert_eq!(msg[MSG_TYPE_STR], "Submit");
+		assert_eq!(msg[SP_ID], "101094");
+		assert_eq!(msg[SERVICE_ID], "HELP");
+		assert_eq!(msg[DEST_IDS][1], "13900139000");
+		received.push_str(msg[MSG_CONTENT].as_str().unwrap());
+	}
+
+	assert_eq!(received, content);
+}
+
+
+#[test]
+fn test_gsm7() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::{gsm7, Protocol};
+	use crate::protocol::implements::{Alphabet, encode_msg_content, decode_msg_content_bytes, get_msg_content_limit, split_msg_content};
+	use crate::protocol::names::{LONG_SMS_TOTAL, MSG_FMT};
+
+	let content = "Hello @ 5€ [ok]_";
+	let code = gsm7::encode(content).unwrap();
+	assert_eq!(code[6], 0x00);
+	assert_eq!(code.len(), gsm7::septet_len(content));
+	assert_eq!(gsm7::decode(&code).unwrap(), content);
+	assert!(gsm7::encode("中文").is_err());
+
+	let septets = gsm7::encode("hellohello").unwrap();
+	let packed = gsm7::pack(&septets, 0);
+	assert_eq!(packed, vec![0xE8, 0x32, 0x9B, 0xFD, 0x46, 0x97, 0xD9, 0xEC, 0x37]);
+	assert_eq!(gsm7::septet_count(&packed, 0), septets.len());
+	assert_eq!(gsm7::unpack(&packed, septets.len(), 0), septets);
+	assert_eq!(gsm7::unpack(&gsm7::pack(&septets, 1), septets.len(), 1), septets);
+	//7个septet压缩以后最后剩下7个填充位
+	assert_eq!(gsm7::septet_count(&gsm7::pack(&septets[..7], 0), 0), 7);
+
+	//CMPP等的msg_fmt 0为ASCII.不做GSM的转换
+	let code = encode_msg_content(0, "a@$_[]{}", Alphabet::Ascii).unwrap();
+	assert_eq!(code, b"a@$_[]{}".to_vec());
+	assert!(encode_msg_content(0, "é", Alphabet::Ascii).is_err());
+	assert_eq!(decode_msg_content_bytes(0, &[0x61, 0xE9], Alphabet::Ascii).unwrap(), "aé");
+	assert_eq!(get_msg_content_limit(0, Alphabet::Ascii), (140, 134));
+	assert_eq!(split_msg_content(0, &"a".repeat(140).into_bytes(), Alphabet::Ascii).len(), 1);
+	assert_eq!(split_msg_content(0, &"a".repeat(141).into_bytes(), Alphabet::Ascii)[0].len(), 134);
+
+	//GSM 7bit每个septet一个字节(不压缩).按septet个数拆分,扩展字符不会被拆开
+	let code = encode_msg_content(0, "a@€", Alphabet::Gsm7).unwrap();
+	assert_eq!(code, vec![0x61, 0x00, 0x1B, 0x65]);
+	assert_eq!(decode_msg_content_bytes(0, &code, Alphabet::Gsm7).unwrap(), "a@€");
+	assert_eq!(get_msg_content_limit(0, Alphabet::Gsm7), (160, 153));
+	let code = encode_msg_content(0, &format!("{}€{}", "a".repeat(152), "b".repeat(10)), Alphabet::Gsm7).unwrap();
+	let parts = split_msg_content(0, &code, Alphabet::Gsm7);
+	assert_eq!(parts.len(), 2);
+	assert_eq!(parts[0].len(), 152);
+	assert_eq!(&parts[1][..2], &[0x1B, 0x65]);
+
+	//SMPP的data_coding 0为GSM 7bit.160个字符一条,压缩以后140个字节
+	let mut smpp: Protocol = "SMPP".into();
+	let content = format!("{}é", "a".repeat(159));
+	let mut json = json::object! {
+		msg_type: "Submit",
+		src_id: "Test",
+		dest_ids: ["447700900123"],
+		msg_content: content.as_str(),
+		msg_fmt: 0,
+	};
+	let mut buf = smpp.encode_message(&mut json).unwrap();
+	assert_eq!(buf.len(), 16 + 33 + 140);
+	let msg = smpp.decode(&mut buf).unwrap().unwrap();
+	assert_eq!(msg[MSG_FMT], 0);
+	assert_eq!(msg[MSG_CONTENT], content.as_str());
+
+	//超过的按153拆分.扩展字符不会被拆开
+	let content = format!("{}€{}", "a".repeat(152), "b".repeat(10));
+	json[MSG_CONTENT] = content.as_str().into();
+	let mut buf = smpp.encode_message(&mut json).unwrap();
+	let first = smpp.decode(&mut buf).unwrap().unwrap();
+	let second = smpp.decode(&mut buf).unwrap().unwrap();
+	assert_eq!(first[LONG_SMS_TOTAL], 2);
+	assert_eq!(first[MSG_CONTENT], "a".repeat(152));
+	assert_eq!(format!("{}{}", first[MSG_CONTENT], second[MSG_CONTENT]), content);
+
+	//不能用GSM 7bit编码的内容使用UCS2
+	json[MSG_CONTENT] = "a`b".into();
+	let mut buf = smpp.encode_message(&mut json).unwrap();
+	assert_eq!(smpp.decode(&mut buf).unwrap().unwrap()[MSG_FMT], 8);
+
+	//UCS2 70个字符一条.超过的按67拆分
+	assert_eq!(split_msg_content(8, &encode_msg_content(8, &"测".repeat(70), Alphabet::Ascii).unwrap(), Alphabet::Ascii).len(), 1);
+	let code = encode_msg_content(8, &"测".repeat(71), Alphabet::Ascii).unwrap();
+	let parts = split_msg_content(8, &code, Alphabet::Ascii);
+	assert_eq!(parts.len(), 2);
+	assert_eq!(parts[0].len(), 134);
+}
+
+
+#[test]
+fn test_select_msg_fmt() {
+	use crate::protocol::implements::{select_msg_fmt, DEFAULT_MSG_FMTS};
+
+	assert_eq!(select_msg_fmt("hello", None, &DEFAULT_MSG_FMTS), Some(0));
+	assert_eq!(select_msg_fmt("你好", None, &DEFAULT_MSG_FMTS), Some(15));
+	assert_eq!(select_msg_fmt("你好😀", None, &DEFAULT_MSG_FMTS), Some(8));
+	//指定的字符集可用时优先使用
+	assert_eq!(select_msg_fmt("hello", Some(8), &DEFAULT_MSG_FMTS), Some(8));
+	assert_eq!(select_msg_fmt("你好", Some(0), &DEFAULT_MSG_FMTS), Some(15));
+	assert_eq!(select_msg_fmt("你好😀", None, &[0, 15]), None);
+}
+
+
+#[test]
+fn test_cmpp_binary_submit() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{MSG_FMT, MSG_HEX, UDH_HEX, TP_UDHI};
+
+	//WAP Push.端口寻址的UDH
+	let mut cmpp: Protocol = "CMPP".into();
+	let mut json = json::object! {
+		msg_type: "Submit",
+		spId: "101094",
+		serviceId: "HELP",
+		src_id: "10690000",
+		dest_ids: ["13800138000"],
+		msg_fmt: 4,
+		udh_hex: "0605040B8423F0",
+		msg_hex: "DC0601AE02056A0045C60C033132372E302E302E310001",
+		msg_ids: ["0720102545000693402291"],
+	};
+
+	let mut buf = cmpp.encode_message(&mut json).unwrap();
+	//TP_pId和TP_udhi在消息体的第57和58个字节
+	assert_eq!(buf[12 + 56], 0);
+	assert_eq!(buf[12 + 57], 1);
+	let msg = cmpp.decode(&mut buf).unwrap().unwrap();
+
+	assert_eq!(msg[MSG_FMT], 4);
+	assert_eq!(msg[TP_UDHI], 1);
+	assert_eq!(msg[UDH_HEX], "0605040B8423F0");
+	assert_eq!(msg[MSG_HEX], "DC0601AE02056A0045C60C033132372E302E302E310001");
+
+	json[UDH_HEX] = "05".into();
+	assert!(cmpp.encode_message(&mut json).is_err());
+}
+
+
+#[test]
+fn test_long_sms_char_boundary() {
+	use tokio_util::codec::Decoder;
+	use crate::entity::LongSmsCache;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{LONG_SMS_TOTAL, MSG_FMT};
+
+	//加上前缀让emoji的代理对和GBK的双字节字符正好落在按134字节拆分的位置
+	for (msg_fmt, content) in [(8, format!("ab{}", "😀".repeat(50))), (15, format!("a{}", "网关".repeat(80)))] {
+		let mut cmpp: Protocol = "CMPP".into();
+		let mut json = json::object! {
+			msg_type: "Submit",
+			spId: "101094",
+			serviceId: "HELP",
+			src_id: "10690000",
+			dest_ids: ["13800138000"],
+			msg_content: content.as_str(),
+			msg_ids: ["0720102545000693402291"],
+		};
+		json[MSG_FMT] = msg_fmt.into();
+
+		let mut buf = cmpp.encode_message(&mut json).unwrap();
+		let mut cache = LongSmsCache::new(60);
+		let mut result = None;
+		while let Some(msg) = cmpp.decode(&mut buf).unwrap() {
+			let total = msg[LONG_SMS_TOTAL].as_u8().unwrap();
+			assert!(total > 1);
+			result = cache.add(msg, total);
+		}
+
+		assert_eq!(result.unwrap()[MSG_CONTENT], content.as_str());
+	}
+}
+
+#[test]
+fn test_parse_udh() {
+	use bytes::BytesMut;
+	use crate::protocol::implements::{Alphabet, decode_msg_content, parse_udh, UdhIE};
+	use crate::protocol::names::{DEST_PORT, LONG_SMS_REF, LONG_SMS_TOTAL, SRC_PORT, TP_UDHI, UDH_HEX};
+
+	//16位参考号的长短信加上16位的端口寻址
+	let udh = [10, 0x08, 4, 0x12, 0x34, 3, 2, 0x05, 4, 0x0B, 0x84, 0x23, 0xF0];
+	assert!(parse_udh(&udh).is_err());
+	let udh = [12, 0x08, 4, 0x12, 0x34, 3, 2, 0x05, 4, 0x0B, 0x84, 0x23, 0xF0];
+	assert_eq!(parse_udh(&udh).unwrap(), vec![
+		UdhIE::Concat { reference: 0x1234, total: 3, number: 2 },
+		UdhIE::Port { dest_port: 0x0B84, src_port: 0x23F0 },
+	]);
+
+	let mut buf = BytesMut::from(&udh[..]);
+	buf.extend_from_slice(b"test");
+	let mut json = json::object! {};
+	decode_msg_content(&mut buf, 0, 17, &mut json, true, Alphabet::Ascii).unwrap();
+	assert_eq!(json[LONG_SMS_REF], 0x1234);
+	assert_eq!(json[LONG_SMS_TOTAL], 3);
+	assert_eq!(json[LONG_SMS_NOW_NUMBER], 2);
+	assert_eq!(json[DEST_PORT], 0x0B84);
+	assert_eq!(json[SRC_PORT], 0x23F0);
+	assert_eq!(json[TP_UDHI], 1);
+	assert!(json[UDH_HEX].is_null());
+	assert_eq!(json[MSG_CONTENT], "test");
+
+	//8位参考号前面带有其他的信息单元
+	let mut buf = BytesMut::from(&[8, 0x24, 1, 1, 0x00, 3, 0xAB, 2, 1, b'o', b'k'][..]);
+	let mut json = json::object! {};
+	decode_msg_content(&mut buf, 0, 11, &mut json, true, Alphabet::Ascii).unwrap();
+	assert_eq!(json[LONG_SMS_REF], 0xAB);
+	assert_eq!(json[LONG_SMS_TOTAL], 2);
+	assert_eq!(json[LONG_SMS_NOW_NUMBER], 1);
+	assert_eq!(json[UDH_HEX], "082401010003AB0201");
+	assert_eq!(json[MSG_CONTENT], "ok");
+}
+
+#[test]
+fn test_long_sms_cache() {
+	use crate::entity::LongSmsCache;
+	use crate::protocol::names::{LONG_SMS_MISSING, LONG_SMS_PARTIAL, LONG_SMS_REF, LONG_SMS_TOTAL};
+
+	let part = |reference: u16, number: u8, content: &str| json::object! {
+		msg_type: "Deliver",
+		src_id: "13800138000",
+		dest_id: "10690000",
+		msg_id: format!("{}-{}", reference, number),
+		msg_content: content,
+		l_s_ref: reference,
+		l_s_total: 2,
+		l_s_n_number: number,
+	};
+
+	//同一对号码之间交叉收到的两条长短信按参考号分别合并
+	let mut cache = LongSmsCache::new(1);
+	assert!(cache.add(part(1, 1, "a1"), 2).is_none());
+	assert!(cache.add(part(2, 2, "b2"), 2).is_none());
+	assert_eq!(cache.add(part(2, 1, "b1"), 2).unwrap()[MSG_CONTENT], "b1b2");
+	let json = cache.add(part(1, 2, "a2"), 2).unwrap();
+	assert_eq!(json[MSG_CONTENT], "a1a2");
+	assert_eq!(json[MSG_IDS], json::array!["1-1", "1-2"]);
+	assert!(json[LONG_SMS_REF].is_null());
+	assert!(cache.is_empty());
+
+	//超时未收全的按收到的部分发出
+	assert!(cache.add(part(3, 2, "c2"), 2).is_none());
+	assert!(cache.take_expired().is_empty());
+	std::thread::sleep(std::time::Duration::from_secs(2));
+	let expired = cache.take_expired();
+	assert_eq!(expired.len(), 1);
+	assert_eq!(expired[0][MSG_CONTENT], "c2");
+	assert_eq!(expired[0][LONG_SMS_PARTIAL], true);
+	assert_eq!(expired[0][LONG_SMS_TOTAL], 2);
+	assert_eq!(expired[0][LONG_SMS_MISSING], json::array![1]);
+	assert_eq!(cache.len(), 0);
+}
+
+#[test]
+fn test_decode_truncated() {
+	use bytes::BufMut;
+	use tokio_util::codec::Decoder;
+	use crate::protocol::{Protocol, SmsStatus};
+	use crate::protocol::names::DECODE_ERROR;
+
+	for name in ["CMPP", "SMGP", "SGIP", "SMPP", "CNGP"] {
+		let mut protocol: Protocol = name.into();
+		for msg_type in ["Submit", "Deliver"] {
+			let mut json = json::object! {
+				msg_type: msg_type,
+				spId: "101094",
+				serviceId: "HELP",
+				src_id: "10690000",
+				dest_id: "13800138000",
+				dest_ids: ["13800138000"],
+				msg_content: "长短信".repeat(30),
+				msg_ids: ["0720102545000693402291"],
+				msg_id: "0720102545000693402291",
+				r_t: "20210719191731",
+				nodeId: 3020000001u32,
+			};
+			let buf = protocol.encode_message(&mut json).unwrap();
+			let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+
+			//把第一条消息截断成各种长度.不能panic.截掉短信内容的时候返回带有decode_error的消息
+			for cut in 4..len {
+				let mut truncated = bytes::BytesMut::new();
+				truncated.put_u32(cut as u32);
+				truncated.extend_from_slice(&buf[4..cut]);
+
+				//不够消息头长度的在分帧时返回错误
+				if let Ok(Some(mut msg)) = protocol.decode(&mut truncated) {
+					if cut == len / 2 {
+						assert!(!msg[DECODE_ERROR].is_null(), "{} {} cut:{} msg:{}", name, msg_type, cut, msg);
+						assert!(protocol.encode_receipt(SmsStatus::MessageError, &mut msg).is_some());
+					} else {
+						protocol.encode_receipt(SmsStatus::MessageError, &mut msg);
+					}
+				}
+			}
+		}
+	}
+}
+
+#[test]
+fn test_frame_check() {
+	use bytes::BufMut;
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::implements::FrameError;
+
+	for (name, head_len, max_len, active_test) in [("CMPP", 12, 4096, 0x08u32), ("SMGP", 12, 4096, 0x04), ("SGIP", 20, 4096, 0x09), ("SMPP", 16, 72 * 1024, 0x15), ("CNGP", 16, 4096, 0x04)] {
+		let mut protocol: Protocol = name.into();
+
+		//正常的心跳
+		let mut buf = bytes::BytesMut::new();
+		buf.put_u32(head_len as u32);
+		buf.put_u32(active_test);
+		buf.extend_from_slice(&vec![0u8; head_len - 8]);
+		assert!(protocol.decode(&mut buf).unwrap().is_some(), "{}", name);
+
+		//超过最大长度的.不等后面的数据直接返回错误
+		let count = FrameError::TooLong.count();
+		let mut buf = bytes::BytesMut::new();
+		buf.put_u32(max_len as u32 + 1);
+		buf.put_u32(active_test);
+		assert!(protocol.decode(&mut buf).is_err(), "{}", name);
+		assert!(FrameError::TooLong.count() > count);
+
+		//不够消息头长度的
+		let mut protocol: Protocol = name.into();
+		let count = FrameError::TooShort.count();
+		let mut buf = bytes::BytesMut::new();
+		buf.put_u32(head_len as u32 - 1);
+		buf.put_u32(active_test);
+		buf.extend_from_slice(&vec![0u8; head_len - 9]);
+		assert!(protocol.decode(&mut buf).is_err(), "{}", name);
+		assert!(FrameError::TooShort.count() > count);
+
+		//不认识的命令.smpp回复generic_nack,不断开连接
+		if name == "SMPP" {
+			continue;
+		}
+		let mut protocol: Protocol = name.into();
+		let count = FrameError::UnknownCommand.count();
+		let mut buf = bytes::BytesMut::new();
+		buf.put_u32(head_len as u32);
+		buf.put_u32(0x7FFFFFFF);
+		buf.extend_from_slice(&vec![0u8; head_len - 8]);
+		assert!(protocol.decode(&mut buf).is_err(), "{}", name);
+		assert!(FrameError::UnknownCommand.count() > count);
+	}
+}
+
+#[test]
+fn test_smpp_generic_nack() {
+	use bytes::{Buf, BufMut};
+	use tokio_util::codec::Decoder;
+	use crate::protocol::{Protocol, SmsStatus};
+
+	let mut protocol: Protocol = "SMPP".into();
+	let mut buf = bytes::BytesMut::new();
+	buf.put_u32(20);
+	buf.put_u32(0x7FFFFFFF);
+	buf.put_u32(0);
+	buf.put_u32(7);
+	buf.put_u32(0);
+	let mut json = protocol.decode(&mut buf).unwrap().unwrap();
+
+	let mut nack = protocol.encode_receipt(SmsStatus::Success, &mut json).unwrap();
+	assert_eq!(nack.get_u32(), 16);
+	assert_eq!(nack.get_u32(), 0x80000000);
+	assert_eq!(nack.get_u32(), 0x00000003);
+	assert_eq!(nack.get_u32(), 7);
+}
+
+#[test]
+fn test_smgp_tlvs() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{DEST_TERM_PSEUDO, LINK_ID, M_SERVICE_ID, MSG_SRC, TLVS};
+
+	let mut protocol: Protocol = "SMGP".into();
+	for msg_type in ["Submit", "Deliver"] {
+		let mut json = json::object! {
+			msg_type: msg_type,
+			serviceId: "HELP",
+			src_id: "10690000",
+			dest_id: "13800138000",
+			dest_ids: ["13800138000"],
+			msg_content: "长短信".repeat(30),
+			msg_ids: ["0720102545000693402291", "0720102545000693402292"],
+			msg_id: "0720102545000693402291",
+			r_t: "20210719191731",
+			link_id: "LINK0001",
+			msg_src: "SP0001",
+			m_service_id: "MHELP",
+			dest_pseudo: "PSEUDO01",
+			tlvs: {"00FF": "0102", "000B": "03"},
+		};
+		let mut buf = protocol.encode_message(&mut json).unwrap();
+
+		//长短信的每一条都带有相同的tlv.未识别的原样带回
+		let mut count = 0;
+		while let Some(msg) = protocol.decode(&mut buf).unwrap() {
+			count += 1;
+			assert_eq!(msg[LINK_ID], "LINK0001", "{}", msg);
+			assert_eq!(msg[MSG_SRC], "SP0001");
+			assert_eq!(msg[M_SERVICE_ID], "MHELP");
+			assert_eq!(msg[DEST_TERM_PSEUDO], "PSEUDO01");
+			assert_eq!(msg[TLVS]["00FF"], "0102");
+			assert_eq!(msg[TLVS]["000B"], "03");
+			assert_eq!(msg["l_s_total"], 2);
+		}
+		assert_eq!(count, 2);
+	}
+
+	//消息最后长度为0的tlv
+	let mut json = json::object! {
+		msg_type: "Submit",
+		serviceId: "HELP",
+		src_id: "10690000",
+		dest_ids: ["13800138000"],
+		msg_content: "短信",
+		msg_ids: ["0720102545000693402291"],
+		tlvs: {"00FE": ""},
+	};
+	let mut buf = protocol.encode_message(&mut json).unwrap();
+	let msg = protocol.decode(&mut buf).unwrap().unwrap();
+	assert_eq!(msg[TLVS]["00FE"], "", "{}", msg);
+}
+
+#[test]
+fn test_cngp_tlvs() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{LONG_SMS_TOTAL, TLVS};
+
+	let mut protocol: Protocol = "CNGP".into();
+	for msg_type in ["Submit", "Deliver"] {
+		let mut json = json::object! {
+			msg_type: msg_type,
+			spId: "101094",
+			serviceId: "HELP",
+			src_id: "10690000",
+			dest_id: "13800138000",
+			dest_ids: ["13800138000"],
+			msg_content: "长短信".repeat(30),
+			msg_ids: ["0720102545000693402291", "0720102545000693402292"],
+			tlvs: {"00FF": "0102"},
+		};
+		let mut buf = protocol.encode_message(&mut json).unwrap();
+
+		//长短信带有TP_udhi.未识别的tlv原样带回
+		let mut count = 0;
+		while let Some(msg) = protocol.decode(&mut buf).unwrap() {
+			count += 1;
+			assert_eq!(msg[TLVS]["00FF"], "0102", "{}", msg);
+			assert!(msg[TLVS]["0002"].is_null());
+			assert_eq!(msg[LONG_SMS_TOTAL], 2);
+		}
+		assert_eq!(count, 2);
+	}
+
+	//对端发送TP_udhi为0时.内容像UDH开头也不按长短信处理
+	let mut json = json::object! {
+		msg_type: "Submit",
+		spId: "101094",
+		serviceId: "HELP",
+		src_id: "10690000",
+		dest_ids: ["13800138000"],
+		msg_fmt: 4,
+		msg_hex: "0500030102014142",
+		msg_ids: ["0720102545000693402291"],
+		tlvs: {"0002": "00", "00FE": ""},
+	};
+	let mut buf = protocol.encode_message(&mut json).unwrap();
+	let msg = protocol.decode(&mut buf).unwrap().unwrap();
+	assert!(msg[LONG_SMS_TOTAL].is_null(), "{}", msg);
+	//消息最后长度为0的tlv
+	assert_eq!(msg[TLVS]["00FE"], "");
+}
+
+#[test]
+fn test_submit_fee_fields() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{CHARGE_ID, FEE_CODE, FEE_TYPE, MSG_LEVEL, PK_NUMBER, PK_TOTAL};
+
+	for mut protocol in [Protocol::get_protocol("CMPP", 0x30), Protocol::get_protocol("CMPP", 0x20), Protocol::get_protocol("SMGP", 0x30), Protocol::get_protocol("SGIP", 0), Protocol::get_protocol("CNGP", 0x20)] {
+		let mut json = json::object! {
+			msg_type: "Submit",
+			spId: "101094",
+			serviceId: "HELP",
+			src_id: "10690000",
+			dest_ids: ["13800138000"],
+			msg_content: "计费测试",
+			msg_ids: ["0720102545000693402291"],
+			nodeId: 3020000001u32,
+			fee_type: "02",
+			fee_code: "000010",
+			charge_id: "13900139000",
+			fee_user_type: 3,
+			msg_level: 3,
+			pk_total: 1,
+			pk_number: 1,
+			mo_relate_mt: 2,
+		};
+		let mut buf = protocol.encode_message(&mut json).unwrap();
+		let msg = protocol.decode(&mut buf).unwrap().unwrap();
+
+		assert_eq!(msg[FEE_CODE], "000010", "{:?} {}", protocol, msg);
+		assert_eq!(msg[CHARGE_ID], "13900139000", "{:?} {}", protocol, msg);
+		assert_eq!(msg[MSG_LEVEL], 3, "{:?} {}", protocol, msg);
+		if let Protocol::SGIP(_) = protocol {
+			assert_eq!(msg[FEE_TYPE], 2);
+			assert_eq!(msg["mo_relate_mt"], 2);
+		} else if let Protocol::CNGP(_) = protocol {
+			assert_eq!(msg[FEE_TYPE], "02");
+			assert_eq!(msg["fee_user_type"], 3);
+		} else {
+			assert_eq!(msg[FEE_TYPE], "02");
+			assert_eq!(msg[PK_TOTAL], 1);
+			assert_eq!(msg[PK_NUMBER], 1);
+		}
+	}
+}
+
+#[test]
+fn test_report_status() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::names::{MSG_ID, REPORT_PROTOCOL, REPORT_STATUS, STATE};
+	use crate::protocol::implements::{cmpp_msg_id_u64_to_str, create_cmpp_msg_id};
+	use crate::protocol::report_status::{get_report_state, get_report_status, load_report_status_table};
+
+	let mut cmpp: Protocol = "CMPP".into();
+	let mut smpp: Protocol = "SMPP".into();
+
+	let mut json = json::object! {
+		msg_type: "Report",
+		serviceId: "HELP",
+		state: "MK:0012",
+		submit_time: "2107211906",
+		done_time: "2107211906",
+		src_id: "13800138000",
+		dest_id: "10690000",
+	};
+	json[MSG_ID] = cmpp_msg_id_u64_to_str(create_cmpp_msg_id(1)).into();
+
+	let mut buf = cmpp.encode_message(&mut json).unwrap();
+	let mut msg = cmpp.decode(&mut buf).unwrap().unwrap();
+	assert_eq!(msg[STATE], "MK:0012");
+	assert_eq!(msg[REPORT_STATUS], "undeliverable");
+	assert_eq!(msg[REPORT_PROTOCOL], "CMPP");
+
+	//转发到其他协议时按统一状态转换
+	let mut buf = smpp.encode_message(&mut msg).unwrap();
+	let msg = smpp.decode(&mut buf).unwrap().unwrap();
+	assert_eq!(msg[STATE], "UNDELIV");
+	assert_eq!(msg[REPORT_STATUS], "undeliverable");
+	assert_eq!(msg[REPORT_PROTOCOL], "SMPP");
+
+	//配置文件里面的协议替换默认的对照表
+	let file_name = std::env::temp_dir().join("report_status_test.json");
+	std::fs::write(&file_name, r#"{"CMPP": {"DELIVRD": "delivered", "XX:*": "expired"}}"#).unwrap();
+	let table = load_report_status_table(file_name.to_str().unwrap());
+	assert_eq!(get_report_status(&table, "CMPP", "XX:0001"), "expired");
+	assert_eq!(get_report_status(&table, "CMPP", "UNDELIV"), "unknown");
+	assert_eq!(get_report_status(&table, "SGIP", "DELIVRD"), "delivered");
+	assert_eq!(get_report_state(&table, "SGIP", "expired").unwrap(), "SGIPERR");
+	assert!(get_report_state(&table, "CMPP", "undeliverable").is_none());
+}
+
+#[test]
+fn test_result_status() {
+	use tokio_util::codec::Decoder;
+	use crate::protocol::Protocol;
+	use crate::protocol::smgp::Smgp30;
+	use crate::protocol::names::{RESULT, RESULT_STATUS, RETRYABLE, SPEED_LIMIT};
+
+	let mut cmpp: Protocol = "CMPP".into();
+
+	let mut submit_resp = |result: u32| {
+		let mut buf = BytesMut::with_capacity(24);
+		buf.put_u32(24);
+		buf.put_u32(0x80000004);
+		buf.put_u32(1);
+		buf.put_u64(create_cmpp_msg_id(1));
+		buf.put_u32(result);
+		cmpp.decode(&mut buf).unwrap().unwrap()
+	};
+
+	let msg = submit_resp(13);
+	assert_eq!(msg[RESULT], 13);
+	assert_eq!(msg[RESULT_STATUS], "DestIdError");
+	assert_eq!(msg[RETRYABLE], false);
+
+	let msg = submit_resp(8);
+	assert_eq!(msg[RESULT_STATUS], "TrafficRestrictions");
+	assert_eq!(msg[RETRYABLE], true);
+	assert_eq!(msg[SPEED_LIMIT], true);
+
+	let msg = submit_resp(200);
+	assert_eq!(msg[RESULT_STATUS], "UNKNOWN");
+	assert_eq!(msg[RETRYABLE], false);
+
+	let smgp = Smgp30::new();
+	assert_eq!(smgp.get_result_enum(47), (SmsStatus::DestIdError, false));
+	assert_eq!(smgp.get_result_enum(1), (SmsStatus::SystemBusy, true));
+	assert!(smgp.is_speed_limit(75));
+
+	let sgip = Sgip::new();
+	assert_eq!(sgip.get_status_id(&SmsStatus::DestIdError), 6);
+	assert!(sgip.is_speed_limit(88));
+	assert!(!sgip.is_speed_limit(6));
+}
+
+#[test]
+fn test_sms_schedule() {
+	use crate::entity::SmsSchedule;
+	use crate::entity::schedule::parse_at_time;
+	use crate::protocol::names::AT_TIME;
+	use chrono::TimeZone;
+
+	let now = Local::now().timestamp();
+	assert_eq!(parse_at_time("210721190600032+", now), Some(1626865560));
+	assert_eq!(parse_at_time("000000010000000R", now), Some(now + 3600));
+	assert_eq!(parse_at_time("20210721190600", now), Some(Local.with_ymd_and_hms(2021, 7, 21, 19, 6, 0).unwrap().timestamp()));
+	assert_eq!(parse_at_time("", now), None);
+	assert_eq!(parse_at_time("2021-07-21", now), None);
+
+	let dir = std::env::temp_dir().join(format!("schedule_test_{}", now));
+	let dir = dir.to_str().unwrap();
+
+	let mut schedule = SmsSchedule::load(dir, 1);
+	assert!(schedule.is_empty());
+
+	//没有at_time和已经过去的直接返回
+	assert!(schedule.add(json::object! {msg_type: "Submit", msg_ids: ["a"]}, now).is_some());
+	assert!(schedule.add(json::object! {msg_type: "Submit", at_time: "20200101000000", msg_ids: ["b"]}, now).is_some());
+
+	let at_time = (Local::now() + chrono::Duration::hours(1)).format("%Y%m%d%H%M%S").to_string();
+	assert!(schedule.add(json::object! {msg_type: "Submit", at_time: at_time.as_str(), msg_ids: ["c1", "c2"]}, now).is_none());
+	assert!(schedule.add(json::object! {msg_type: "Submit", at_time: "000000020000000R", msg_ids: ["d"]}, now).is_none());
+	assert_eq!(schedule.len(), 2);
+	get_runtime().block_on(schedule.save());
+
+	//重新启动时从文件恢复
+	let mut schedule = SmsSchedule::load(dir, 1);
+	assert_eq!(schedule.msg_ids(), vec!["c1", "c2", "d"]);
+	assert!(schedule.take_due(now).is_empty());
+
+	assert!(schedule.remove("c2").is_some());
+	assert!(schedule.remove("c1").is_none());
+
+	let due = schedule.take_due(now + 7200);
+	assert_eq!(due.len(), 1);
+	assert_eq!(due[0][AT_TIME], "");
+	get_runtime().block_on(schedule.save());
+	assert!(SmsSchedule::load(dir, 1).is_empty());
+
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_valid_time_expire() {
+	use crate::entity::schedule::{fill_expire_time, is_expired};
+	use crate::protocol::names::EXPIRE_TIME;
+
+	let now = Local::now().timestamp();
+
+	//相对时间按第一次计算的时间.以后不再改变
+	let mut msg = json::object! {msg_type: "Submit", valid_time: "000000000100000R"};
+	fill_expire_time(&mut msg, now);
+	assert_eq!(msg[EXPIRE_TIME], now + 60);
+	fill_expire_time(&mut msg, now + 1000);
+	assert_eq!(msg[EXPIRE_TIME], now + 60);
+	assert!(!is_expired(&msg, now + 60));
+	assert!(is_expired(&msg, now + 61));
+
+	let mut msg = json::object! {msg_type: "Submit", valid_time: "20200101000000"};
+	fill_expire_time(&mut msg, now);
+	assert!(is_expired(&msg, now));
+
+	//没有有效期的不会过期
+	let mut msg = json::object! {msg_type: "Submit", valid_time: ""};
+	fill_expire_time(&mut msg, now);
+	assert!(!is_expired(&msg, now + 86400 * 365));
+}
+
+#[test]
+fn test_channel_select() {
+	use crate::entity::ChannelSelect;
+
+	assert_eq!(ChannelSelect::from("leastQueued"), ChannelSelect::LeastQueued);
+	assert_eq!(ChannelSelect::from("leastInFlight"), ChannelSelect::LeastInFlight);
+	assert_eq!(ChannelSelect::from("roundRobin"), ChannelSelect::RoundRobin);
+	assert_eq!(ChannelSelect::from(""), ChannelSelect::RoundRobin);
+}
+
+#[test]
+fn test_window_seq_ids() {
+	use crate::entity::channel::window_seq_ids;
+
+	let submit = json::object! {msg_type: "Submit", seq_ids: [11, 12]};
+	assert_eq!(window_seq_ids(&submit), vec![11, 12]);
+
+	let query = json::object! {msg_type: "Query", seq_id: 13};
+	assert_eq!(window_seq_ids(&query), vec![13]);
+
+	let active_test = json::object! {msg_type: "ActiveTest", seq_id: 14};
+	assert!(window_seq_ids(&active_test).is_empty());
+}
+
+#[test]
+fn test_token_bucket() {
+	use crate::entity::TokenBucket;
+
+	//默认突发数量为速度的十分之一
+	let bucket = TokenBucket::new(100, 0);
+	assert!(bucket.try_take(10));
+	assert!(!bucket.has_tokens(1));
+	assert!(bucket.wait_time(1) <= std::time::Duration::from_millis(10));
+
+	//长短信条数大于突发数量时桶满就可以发送
+	let bucket = TokenBucket::new(10, 2);
+	assert!(bucket.try_take(5));
+	assert!(!bucket.try_take(1));
+
+	//速度为0时不能发送
+	let bucket = TokenBucket::new(0, 0);
+	assert!(!bucket.try_take(1));
+}
+
+#[test]
+fn test_adaptive_rate() {
+	use crate::entity::AdaptiveRate;
+
+	let mut rate = AdaptiveRate::new(100);
+	assert!(rate.on_speed_limit(1000));
+	assert_eq!(rate.rate(), 50);
+	//同一批回复只降一次
+	assert!(!rate.on_speed_limit(1001));
+	assert_eq!(rate.rate(), 50);
+
+	assert!(!rate.on_tick(1003));
+	assert!(rate.on_tick(1005));
+	assert_eq!(rate.rate(), 60);
+
+	//最低为配置速度的十分之一
+	for now in (1010..1100).step_by(5) {
+		rate.on_speed_limit(now);
+	}
+	assert_eq!(rate.rate(), 10);
+}
+
+#[test]
+fn test_retry_policy() {
+	use crate::entity::RetryPolicy;
+	use crate::entity::retry::{RETRY_DEADLINE_EXCEEDED, RETRY_EXHAUSTED};
+
+	let policy = RetryPolicy { max_retries: 2, max_interval: 100, deadline: 600 };
+	assert_eq!(policy.next_duration(30), 60);
+	assert_eq!(policy.next_duration(60), 100);
+
+	let mut msg = json::object! {first_send_time: 1000};
+	assert_eq!(policy.add_retry(&mut msg, 1100), None);
+	assert_eq!(policy.add_retry(&mut msg, 1200), None);
+	assert_eq!(policy.add_retry(&mut msg, 1300), Some(RETRY_EXHAUSTED));
+
+	let mut msg = json::object! {first_send_time: 1000};
+	assert_eq!(policy.add_retry(&mut msg, 1601), Some(RETRY_DEADLINE_EXCEEDED));
+}
+
+#[test]
+fn test_statistics_mt_users() {
+	use crate::entity::statistics::Statistics;
+	use crate::protocol::names::{MT_TL_MSG, MT_TL_USR, QUERY_TIME, QUERY_TYPE};
+
+	let mut statistics = Statistics::new();
+	for i in 0..10000 {
+		let dest_ids = json::array![format!("1380000{:04}", i)];
+		statistics.add_mt("a", 1, &dest_ids);
+		//同一个号码在其他业务类型里不重复计算
+		statistics.add_mt("b", 1, &dest_ids);
+	}
+
+	let mut json = json::object! {};
+	json[QUERY_TIME] = Local::now().format("%Y%m%d").to_string().into();
+	json[QUERY_TYPE] = 0.into();
+	statistics.fill_query_resp(&mut json);
+
+	assert_eq!(json[MT_TL_MSG], 20000);
+	let users = json[MT_TL_USR].as_u32().unwrap();
+	assert!(users > 9500 && users < 10500, "{}", users);
+}